@@ -14,7 +14,7 @@ pub enum BitPieceFlags {
     FirstMove = 1 << 4,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct BitPiece(u8);
 
 impl BitPiece {
@@ -93,9 +93,7 @@ impl TryFrom<u8> for BitPiece {
     type Error = PieceError;
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
-        if let Err(e) = Piece::try_from((value & 0b0000_1110) >> 1) {
-            return Err(e);
-        }
+        Piece::try_from((value & 0b0000_1110) >> 1)?;
         Ok(BitPiece(value))
     }
 }
@@ -1,6 +1,8 @@
 use crate::{
-    movegen::{Legal, RawMove},
-    BitPiece, BoardError, CastleMove, Color, MoveInfo, Piece, Square,
+    movegen::{AttackMap, Attacks, Legal, RawMove},
+    tablebase::TablebaseResult,
+    zobrist::ZOBRIST,
+    BitPiece, BoardError, CastleMove, Color, Move, MoveInfo, Piece, Square,
 };
 use regex::Regex;
 use std::{
@@ -34,6 +36,14 @@ pub struct Board {
     pub en_passant: Option<Square>,
     pub halfmove_clock: u8,
     pub fullmove_number: u16,
+    // occupied squares and the zobrist keys of all pieces and of the pawns,
+    // kept up to date by every change to the board
+    piece_count: u8,
+    piece_key: u64,
+    pawn_key: u64,
+    // pieces were changed through IndexMut, behind the back of set, so the
+    // count and keys are computed from the board until it is copied
+    stale: bool,
 
     pub history: Vec<MoveInfo>,
 }
@@ -48,6 +58,7 @@ impl Index<Square> for Board {
 
 impl IndexMut<Square> for Board {
     fn index_mut(&mut self, index: Square) -> &mut Self::Output {
+        self.stale = true;
         &mut self.board[usize::from(index)]
     }
 }
@@ -62,7 +73,7 @@ impl Default for Board {
         for i in 0..2 {
             let color = if i == 0 { Color::White } else { Color::Black };
             let row = if i == 0 { 0 } else { 7 };
-            board[0 + row * 8] = BitPiece::new(Piece::Rook, color, false);
+            board[row * 8] = BitPiece::new(Piece::Rook, color, false);
             board[7 + row * 8] = BitPiece::new(Piece::Rook, color, false);
             board[1 + row * 8] = BitPiece::new(Piece::Knight, color, false);
             board[6 + row * 8] = BitPiece::new(Piece::Knight, color, false);
@@ -71,7 +82,7 @@ impl Default for Board {
             board[3 + row * 8] = BitPiece::new(Piece::Queen, color, false);
             board[4 + row * 8] = BitPiece::new(Piece::King, color, false);
         }
-        Self {
+        let mut board = Self {
             board,
             turn: Color::White,
             status: BoardStatus::Ongoing,
@@ -79,10 +90,62 @@ impl Default for Board {
             en_passant: None,
             halfmove_clock: 0,
             fullmove_number: 1,
+            piece_count: 0,
+            piece_key: 0,
+            pawn_key: 0,
+            stale: false,
 
             history: Vec::new(),
+        };
+        board.init_pieces();
+        board
+    }
+}
+
+// the piece placement field of a fen, which must hold eight ranks of eight
+// squares, one king of each color and no more pieces than a game starts with
+fn parse_pieces(placement: &str) -> Result<BitBoard, BoardError> {
+    let invalid = |reason: &str| BoardError::InvalidFEN(format!("{}: {}", placement, reason));
+    let ranks = placement.split('/').collect::<Vec<&str>>();
+    if ranks.len() != 8 {
+        return Err(invalid("expected 8 ranks"));
+    }
+    let mut b: BitBoard = [BitPiece::new_blank(); 64];
+    for (i, row) in ranks.iter().enumerate() {
+        let rank = 7 - i;
+        let mut file = 0;
+        for c in row.chars() {
+            match c.to_digit(10) {
+                Some(empty @ 1..=8) => file += empty as usize,
+                _ => {
+                    let p =
+                        BitPiece::try_from(c).map_err(|e| BoardError::InvalidFEN(e.to_string()))?;
+                    if file < 8 {
+                        b[rank * 8 + file] = p;
+                    }
+                    file += 1;
+                }
+            }
+            if file > 8 {
+                return Err(invalid("a rank has more than 8 squares"));
+            }
+        }
+        if file != 8 {
+            return Err(invalid("a rank has less than 8 squares"));
         }
     }
+
+    for color in [Color::White, Color::Black] {
+        let pieces = b.iter().filter(|p| !p.is_blank() && p.get_color() == color);
+        let count = |piece: Piece| pieces.clone().filter(|p| p.get_piece() == piece).count();
+        if count(Piece::King) != 1 {
+            return Err(invalid("each side needs exactly one king"));
+        }
+        if count(Piece::Pawn) > 8 || pieces.clone().count() > 16 {
+            return Err(invalid("too many pieces"));
+        }
+    }
+    Ok(b)
 }
 
 impl Board {
@@ -90,24 +153,7 @@ impl Board {
         let coll = fen.split_whitespace().collect::<Vec<&str>>();
         match coll[..] {
             [board, turn, castling, en_passant, halfmove_clock, fullmove_number] => {
-                let mut b: BitBoard = [BitPiece::new_blank(); 64];
-                let mut rank = 7;
-                let mut file = 0;
-                for c in board.chars() {
-                    if c == '/' {
-                        rank -= 1;
-                        file = 0;
-                    } else if c.is_digit(10) {
-                        file += c.to_digit(10).unwrap();
-                    } else {
-                        let p = match BitPiece::try_from(c) {
-                            Ok(p) => p,
-                            Err(e) => return Err(BoardError::InvalidFEN(e.to_string())),
-                        };
-                        b[rank * 8 + file as usize] = p;
-                        file += 1;
-                    }
-                }
+                let b = parse_pieces(board)?;
 
                 let color = match turn {
                     "w" => Color::White,
@@ -145,7 +191,18 @@ impl Board {
                 let en_passant = match en_passant {
                     "-" => None,
                     _ => match Square::try_from(en_passant.to_owned()) {
-                        Ok(en_passant) => Some(en_passant),
+                        // the square a pawn of the other side just skipped
+                        Ok(en_passant)
+                            if en_passant.rank() == if color == Color::White { 6 } else { 3 } =>
+                        {
+                            Some(en_passant)
+                        }
+                        Ok(_) => {
+                            return Err(BoardError::InvalidFEN(format!(
+                                "{} is not a valid en passant square",
+                                en_passant
+                            )))
+                        }
                         Err(e) => return Err(BoardError::InvalidFEN(e.to_string())),
                     },
                 };
@@ -174,7 +231,7 @@ impl Board {
                     )));
                 }
 
-                Ok(Self {
+                let mut board = Self {
                     board: b,
                     turn: color,
                     status: BoardStatus::Ongoing,
@@ -182,10 +239,17 @@ impl Board {
                     en_passant,
                     halfmove_clock,
                     fullmove_number,
+                    piece_count: 0,
+                    piece_key: 0,
+                    pawn_key: 0,
+                    stale: false,
                     history: Vec::new(),
-                })
+                };
+                board.init_pieces();
+                board.update_status();
+                Ok(board)
             }
-            _ => return Err(BoardError::InvalidFEN(fen)),
+            _ => Err(BoardError::InvalidFEN(fen)),
         }
     }
 
@@ -195,90 +259,237 @@ impl Board {
         match mv {
             RawMove::Single(mut info) => {
                 if let Some(capture) = info.capture {
-                    self[capture.square] = BitPiece::new_blank();
+                    self.set(capture.square, BitPiece::new_blank());
                 }
-                self[info.from] = BitPiece::new_blank();
+                self.set(info.from, BitPiece::new_blank());
                 info.piece.set_moved();
-                self[info.to] = info.piece;
-                if let Some(en_passant_square) = info.en_passant_square {
-                    self.en_passant = Some(en_passant_square);
-                }
+                self.set(info.to, info.piece);
+                self.en_passant = info.en_passant_square;
             }
             RawMove::Castle(mut info1, mut info2) => {
-                self[info1.from] = BitPiece::new_blank();
+                self.set(info1.from, BitPiece::new_blank());
                 info1.piece.set_moved();
-                self[info1.to] = info1.piece;
-                self[info2.from] = BitPiece::new_blank();
+                self.set(info1.to, info1.piece);
+                self.set(info2.from, BitPiece::new_blank());
                 info2.piece.set_moved();
-                self[info2.to] = info2.piece;
+                self.set(info2.to, info2.piece);
             }
         }
     }
 
-    pub(crate) fn execute_move(&mut self, mv: &MoveInfo, rmv: &RawMove) {
-        match rmv {
-            RawMove::Single(mut info) => {
-                if let Some(capture) = info.capture {
-                    self[capture.square] = BitPiece::new_blank();
-                }
-                self[info.from] = BitPiece::new_blank();
-                if let Some(promotion) = mv.promotion {
-                    self[info.to] = promotion;
-                } else {
-                    info.piece.set_moved();
-                    self[info.to] = info.piece;
-                }
-                if let Some(en_passant_square) = info.en_passant_square {
-                    self.en_passant = Some(en_passant_square);
-                }
-            }
-            RawMove::Castle(mut info1, mut info2) => {
-                self[info1.from] = BitPiece::new_blank();
-                info1.piece.set_moved();
-                self[info1.to] = info1.piece;
-                self[info2.from] = BitPiece::new_blank();
-                info2.piece.set_moved();
-                self[info2.to] = info2.piece;
+    // the only way pieces are put on the board or taken off it
+    fn set(&mut self, square: Square, piece: BitPiece) {
+        let old = std::mem::replace(&mut self.board[usize::from(square)], piece);
+        if self.stale {
+            return;
+        }
+        self.piece_count = self.piece_count + !piece.is_blank() as u8 - !old.is_blank() as u8;
+        for p in [old, piece].into_iter().filter(|p| !p.is_blank()) {
+            let key = ZOBRIST.pieces[p.get_color() as usize][p.get_piece() as usize - 1]
+                [usize::from(square)];
+            self.piece_key ^= key;
+            if p.is_pawn() {
+                self.pawn_key ^= key;
             }
         }
     }
 
-    pub(crate) fn is_being_checked(&self, color: Color, raw_moves: &Vec<RawMove>) -> bool {
-        // current turn meaning that opponent is not checked or checkmated
-        // or previous status of the board is not check or checkmated
-        // in other way, the fact that the board can advance to current turn
-        // meaning that current turn is not checking or checkmating opponent
-        // we can use opponent's raw moves instead of legal moves to determine
-        // if current turn is being checked
-        // if we chose legal moves, this function calls Legal::gen_legal_moves
-        // Legal::gen_legal_moves call is_being_checked => indefinite recursion
+    // count and hash the pieces of a board built square by square
+    fn init_pieces(&mut self) {
+        let board = std::mem::replace(&mut self.board, [BitPiece::new_blank(); 64]);
+        (self.piece_count, self.piece_key, self.pawn_key) = (0, 0, 0);
+        self.stale = false;
+        for (i, p) in board.into_iter().enumerate() {
+            self.set(Square::try_from(i as u8).unwrap(), p);
+        }
+    }
 
-        // we check if destination of a move can result in current
-        // color king square => capture king
-        // being said, castle move cannot result in king capture
-        let dest = raw_moves.iter().filter_map(|&mov| match mov {
-            RawMove::Single(info) => {
-                if info.piece.get_color() != color {
-                    Some(info.to)
-                } else {
-                    None
-                }
-            }
-            _ => None,
-        });
-        for mov in dest {
-            let p = self[mov];
-            if !p.is_blank() && p.is_king() && p.get_color() == color {
-                return true;
+    // number of pieces on the board, kings and pawns included
+    pub fn piece_count(&self) -> usize {
+        self.keys().0 as usize
+    }
+
+    // the piece count, the key of all pieces and the key of the pawns
+    fn keys(&self) -> (u8, u64, u64) {
+        if !self.stale {
+            return (self.piece_count, self.piece_key, self.pawn_key);
+        }
+        let board = self.clone_position();
+        (board.piece_count, board.piece_key, board.pawn_key)
+    }
+
+    // copy of the position without the move history
+    // cheap to make, used when trying moves on a temporary board
+    pub(crate) fn clone_position(&self) -> Board {
+        let mut board = Board {
+            board: self.board,
+            turn: self.turn,
+            status: self.status,
+            castling: self.castling,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            piece_count: self.piece_count,
+            piece_key: self.piece_key,
+            pawn_key: self.pawn_key,
+            stale: self.stale,
+            history: Vec::new(),
+        };
+        if board.stale {
+            board.init_pieces();
+        }
+        board
+    }
+
+    // play a move without checking legality, updating status or history
+    // this is what the search uses, use play or make_move otherwise
+    pub(crate) fn do_move(&mut self, mv: Move) {
+        let piece = self[mv.from];
+        let color = piece.get_color();
+        let captured = self[mv.to];
+
+        if piece.is_pawn() || !captured.is_blank() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock = self.halfmove_clock.saturating_add(1);
+        }
+
+        if piece.is_pawn() && captured.is_blank() && Some(mv.to) == self.en_passant {
+            let behind = match color {
+                Color::White => mv.to.down(1),
+                Color::Black => mv.to.up(1),
+            };
+            self.set(behind.unwrap(), BitPiece::new_blank());
+        }
+
+        // castling is encoded as a two squares king move, move the rook too
+        if piece.is_king() && (u8::from(mv.from) % 8).abs_diff(u8::from(mv.to) % 8) == 2 {
+            let (rook_from, rook_to) = if mv.to.file() == 'g' {
+                (mv.to.right(1), mv.to.left(1))
+            } else {
+                (mv.to.left(2), mv.to.right(1))
+            };
+            let (rook_from, rook_to) = (rook_from.unwrap(), rook_to.unwrap());
+            let mut rook = self[rook_from];
+            rook.set_moved();
+            self.set(rook_from, BitPiece::new_blank());
+            self.set(rook_to, rook);
+        }
+
+        let mut moved = match mv.promotion {
+            Some(p) => BitPiece::new(p, color, true),
+            None => piece,
+        };
+        moved.set_moved();
+        self.set(mv.from, BitPiece::new_blank());
+        self.set(mv.to, moved);
+
+        self.en_passant = if piece.is_pawn() && mv.from.rank().abs_diff(mv.to.rank()) == 2 {
+            match color {
+                Color::White => mv.from.up(1),
+                Color::Black => mv.from.down(1),
             }
+        } else {
+            None
+        };
+        self.castling &= castling_mask(mv.from) & castling_mask(mv.to);
+
+        self.turn = self.turn.opposite();
+        if self.turn == Color::White {
+            self.fullmove_number += 1;
         }
-        false
     }
 
-    pub(crate) fn legal_moves(&self) -> Vec<RawMove> {
+    // pass the turn to the opponent, used by null move pruning
+    pub(crate) fn do_null_move(&mut self) {
+        self.en_passant = None;
+        self.turn = self.turn.opposite();
+    }
+
+    // is the side to move in check
+    pub fn in_check(&self) -> bool {
+        match self.king_square(self.turn) {
+            Some(king) => self.is_square_attacked(king, self.turn.opposite()),
+            None => false,
+        }
+    }
+
+    pub(crate) fn update_status(&mut self) {
+        let has_moves = !self.legal_raw_moves().is_empty();
+        self.status = match (self.in_check(), has_moves) {
+            (true, true) => BoardStatus::Check(self.turn),
+            (true, false) => BoardStatus::Checkmate(self.turn),
+            (false, true) => BoardStatus::Ongoing,
+            (false, false) => BoardStatus::Stalemate,
+        };
+    }
+
+    pub(crate) fn legal_raw_moves(&self) -> Vec<RawMove> {
         Legal::gen_all_legal_moves(self)
     }
 
+    // legal moves of the side to move
+    pub fn legal_moves(&self) -> Vec<Move> {
+        self.legal_raw_moves()
+            .into_iter()
+            .flat_map(RawMove::to_moves)
+            .collect()
+    }
+
+    // play a legal move, updating the status and history of the board
+    pub fn play(&mut self, mv: Move) -> Result<(), BoardError> {
+        if !self.legal_moves().contains(&mv) {
+            return Err(BoardError::IllegalMove(mv.to_string()));
+        }
+        let mut info = self.move_info(mv);
+        self.do_move(mv);
+        self.update_status();
+        info.check = matches!(self.status, BoardStatus::Check(_));
+        info.checkmate = matches!(self.status, BoardStatus::Checkmate(_));
+        self.history.push(info);
+        Ok(())
+    }
+
+    fn move_info(&self, mv: Move) -> MoveInfo {
+        let piece = self[mv.from];
+        let captured = self[mv.to];
+        let en_passant = piece.is_pawn() && captured.is_blank() && Some(mv.to) == self.en_passant;
+        let castle = if piece.is_king() && mv.from.file() == 'e' {
+            match mv.to.file() {
+                'g' => Some(CastleMove::KingSide),
+                'c' => Some(CastleMove::QueenSide),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        MoveInfo {
+            piece,
+            from: mv.from,
+            to: mv.to,
+            capture: if en_passant {
+                Some(BitPiece::new(Piece::Pawn, self.turn.opposite(), true))
+            } else if captured.is_blank() {
+                None
+            } else {
+                Some(captured)
+            },
+            promotion: mv.promotion.map(|p| BitPiece::new(p, self.turn, true)),
+            castle: castle.filter(|_| u8::from(mv.from).abs_diff(u8::from(mv.to)) == 2),
+            en_passant,
+            en_passant_square: if piece.is_pawn() && mv.from.rank().abs_diff(mv.to.rank()) == 2 {
+                match self.turn {
+                    Color::White => mv.from.up(1),
+                    Color::Black => mv.from.down(1),
+                }
+            } else {
+                None
+            },
+            check: false,
+            checkmate: false,
+        }
+    }
+
     pub(crate) fn parse_move(&self, m: String) -> Result<RawMove, BoardError> {
         // this is used to parse string move to RawMove to execute move
         // pawn abbreviation may be specified or not, eg. e4 or Pe4
@@ -291,23 +502,11 @@ impl Board {
         // else O-O or O-O-O
         // {to} is a must-have field
         let mut legal_moves = self
-            .legal_moves()
+            .legal_raw_moves()
             .into_iter()
             .filter(|&mov| match mov {
-                RawMove::Single(info) => {
-                    if info.piece.get_color() == self.turn {
-                        true
-                    } else {
-                        false
-                    }
-                }
-                RawMove::Castle(info1, _) => {
-                    if info1.piece.get_color() == self.turn {
-                        true
-                    } else {
-                        false
-                    }
-                }
+                RawMove::Single(info) => info.piece.get_color() == self.turn,
+                RawMove::Castle(info1, _) => info1.piece.get_color() == self.turn,
             })
             .collect::<Vec<RawMove>>();
 
@@ -323,13 +522,13 @@ impl Board {
                 _ => false,
             });
             if let Some(m) = legal_castle {
-                return Ok(m.clone());
+                return Ok(*m);
             } else {
                 return Err(BoardError::IllegalMove(m));
             }
         }
 
-        const PATTERN: &'static str = r"^(?<from>([a-h]|[1-8]))?(?<piece>[kqbnrpKQBNRP])?(?<capture>x)?(?<to>[a-h][1-8])(?<promote>=[qnbrQNBR])?$";
+        const PATTERN: &str = r"^(?<from>([a-h]|[1-8]))?(?<piece>[kqbnrpKQBNRP])?(?<capture>x)?(?<to>[a-h][1-8])(?<promote>=[qnbrQNBR])?$";
         let re = Regex::new(PATTERN).unwrap();
         let Some(cap) = re.captures(&m) else {
             return Err(BoardError::InvalidPattern(m));
@@ -352,7 +551,7 @@ impl Board {
                 RawMove::Single(info) => {
                     // because of the match, from is guaranteed to be a file or rank
                     let c = from.as_str().chars().next().unwrap();
-                    if c.is_digit(10) {
+                    if c.is_ascii_digit() {
                         info.from.rank() == c.to_digit(10).unwrap() as u8
                     } else {
                         info.from.file() == c
@@ -374,7 +573,7 @@ impl Board {
         legal_moves.retain(|&mov| match mov {
             RawMove::Single(info) => {
                 let p = piece;
-                let p = if p.len() == 0 {
+                let p = if p.is_empty() {
                     'P'
                 } else {
                     p.chars().next().unwrap()
@@ -384,7 +583,7 @@ impl Board {
             _ => false,
         });
 
-        if let Some(_) = capture {
+        if capture.is_some() {
             legal_moves.retain(|&mov| match mov {
                 RawMove::Single(info) => info.capture.is_some(),
                 _ => false,
@@ -409,7 +608,7 @@ impl Board {
         // else if more than 1 => ambiguous move
         // else if 0 => illegal move
         if legal_moves.len() == 1 {
-            Ok(legal_moves[0].clone())
+            Ok(legal_moves[0])
         } else if legal_moves.len() > 1 {
             Err(BoardError::AmbiguousMove(m))
         } else {
@@ -418,170 +617,103 @@ impl Board {
     }
 
     pub fn make_move(&mut self, m: String) -> Result<(), BoardError> {
-        let mov = self.parse_move(m.clone())?;
-
-        let mut move_info = match mov {
+        let mv = match self.parse_move(m.clone())? {
             RawMove::Single(info) => {
-                // if pawn move or capture, reset halfmove clock
-                if info.piece.is_pawn() || info.capture.is_some() {
-                    self.halfmove_clock = 0;
-                } else {
-                    self.halfmove_clock += 1;
-                };
-                // update en passant
-                if let Some(en_passant_square) = info.en_passant_square {
-                    self.en_passant = Some(en_passant_square);
-                } else {
-                    self.en_passant = None;
-                };
-                // castling
-                if info.piece.is_king() && !info.piece.has_moved() {
-                    match self.turn {
-                        Color::White => {
-                            self.castling &= 0b1100;
-                        }
-                        Color::Black => {
-                            self.castling &= 0b0011;
-                        }
-                    };
-                } else if info.piece.is_rook() && !info.piece.has_moved() {
-                    match self.turn {
-                        Color::White => {
-                            if info.from.file() == 'h' {
-                                self.castling &= 0b1011;
-                            } else if info.from.file() == 'a' {
-                                self.castling &= 0b1110;
-                            }
-                        }
-                        Color::Black => {
-                            if info.from.file() == 'h' {
-                                self.castling &= 0b0111;
-                            } else if info.from.file() == 'a' {
-                                self.castling &= 0b1101;
-                            }
-                        }
-                    };
-                }
-
-                MoveInfo {
-                    piece: info.piece,
-                    from: info.from,
-                    to: info.to,
-                    capture: if let Some(capture) = info.capture {
-                        Some(capture.piece)
-                    } else {
-                        None
-                    },
-                    promotion: if info.promotion {
-                        let chr = m.chars().last().unwrap();
-                        let p = Piece::try_from(chr).unwrap();
-                        Some(BitPiece::new(p, self.turn, true))
-                    } else {
-                        None
-                    },
-                    castle: None,
-                    en_passant: info.en_passant,
-                    en_passant_square: info.en_passant_square,
-                    check: false,
-                    checkmate: false,
-                }
-            }
-            RawMove::Castle(info1, info2) => {
-                // because we update turn in the beginning of this function
-                // to get the correct turn, we need to get the opposite of current turn
-                match self.turn {
-                    Color::White => {
-                        self.castling &= 0b1100;
-                    }
-                    Color::Black => {
-                        self.castling &= 0b0011;
+                let promotion = if info.promotion {
+                    let chr = m.chars().last().unwrap();
+                    match Piece::try_from(chr) {
+                        Ok(p) => Some(p),
+                        Err(_) => return Err(BoardError::IllegalMove(m)),
                     }
+                } else {
+                    None
                 };
-                self.halfmove_clock += 1;
-                MoveInfo {
-                    piece: info1.piece,
-                    from: info1.from,
-                    to: info1.to,
-                    capture: None,
-                    promotion: None,
-                    castle: if info2.from.file() == 'h' {
-                        Some(CastleMove::KingSide)
-                    } else {
-                        Some(CastleMove::QueenSide)
-                    },
-                    en_passant: false,
-                    en_passant_square: None,
-                    check: false,
-                    checkmate: false,
-                }
+                Move::new(info.from, info.to, promotion)
             }
+            RawMove::Castle(king, _) => Move::new(king.from, king.to, None),
         };
+        self.play(mv)
+    }
 
-        self.execute_move(&move_info, &mov);
-        self.turn = self.turn.opposite();
-        let next_legal_moves = self.legal_moves();
-
-        if self.is_being_checked(self.turn, &next_legal_moves) {
-            // if being checked and no next legal moves => checkmate
-            // else it is just check
-            if next_legal_moves
-                .iter()
-                .find(|&mov| match mov {
-                    RawMove::Single(info) => {
-                        if info.piece.get_color() == self.turn {
-                            true
-                        } else {
-                            false
-                        }
-                    }
-                    RawMove::Castle(info1, _) => {
-                        if info1.piece.get_color() == self.turn {
-                            true
-                        } else {
-                            false
-                        }
-                    }
-                })
-                .is_some()
-            {
-                self.status = BoardStatus::Check(self.turn);
-                move_info.check = true;
-            } else {
-                self.status = BoardStatus::Checkmate(self.turn);
-                move_info.checkmate = true;
-            }
-        } else {
-            // if not being checked and no next legal moves => stalemate
-            // else it is just normal move
-            if next_legal_moves.iter().find(|&mov| match mov {
-                RawMove::Single(info) => {
-                    if info.piece.get_color() == self.turn {
-                        true
-                    } else {
-                        false
-                    }
-                }
-                RawMove::Castle(info1, _) => {
-                    if info1.piece.get_color() == self.turn {
-                        true
-                    } else {
-                        false
-                    }
-                }
-            }).is_some() {
-                self.status = BoardStatus::Ongoing;
-            } else {
-                self.status = BoardStatus::Stalemate;
-            }
-        }
+    // the distance to mate from the loaded endgame tablebases, none when no
+    // table has the position
+    pub fn tablebase_result(&self) -> Option<TablebaseResult> {
+        crate::tablebase::probe(self)
+    }
 
-        if self.turn == Color::White {
-            self.fullmove_number += 1;
+    // zobrist hash of the whole position
+    pub fn hash(&self) -> u64 {
+        let mut key = self.keys().1;
+        key ^= ZOBRIST.castling[self.castling as usize & 0b1111];
+        if let Some(sq) = self.en_passant {
+            key ^= ZOBRIST.en_passant[usize::from(sq) % 8];
         }
+        if self.turn == Color::Black {
+            key ^= ZOBRIST.black_to_move;
+        }
+        key
+    }
 
-        self.history.push(move_info);
+    // zobrist hash of the pawns only, used to cache pawn structure
+    pub fn pawn_hash(&self) -> u64 {
+        self.keys().2
+    }
 
-        Ok(())
+    // all squares attacked by the pieces of color
+    pub fn attacks(&self, color: Color) -> AttackMap {
+        AttackMap::new(self, color)
+    }
+
+    pub fn is_square_attacked(&self, square: Square, by: Color) -> bool {
+        Attacks::is_attacked(self, square, by)
+    }
+
+    pub fn king_square(&self, color: Color) -> Option<Square> {
+        self.pieces()
+            .find(|(_, p)| p.is_king() && p.get_color() == color)
+            .map(|(sq, _)| sq)
+    }
+
+    // iterate over all occupied squares
+    pub fn pieces(&self) -> impl Iterator<Item = (Square, BitPiece)> + '_ {
+        self.board
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| !p.is_blank())
+            .map(|(i, &p)| (Square::try_from(i as u8).unwrap(), p))
+    }
+
+    // flip the board vertically and swap the colors of all pieces
+    // the resulting position is the same game seen from the other side,
+    // so any evaluation must give the same score for the side to move
+    pub fn mirror(&self) -> Board {
+        let mut board = [BitPiece::new_blank(); 64];
+        for (square, p) in self.pieces() {
+            board[usize::from(square) ^ 56] =
+                BitPiece::new(p.get_piece(), p.get_color().opposite(), p.has_moved());
+        }
+        let mut mirror = Self {
+            board,
+            turn: self.turn.opposite(),
+            status: match self.status {
+                BoardStatus::Check(color) => BoardStatus::Check(color.opposite()),
+                BoardStatus::Checkmate(color) => BoardStatus::Checkmate(color.opposite()),
+                status => status,
+            },
+            castling: (self.castling & 0b1100) >> 2 | (self.castling & 0b0011) << 2,
+            en_passant: self
+                .en_passant
+                .map(|sq| Square::try_from(u8::from(sq) ^ 56).unwrap()),
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            piece_count: 0,
+            piece_key: 0,
+            pawn_key: 0,
+            stale: false,
+            history: Vec::new(),
+        };
+        mirror.init_pieces();
+        mirror
     }
 
     pub fn fen_notation(&self) -> String {
@@ -650,6 +782,19 @@ impl Board {
     }
 }
 
+// castling rights that survive a move from or to a square
+fn castling_mask(square: Square) -> u8 {
+    match u8::from(square) {
+        0 => 0b1011,
+        4 => 0b0011,
+        7 => 0b0111,
+        56 => 0b1110,
+        60 => 0b1100,
+        63 => 0b1101,
+        _ => 0b1111,
+    }
+}
+
 impl fmt::Display for Board {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for i in (0..8).rev() {
@@ -662,8 +807,8 @@ impl fmt::Display for Board {
                     write!(f, "{} ", p)?;
                 }
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
-        write!(f, "  a b c d e f g h\n")
+        writeln!(f, "  a b c d e f g h")
     }
 }
@@ -0,0 +1,207 @@
+use std::{cmp::Reverse, collections::HashMap, fs, path::Path};
+
+use super::{encode_move, polyglot_key, BookEntry};
+use crate::{parse_san, BoardError, BookError, PgnGame};
+
+// builds polyglot books from games
+// the moves of the first plies of every game are counted per position. a
+// move scores 2 points when the side playing it won and 1 for a draw, like
+// the polyglot book maker, and its weight in the book is its points, scaled
+// down per position when they do not fit 16 bits
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BookFilter {
+    // plies of every game to use
+    pub max_ply: usize,
+    // moves played in fewer games are left out
+    pub min_games: u32,
+    // only count the moves of players rated at least this
+    pub min_rating: Option<u32>,
+    // only count the moves of the side that won
+    pub winners_only: bool,
+}
+
+impl Default for BookFilter {
+    fn default() -> Self {
+        BookFilter {
+            max_ply: 20,
+            min_games: 3,
+            min_rating: None,
+            winners_only: false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct MoveStats {
+    games: u32,
+    points: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BookBuilder {
+    filter: BookFilter,
+    // by polyglot key and move
+    moves: HashMap<(u64, u16), MoveStats>,
+}
+
+impl BookBuilder {
+    pub fn new(filter: BookFilter) -> BookBuilder {
+        BookBuilder {
+            filter,
+            moves: HashMap::new(),
+        }
+    }
+
+    // count the moves of the game, up to the first move that cannot be
+    // played
+    pub fn add_game(&mut self, game: &PgnGame) -> Result<(), BoardError> {
+        let winner = game.winner();
+        let draw = game.result == "1/2-1/2";
+        if self.filter.winners_only && winner.is_none() {
+            return Ok(());
+        }
+        let mut board = game.start()?;
+        for san in game.moves.iter().take(self.filter.max_ply) {
+            let mv = parse_san(&board, san)?;
+            let side = board.turn;
+            let rated = self
+                .filter
+                .min_rating
+                .is_none_or(|min| game.rating(side).is_some_and(|r| r >= min));
+            if rated && (!self.filter.winners_only || winner == Some(side)) {
+                let key = (polyglot_key(&board), encode_move(&board, mv));
+                let stats = self.moves.entry(key).or_default();
+                stats.games += 1;
+                stats.points += if winner == Some(side) {
+                    2
+                } else if draw {
+                    1
+                } else {
+                    0
+                };
+            }
+            board.play(mv)?;
+        }
+        Ok(())
+    }
+
+    // the entries of the book, sorted by key and best first
+    pub fn entries(&self) -> Vec<BookEntry> {
+        let mut moves: Vec<(u64, u16, u32)> = self
+            .moves
+            .iter()
+            .filter(|(_, s)| s.games >= self.filter.min_games && s.points > 0)
+            .map(|(&(key, raw_move), s)| (key, raw_move, s.points))
+            .collect();
+        moves.sort_by_key(|&(key, raw_move, points)| (key, Reverse(points), raw_move));
+        let mut entries = Vec::with_capacity(moves.len());
+        for position in moves.chunk_by(|a, b| a.0 == b.0) {
+            let most = position[0].2.max(u16::MAX as u32) as u64;
+            entries.extend(position.iter().map(|&(key, raw_move, points)| BookEntry {
+                key,
+                raw_move,
+                weight: (points as u64 * u16::MAX as u64 / most) as u16,
+                learn: 0,
+            }));
+        }
+        entries
+    }
+
+    // write the book, returning the number of entries
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<usize, BookError> {
+        let entries = self.entries();
+        let bytes: Vec<u8> = entries.iter().flat_map(|e| e.to_bytes()).collect();
+        fs::write(path, bytes)?;
+        Ok(entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{book::Book, parse_pgn, Board, Move};
+
+    const GAMES: &str = r#"
+[WhiteElo "2500"] [BlackElo "2000"]
+1. e4 e5 2. Nf3 Nc6 1-0
+[WhiteElo "2500"] [BlackElo "2000"]
+1. e4 c5 2. Nf3 d6 0-1
+[WhiteElo "1800"] [BlackElo "2400"]
+1. d4 d5 2. c4 e6 1/2-1/2
+[WhiteElo "2400"] [BlackElo "2400"]
+1. e4 e5 2. Bc4 Nf6 1-0
+"#;
+
+    fn build(filter: BookFilter) -> Book {
+        let mut builder = BookBuilder::new(BookFilter {
+            min_games: 1,
+            ..filter
+        });
+        for game in parse_pgn(GAMES) {
+            builder.add_game(&game).unwrap();
+        }
+        let bytes: Vec<u8> = builder
+            .entries()
+            .iter()
+            .flat_map(|e| e.to_bytes())
+            .collect();
+        Book::from_bytes(&bytes).unwrap()
+    }
+
+    fn moves(book: &Book, moves: &[&str]) -> Vec<(String, u16)> {
+        let mut board = Board::default();
+        for mv in moves {
+            board.play(Move::try_from(*mv).unwrap()).unwrap();
+        }
+        book.moves(&board)
+            .into_iter()
+            .map(|(mv, weight)| (mv.to_string(), weight))
+            .collect()
+    }
+
+    #[test]
+    fn builds_books() {
+        let book = build(BookFilter::default());
+        // e4 won twice and lost once, d4 drew once
+        assert_eq!(moves(&book, &[]), [("e2e4".into(), 4), ("d2d4".into(), 1)]);
+        assert_eq!(moves(&book, &["e2e4"]), [("c7c5".into(), 2)]);
+        assert_eq!(
+            moves(&book, &["e2e4", "e7e5"]),
+            [("f1c4".into(), 2), ("g1f3".into(), 2)]
+        );
+        assert!(book.entries.windows(2).all(|w| w[0].key <= w[1].key));
+
+        let shallow = build(BookFilter {
+            max_ply: 1,
+            ..Default::default()
+        });
+        assert!(moves(&shallow, &["e2e4"]).is_empty());
+
+        let rated = build(BookFilter {
+            min_rating: Some(2400),
+            ..Default::default()
+        });
+        assert_eq!(moves(&rated, &[]), [("e2e4".into(), 4)]);
+        assert_eq!(moves(&rated, &["d2d4"]), [("d7d5".into(), 1)]);
+
+        let winners = build(BookFilter {
+            winners_only: true,
+            ..Default::default()
+        });
+        assert_eq!(moves(&winners, &[]), [("e2e4".into(), 4)]);
+        assert!(moves(&winners, &["d2d4"]).is_empty());
+        assert_eq!(moves(&winners, &["e2e4"]), [("c7c5".into(), 2)]);
+    }
+
+    #[test]
+    fn scales_weights() {
+        let mut builder = BookBuilder::new(BookFilter::default());
+        for (raw_move, points) in [(1, 200000), (2, 100000), (3, 1)] {
+            let stats = MoveStats { games: 5, points };
+            builder.moves.insert((7, raw_move), stats);
+        }
+        let weights: Vec<u16> = builder.entries().iter().map(|e| e.weight).collect();
+        assert_eq!(weights, [u16::MAX, u16::MAX / 2, 0]);
+    }
+}
@@ -0,0 +1,245 @@
+use std::{fs, path::Path};
+
+use crate::{search::Rng, Board, BookError, Move, Piece, Square};
+
+mod keys;
+pub use self::keys::*;
+
+mod builder;
+pub use self::builder::*;
+
+// polyglot opening books
+// a book is a sorted array of 16 byte entries: the polyglot key of the
+// position, a move, its weight and learning data, all big endian. a
+// position has one entry per book move, so its moves are found with a
+// binary search for the first entry with its key
+
+const ENTRY_SIZE: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: u64,
+    // to file, to row, from file, from row and promotion, 3 bits each
+    pub raw_move: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl BookEntry {
+    fn parse(bytes: &[u8]) -> BookEntry {
+        BookEntry {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            raw_move: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(bytes[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.raw_move.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+
+    // the move in the position, none if it is not legal there
+    pub fn decode_move(&self, board: &Board) -> Option<Move> {
+        let square = |bits: u16| Square::try_from((bits & 63) as u8).unwrap();
+        let from = square(self.raw_move >> 6);
+        let mut to = square(self.raw_move);
+        let promotion = match self.raw_move >> 12 & 7 {
+            1 => Some(Piece::Knight),
+            2 => Some(Piece::Bishop),
+            3 => Some(Piece::Rook),
+            4 => Some(Piece::Queen),
+            _ => None,
+        };
+        // castling is written as the king taking its own rook
+        let king = board[from];
+        let target = board[to];
+        if !king.is_blank()
+            && king.get_piece() == Piece::King
+            && !target.is_blank()
+            && target.get_piece() == Piece::Rook
+            && target.get_color() == king.get_color()
+        {
+            let (from, rook) = (u8::from(from), u8::from(to));
+            to = Square::try_from(if rook > from { from + 2 } else { from - 2 }).unwrap();
+        }
+        let mv = Move {
+            from,
+            to,
+            promotion,
+        };
+        board.legal_moves().contains(&mv).then_some(mv)
+    }
+}
+
+// the polyglot encoding of a legal move in the position
+pub fn encode_move(board: &Board, mv: Move) -> u16 {
+    let (from, mut to) = (u8::from(mv.from), u8::from(mv.to));
+    let king = board[mv.from];
+    // castling is written as the king taking its own rook
+    if !king.is_blank() && king.get_piece() == Piece::King && from.abs_diff(to) == 2 {
+        to = if to > from { from + 3 } else { from - 4 };
+    }
+    let promotion = match mv.promotion {
+        Some(Piece::Knight) => 1,
+        Some(Piece::Bishop) => 2,
+        Some(Piece::Rook) => 3,
+        Some(Piece::Queen) => 4,
+        _ => 0,
+    };
+    to as u16 | (from as u16) << 6 | promotion << 12
+}
+
+// how to choose among the book moves of a position
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum BookSelection {
+    // the move with the highest weight
+    Best,
+    // a random move with a chance proportional to its weight
+    #[default]
+    Weighted,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Book {
+    entries: Vec<BookEntry>,
+}
+
+impl Book {
+    pub fn open(path: impl AsRef<Path>) -> Result<Book, BookError> {
+        Book::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Book, BookError> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(BookError::InvalidSize(bytes.len()));
+        }
+        Ok(Book {
+            entries: bytes.chunks(ENTRY_SIZE).map(BookEntry::parse).collect(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // the entries of the position, in book order
+    pub fn entries(&self, board: &Board) -> &[BookEntry] {
+        let key = polyglot_key(board);
+        let start = self.entries.partition_point(|e| e.key < key);
+        let end = start + self.entries[start..].partition_point(|e| e.key == key);
+        &self.entries[start..end]
+    }
+
+    // the legal book moves of the position with their weights
+    pub fn moves(&self, board: &Board) -> Vec<(Move, u16)> {
+        self.entries(board)
+            .iter()
+            .filter_map(|e| e.decode_move(board).map(|mv| (mv, e.weight)))
+            .collect()
+    }
+
+    pub fn pick(&self, board: &Board, selection: BookSelection, rng: &mut Rng) -> Option<Move> {
+        let moves = self.moves(board);
+        match selection {
+            // the first of equal weights, like the book order
+            BookSelection::Best => moves
+                .iter()
+                .rev()
+                .max_by_key(|&&(_, weight)| weight)
+                .map(|&(mv, _)| mv),
+            BookSelection::Weighted => {
+                let total: u64 = moves.iter().map(|&(_, w)| w as u64).sum();
+                if total == 0 {
+                    return None;
+                }
+                let mut choice = rng.next_u64() % total;
+                moves.into_iter().find_map(|(mv, weight)| {
+                    if choice < weight as u64 {
+                        Some(mv)
+                    } else {
+                        choice -= weight as u64;
+                        None
+                    }
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(board: &Board, mv: &str, weight: u16) -> Vec<u8> {
+        // the raw move as written by other tools, castling included
+        let mv = Move::try_from(mv).unwrap();
+        let raw = u8::from(mv.to) as u16 | (u8::from(mv.from) as u16) << 6;
+        let entry = BookEntry {
+            key: polyglot_key(board),
+            raw_move: raw,
+            weight,
+            learn: 0,
+        };
+        entry.to_bytes().to_vec()
+    }
+
+    #[test]
+    fn reads_entries() {
+        let start = Board::default();
+        let castle = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1".into()).unwrap();
+        let mut entries = vec![
+            (polyglot_key(&start), entry(&start, "e2e4", 10)),
+            (polyglot_key(&start), entry(&start, "d2d4", 30)),
+            // not legal, skipped
+            (polyglot_key(&start), entry(&start, "e2e5", 50)),
+            (polyglot_key(&castle), entry(&castle, "e1h1", 1)),
+            (polyglot_key(&castle), entry(&castle, "e1a1", 1)),
+        ];
+        entries.sort_by_key(|&(key, _)| key);
+        let bytes: Vec<u8> = entries.into_iter().flat_map(|(_, e)| e).collect();
+        let book = Book::from_bytes(&bytes).unwrap();
+        assert!(matches!(
+            Book::from_bytes(&bytes[1..]),
+            Err(BookError::InvalidSize(79))
+        ));
+        assert_eq!(book.len(), 5);
+
+        let e4 = Move::try_from("e2e4").unwrap();
+        let d4 = Move::try_from("d2d4").unwrap();
+        assert_eq!(book.entries(&start).len(), 3);
+        assert_eq!(book.moves(&start), [(e4, 10), (d4, 30)]);
+        let castles = book.moves(&castle);
+        assert!(castles.contains(&(Move::try_from("e1g1").unwrap(), 1)));
+        assert!(castles.contains(&(Move::try_from("e1c1").unwrap(), 1)));
+        assert!(book
+            .moves(&Board::from_fen("8/8/8/8/8/8/8/K6k w - - 0 1".into()).unwrap())
+            .is_empty());
+
+        let e1g1 = Move::try_from("e1g1").unwrap();
+        let e1c1 = Move::try_from("e1c1").unwrap();
+        assert_eq!(encode_move(&castle, e1g1), 4 << 6 | 7);
+        assert_eq!(encode_move(&castle, e1c1), 4 << 6);
+        let promotion = Board::from_fen("8/P6k/8/8/8/8/8/K7 w - - 0 1".into()).unwrap();
+        assert_eq!(
+            encode_move(&promotion, Move::try_from("a7a8q").unwrap()),
+            4 << 12 | 48 << 6 | 56
+        );
+
+        let mut rng = Rng::new(3);
+        assert_eq!(book.pick(&start, BookSelection::Best, &mut rng), Some(d4));
+        let e4_count = (0..1000)
+            .filter(|_| book.pick(&start, BookSelection::Weighted, &mut rng) == Some(e4))
+            .count();
+        assert!((150..350).contains(&e4_count), "{}", e4_count);
+    }
+}
@@ -1,9 +1,13 @@
 // this file contains the definition of a chess move
 // which will be stored in history of the match
 use crate::BitPiece;
+use crate::BoardError;
+use crate::Piece;
 use crate::Square;
-#[derive(Debug, Copy, Clone, PartialEq)]
+use crate::PROMOTION_PIECES;
+use std::fmt;
 
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum CastleMove {
     KingSide,
     QueenSide,
@@ -30,3 +34,53 @@ pub struct MoveInfo {
     // does this move checkmate the opponent's king?
     pub checkmate: bool,
 }
+
+// a move in long algebraic notation as used by engine protocols
+// castling is written as the king move, eg. e1g1
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Move {
+    pub from: Square,
+    pub to: Square,
+    pub promotion: Option<Piece>,
+}
+
+impl Move {
+    pub fn new(from: Square, to: Square, promotion: Option<Piece>) -> Move {
+        Move {
+            from,
+            to,
+            promotion,
+        }
+    }
+}
+
+impl TryFrom<&str> for Move {
+    type Error = BoardError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        if !(4..=5).contains(&value.len()) || !value.is_ascii() {
+            return Err(BoardError::InvalidPattern(value.to_owned()));
+        }
+        let square = |s: &str| {
+            Square::try_from(s.to_owned()).map_err(|_| BoardError::InvalidPattern(value.to_owned()))
+        };
+        let promotion = match value.chars().nth(4) {
+            Some(c) => match Piece::try_from(c) {
+                Ok(p) if PROMOTION_PIECES.contains(&p) => Some(p),
+                _ => return Err(BoardError::InvalidPattern(value.to_owned())),
+            },
+            None => None,
+        };
+        Ok(Move::new(square(&value[0..2])?, square(&value[2..4])?, promotion))
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.from, self.to)?;
+        if let Some(p) = self.promotion {
+            write!(f, "{}", p)?;
+        }
+        Ok(())
+    }
+}
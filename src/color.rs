@@ -5,7 +5,7 @@ use crate::ColorError;
 type ColorResult<T> = Result<T, ColorError>;
 
 #[repr(u8)]
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Color {
     White = 0,
     Black = 1,
@@ -39,3 +39,45 @@ pub enum BoardError {
     #[error("Invalid pattern: {0}")]
     InvalidPattern(String),
 }
+
+#[derive(Error, Debug)]
+pub enum BookError {
+    #[error("Cannot read book: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid book size: {0} bytes")]
+    InvalidSize(usize),
+}
+
+#[derive(Error, Debug)]
+pub enum TablebaseError {
+    #[error("Invalid tablebase signature: {0}")]
+    InvalidSignature(String),
+    #[error("Cannot read tablebase: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid tablebase file: {0}")]
+    InvalidFile(String),
+}
+
+#[derive(Error, Debug)]
+pub enum NetworkError {
+    #[error("Cannot read network: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid network file: {0}")]
+    InvalidFile(String),
+}
+
+#[derive(Error, Debug)]
+pub enum ParamsError {
+    #[error("Cannot read parameters: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid parameters on line {0}: {1}")]
+    InvalidLine(usize, String),
+}
+
+#[derive(Error, Debug)]
+pub enum TuneError {
+    #[error("Cannot read positions: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("No labeled positions in {0}")]
+    NoPositions(String),
+}
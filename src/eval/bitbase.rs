@@ -0,0 +1,153 @@
+use std::sync::OnceLock;
+
+// king and pawn against king bitbase
+// one bit per position telling whether white, with the pawn, wins. the
+// positions have the pawn on files a to d, the others are looked up with the
+// files flipped. the bits are computed on first use: positions are
+// classified by their moves until nothing changes, white to move wins with a
+// move to a won position and black to move draws with a move to a drawn one
+
+// side to move, pawn on 24 squares and both kings
+const SIZE: usize = 2 * 24 * 64 * 64;
+
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+fn index(white_to_move: bool, white_king: u8, pawn: u8, black_king: u8) -> usize {
+    let pawn = (pawn / 8 - 1) as usize * 4 + (pawn % 8) as usize;
+    let side = if white_to_move { 0 } else { 1 };
+    ((side * 24 + pawn) * 64 + white_king as usize) * 64 + black_king as usize
+}
+
+fn distance(a: u8, b: u8) -> u8 {
+    let file = (a % 8).abs_diff(b % 8);
+    let rank = (a / 8).abs_diff(b / 8);
+    file.max(rank)
+}
+
+fn king_moves(square: u8) -> impl Iterator<Item = u8> {
+    (0..64).filter(move |&s| distance(s, square) == 1)
+}
+
+fn pawn_attacks(pawn: u8, square: u8) -> bool {
+    square / 8 == pawn / 8 + 1 && (square % 8).abs_diff(pawn % 8) == 1
+}
+
+// the positions decided without looking at the moves
+fn initial(white_to_move: bool, white_king: u8, pawn: u8, black_king: u8) -> u8 {
+    if distance(white_king, black_king) <= 1
+        || white_king == pawn
+        || black_king == pawn
+        || (white_to_move && pawn_attacks(pawn, black_king))
+    {
+        return INVALID;
+    }
+    let queen = pawn + 8;
+    // the pawn promotes and the queen cannot be taken
+    if white_to_move
+        && pawn / 8 == 6
+        && white_king != queen
+        && (distance(black_king, queen) > 1 || distance(white_king, queen) == 1)
+    {
+        return WIN;
+    }
+    if !white_to_move {
+        let mut moves = king_moves(black_king)
+            .filter(|&s| distance(s, white_king) > 1 && !pawn_attacks(pawn, s));
+        // stalemate, or the pawn is taken
+        if moves.next().is_none()
+            || (distance(black_king, pawn) == 1 && distance(white_king, pawn) > 1)
+        {
+            return DRAW;
+        }
+    }
+    UNKNOWN
+}
+
+fn classify(results: &[u8], white_to_move: bool, white_king: u8, pawn: u8, black_king: u8) -> u8 {
+    let mut moves = INVALID;
+    if white_to_move {
+        for square in king_moves(white_king) {
+            moves |= results[index(false, square, pawn, black_king)];
+        }
+        let one = pawn + 8;
+        if pawn / 8 < 6 && one != white_king && one != black_king {
+            moves |= results[index(false, white_king, one, black_king)];
+            let two = one + 8;
+            if pawn / 8 == 1 && two != white_king && two != black_king {
+                moves |= results[index(false, white_king, two, black_king)];
+            }
+        }
+        if moves & WIN != 0 {
+            WIN
+        } else if moves & UNKNOWN != 0 {
+            UNKNOWN
+        } else {
+            DRAW
+        }
+    } else {
+        for square in king_moves(black_king) {
+            moves |= results[index(true, white_king, pawn, square)];
+        }
+        if moves & DRAW != 0 {
+            DRAW
+        } else if moves & UNKNOWN != 0 {
+            UNKNOWN
+        } else {
+            WIN
+        }
+    }
+}
+
+fn positions() -> impl Iterator<Item = (bool, u8, u8, u8)> {
+    [true, false].into_iter().flat_map(|white_to_move| {
+        (8..56).filter(|pawn| pawn % 8 < 4).flat_map(move |pawn| {
+            (0..64).flat_map(move |white_king| {
+                (0..64).map(move |black_king| (white_to_move, white_king, pawn, black_king))
+            })
+        })
+    })
+}
+
+fn generate() -> Vec<u64> {
+    let mut results = vec![INVALID; SIZE];
+    for (white_to_move, white_king, pawn, black_king) in positions() {
+        results[index(white_to_move, white_king, pawn, black_king)] =
+            initial(white_to_move, white_king, pawn, black_king);
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (white_to_move, white_king, pawn, black_king) in positions() {
+            let i = index(white_to_move, white_king, pawn, black_king);
+            if results[i] == UNKNOWN {
+                results[i] = classify(&results, white_to_move, white_king, pawn, black_king);
+                changed |= results[i] != UNKNOWN;
+            }
+        }
+    }
+    let mut bits = vec![0; SIZE / 64];
+    for (i, &result) in results.iter().enumerate() {
+        if result == WIN {
+            bits[i / 64] |= 1 << (i % 64);
+        }
+    }
+    bits
+}
+
+static BITBASE: OnceLock<Vec<u64>> = OnceLock::new();
+
+// whether white wins with the king and pawn on these squares, the pawn
+// anywhere on ranks 2 to 7
+pub fn kpk_win(white_to_move: bool, white_king: u8, pawn: u8, black_king: u8) -> bool {
+    let flip = if pawn % 8 > 3 { 7 } else { 0 };
+    let i = index(
+        white_to_move,
+        white_king ^ flip,
+        pawn ^ flip,
+        black_king ^ flip,
+    );
+    BITBASE.get_or_init(generate)[i / 64] & (1 << (i % 64)) != 0
+}
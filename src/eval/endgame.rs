@@ -0,0 +1,383 @@
+use super::kpk_win;
+use crate::{Board, Color, Piece};
+
+// endgames the general evaluation misjudges
+// known endgames get an evaluation of their own, found by the material of
+// the two sides: the bare king against enough material to mate is driven to
+// the edge, with a bishop and knight to a corner of the bishop's color, and
+// king and pawn against king is won or drawn as the bitbase says. in other
+// drawish endgames the endgame part of the score is scaled down
+
+// a won endgame, above any advantage the general evaluation gives
+pub const KNOWN_WIN: i32 = 10000;
+
+// scale factors are out of this
+pub const SCALE_NORMAL: i32 = 64;
+// opposite colored bishops and pawns
+const SCALE_BISHOPS: i32 = 22;
+// opposite colored bishops with other pieces
+const SCALE_BISHOPS_PIECES: i32 = 46;
+// no pawns and a minor piece more, eg. rook against bishop
+const SCALE_MINOR_UP: i32 = 8;
+const SCALE_MINOR_UP_PIECES: i32 = 24;
+
+// values of the pieces in these endgames
+const VALUES: [i32; 6] = [100, 300, 300, 500, 900, 0];
+
+// the number of pieces of each side, indexed by color and piece
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Material {
+    counts: [[u8; 6]; 2],
+    // the colors of the squares of each side's bishops, 1 for light and 2
+    // for dark
+    bishops: [u8; 2],
+}
+
+impl Material {
+    pub fn new(board: &Board) -> Material {
+        let mut counts = [[0; 6]; 2];
+        let mut bishops = [0; 2];
+        for (sq, p) in board.pieces() {
+            counts[p.get_color() as usize][p.get_piece() as usize - 1] += 1;
+            if p.get_piece() == Piece::Bishop {
+                bishops[p.get_color() as usize] |= if dark(u8::from(sq)) { 2 } else { 1 };
+            }
+        }
+        Material { counts, bishops }
+    }
+
+    pub fn count(&self, color: Color, piece: Piece) -> u8 {
+        self.counts[color as usize][piece as usize - 1]
+    }
+
+    // value of the pieces other than pawns
+    pub fn non_pawn(&self, color: Color) -> i32 {
+        (1..5)
+            .map(|p| self.counts[color as usize][p] as i32 * VALUES[p])
+            .sum()
+    }
+
+    fn only(&self, color: Color, pieces: &[(Piece, u8)]) -> bool {
+        [
+            Piece::Pawn,
+            Piece::Knight,
+            Piece::Bishop,
+            Piece::Rook,
+            Piece::Queen,
+        ]
+        .iter()
+        .all(|&piece| {
+            let wanted = pieces.iter().find(|&&(p, _)| p == piece).map_or(0, |p| p.1);
+            self.count(color, piece) == wanted
+        })
+    }
+
+    fn bare(&self, color: Color) -> bool {
+        self.only(color, &[])
+    }
+
+    // bishops on both colors of squares
+    fn bishop_pair(&self, color: Color) -> bool {
+        self.bishops[color as usize] == 3
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Endgame {
+    // king and pawn against king
+    Kpk(Color),
+    // bishop and knight against king
+    Kbnk(Color),
+    // enough to mate against a bare king
+    Kxk(Color),
+}
+
+impl Endgame {
+    // the endgame of the material, if it has an evaluation of its own
+    pub fn find(material: &Material) -> Option<Endgame> {
+        for strong in [Color::White, Color::Black] {
+            if !material.bare(strong.opposite()) {
+                continue;
+            }
+            let count = |piece| material.count(strong, piece);
+            if material.only(strong, &[(Piece::Pawn, 1)]) {
+                return Some(Endgame::Kpk(strong));
+            }
+            if material.only(strong, &[(Piece::Bishop, 1), (Piece::Knight, 1)]) {
+                return Some(Endgame::Kbnk(strong));
+            }
+            if count(Piece::Queen) > 0
+                || count(Piece::Rook) > 0
+                || (count(Piece::Bishop) > 0 && count(Piece::Knight) > 0)
+                || material.bishop_pair(strong)
+            {
+                return Some(Endgame::Kxk(strong));
+            }
+        }
+        None
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Endgame::Kpk(_) => "KPK",
+            Endgame::Kbnk(_) => "KBNK",
+            Endgame::Kxk(_) => "KXK",
+        }
+    }
+
+    // the evaluation from white's point of view
+    pub fn evaluate(&self, board: &Board) -> i32 {
+        let (Endgame::Kpk(strong) | Endgame::Kbnk(strong) | Endgame::Kxk(strong)) = *self;
+        let score = match self {
+            Endgame::Kpk(_) => kpk(board, strong),
+            Endgame::Kbnk(_) => kbnk(board, strong),
+            Endgame::Kxk(_) => kxk(board, strong),
+        };
+        match strong {
+            Color::White => score,
+            Color::Black => -score,
+        }
+    }
+}
+
+// the squares of the pieces seen from the strong side, with its pawns going
+// up the board
+fn squares(board: &Board, strong: Color, piece: Piece, color: Color) -> Vec<u8> {
+    board
+        .pieces()
+        .filter(|(_, p)| p.get_piece() == piece && p.get_color() == color)
+        .map(|(sq, _)| match strong {
+            Color::White => u8::from(sq),
+            Color::Black => u8::from(sq) ^ 56,
+        })
+        .collect()
+}
+
+fn distance(a: u8, b: u8) -> i32 {
+    let file = (a % 8).abs_diff(b % 8);
+    let rank = (a / 8).abs_diff(b / 8);
+    file.max(rank) as i32
+}
+
+fn dark(square: u8) -> bool {
+    (square % 8 + square / 8).is_multiple_of(2)
+}
+
+// bonus for the weak king near the edge, 0 in the center to 60 in a corner
+fn push_to_edge(square: u8) -> i32 {
+    let edge = |c: u8| if c < 4 { 3 - c } else { c - 4 } as i32;
+    10 * (edge(square % 8) + edge(square / 8))
+}
+
+// bonus for the kings close together
+fn push_close(a: u8, b: u8) -> i32 {
+    140 - 20 * distance(a, b)
+}
+
+fn kings(board: &Board, strong: Color) -> (u8, u8) {
+    (
+        squares(board, strong, Piece::King, strong)[0],
+        squares(board, strong, Piece::King, strong.opposite())[0],
+    )
+}
+
+// stalemate is the only way the bare king escapes
+fn stalemate(board: &Board, strong: Color) -> bool {
+    board.turn != strong && !board.in_check() && board.legal_moves().is_empty()
+}
+
+fn kxk(board: &Board, strong: Color) -> i32 {
+    if stalemate(board, strong) {
+        return 0;
+    }
+    let (king, bare) = kings(board, strong);
+    let material = Material::new(board);
+    let material =
+        material.non_pawn(strong) + material.count(strong, Piece::Pawn) as i32 * VALUES[0];
+    KNOWN_WIN + material + push_to_edge(bare) + push_close(king, bare)
+}
+
+fn kbnk(board: &Board, strong: Color) -> i32 {
+    if stalemate(board, strong) {
+        return 0;
+    }
+    let (king, bare) = kings(board, strong);
+    let bishop = squares(board, strong, Piece::Bishop, strong)[0];
+    // mate is only possible in the corners the bishop covers
+    let corners: [u8; 2] = if dark(bishop) { [0, 63] } else { [7, 56] };
+    let corner = corners.iter().map(|&c| distance(c, bare)).min().unwrap();
+    KNOWN_WIN + VALUES[1] + VALUES[2] + 30 * (7 - corner) + push_close(king, bare)
+}
+
+fn kpk(board: &Board, strong: Color) -> i32 {
+    let (king, bare) = kings(board, strong);
+    let pawn = squares(board, strong, Piece::Pawn, strong)[0];
+    if kpk_win(board.turn == strong, king, pawn, bare) {
+        KNOWN_WIN + VALUES[0] + 10 * (pawn / 8) as i32
+    } else {
+        0
+    }
+}
+
+// how much of the endgame part of the score the strong side keeps, out of
+// SCALE_NORMAL
+pub fn scale_factor(board: &Board, material: &Material, strong: Color) -> i32 {
+    let weak = strong.opposite();
+    let pawns = material.count(strong, Piece::Pawn);
+    let (strong_pieces, weak_pieces) = (material.non_pawn(strong), material.non_pawn(weak));
+
+    // a bishop that does not cover the promotion square cannot drive the
+    // king out of the corner in front of rook pawns
+    if material.only(strong, &[(Piece::Bishop, 1), (Piece::Pawn, pawns)])
+        && pawns > 0
+        && weak_pieces == 0
+    {
+        let files: Vec<u8> = squares(board, strong, Piece::Pawn, strong)
+            .iter()
+            .map(|s| s % 8)
+            .collect();
+        let file = files[0];
+        if (file == 0 || file == 7) && files.iter().all(|&f| f == file) {
+            let queen = 56 + file;
+            let bishop = squares(board, strong, Piece::Bishop, strong)[0];
+            let (_, bare) = kings(board, strong);
+            if dark(bishop) != dark(queen) && distance(bare, queen) <= 1 {
+                return 0;
+            }
+        }
+    }
+
+    // opposite colored bishops
+    if material.count(strong, Piece::Bishop) == 1 && material.count(weak, Piece::Bishop) == 1 {
+        let bishops = (
+            squares(board, strong, Piece::Bishop, strong)[0],
+            squares(board, strong, Piece::Bishop, weak)[0],
+        );
+        if dark(bishops.0) != dark(bishops.1) {
+            let only_bishops = strong_pieces == VALUES[2] && weak_pieces == VALUES[2];
+            return if only_bishops {
+                SCALE_BISHOPS
+            } else {
+                SCALE_BISHOPS_PIECES
+            };
+        }
+    }
+
+    // two knights, or bishops all on one color, cannot mate a king left
+    // without pawns
+    let knights = material.count(strong, Piece::Knight);
+    let bishops = material.count(strong, Piece::Bishop);
+    let only_knights = material.only(strong, &[(Piece::Knight, knights)]) && knights <= 2;
+    let only_bishops =
+        material.only(strong, &[(Piece::Bishop, bishops)]) && !material.bishop_pair(strong);
+    if pawns == 0 && material.count(weak, Piece::Pawn) == 0 && (only_knights || only_bishops) {
+        return 0;
+    }
+
+    // without pawns a minor piece more is not enough
+    if pawns == 0 && strong_pieces - weak_pieces <= VALUES[2] {
+        return if strong_pieces < VALUES[3] {
+            0
+        } else if weak_pieces <= VALUES[2] {
+            SCALE_MINOR_UP
+        } else {
+            SCALE_MINOR_UP_PIECES
+        };
+    }
+    SCALE_NORMAL
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::evaluate;
+
+    fn eval(fen: &str) -> i32 {
+        let board = Board::from_fen(fen.into()).unwrap();
+        // the same from the other side
+        assert_eq!(evaluate(&board), evaluate(&board.mirror()), "{}", fen);
+        evaluate(&board)
+    }
+
+    #[test]
+    fn kpk() {
+        // the opposition decides
+        assert_eq!(eval("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1"), 0);
+        assert!(eval("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1") < -KNOWN_WIN);
+        // the rook pawn draws
+        assert_eq!(eval("k7/8/K7/P7/8/8/8/8 w - - 0 1"), 0);
+        // the king is too far from the pawn
+        assert!(eval("8/8/8/8/k7/8/6P1/6K1 w - - 0 1") > KNOWN_WIN);
+        // the king in front keeps the opposition
+        assert_eq!(eval("8/8/4k3/8/4K3/4P3/8/8 w - - 0 1"), 0);
+        assert!(eval("8/8/4k3/8/4K3/4P3/8/8 b - - 0 1") < -KNOWN_WIN);
+    }
+
+    #[test]
+    fn mating_nets() {
+        let kqk = eval("8/8/8/3k4/8/8/8/Q3K3 w - - 0 1");
+        assert!(kqk > KNOWN_WIN);
+        // better with the king at the edge, the kings close
+        assert!(eval("3k4/8/3K4/8/8/8/8/Q7 w - - 0 1") > kqk);
+        assert!(eval("3k4/8/3K4/8/8/8/8/R7 w - - 0 1") > eval("8/8/8/3k4/8/3K4/8/R7 w - - 0 1"));
+        // stalemate
+        assert_eq!(eval("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"), 0);
+
+        // the bishop covers a1 and h8, not a8
+        let right = eval("8/8/8/8/8/1K6/8/k1B1N3 w - - 0 1");
+        let wrong = eval("k1N5/8/1K6/8/8/8/8/2B5 w - - 0 1");
+        assert!(right > wrong && wrong > KNOWN_WIN, "{} {}", right, wrong);
+        assert_eq!(
+            Endgame::find(&Material::new(
+                &Board::from_fen("8/8/8/8/8/1K6/8/k1B1N3 w - - 0 1".into()).unwrap()
+            )),
+            Some(Endgame::Kbnk(Color::White))
+        );
+    }
+
+    #[test]
+    fn scale_factors() {
+        let scale = |fen: &str, strong| {
+            let board = Board::from_fen(fen.to_owned()).unwrap();
+            scale_factor(&board, &Material::new(&board), strong)
+        };
+        // the h8 square is dark, the bishop light
+        assert_eq!(scale("7k/8/7P/8/8/8/8/3BK3 w - - 0 1", Color::White), 0);
+        assert_eq!(
+            scale("7k/8/7P/8/8/8/8/2B1K3 w - - 0 1", Color::White),
+            SCALE_NORMAL
+        );
+        assert_eq!(scale("k7/8/8/8/7p/8/6K1/4b3 w - - 0 1", Color::Black), 0);
+        // opposite colored bishops
+        assert_eq!(
+            scale("4k3/5b2/8/8/3P4/2P5/4B3/4K3 w - - 0 1", Color::White),
+            SCALE_NORMAL
+        );
+        assert_eq!(
+            scale("4k3/4b3/8/8/3P4/2P5/4B3/4K3 w - - 0 1", Color::White),
+            SCALE_BISHOPS
+        );
+        // rook against bishop, a knight alone
+        assert_eq!(
+            scale("4k3/8/8/8/8/2b5/8/R3K3 w - - 0 1", Color::White),
+            SCALE_MINOR_UP
+        );
+        assert_eq!(scale("4k3/8/8/p7/8/8/8/N3K3 w - - 0 1", Color::White), 0);
+        assert!(eval("4k3/8/8/p7/8/8/8/N3K3 w - - 0 1").abs() < 20);
+    }
+
+    #[test]
+    fn insufficient_material() {
+        // two knights, and two bishops on dark squares
+        assert_eq!(eval("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1"), 0);
+        assert_eq!(eval("4k3/8/8/8/8/8/8/2B1K1B1 w - - 0 1"), 0);
+        let kbbk = Board::from_fen("4k3/8/8/8/8/8/8/2B1K1B1 w - - 0 1".into()).unwrap();
+        assert_eq!(Endgame::find(&Material::new(&kbbk)), None);
+        // the bishop pair mates
+        let kbbk = Board::from_fen("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1".into()).unwrap();
+        assert_eq!(
+            Endgame::find(&Material::new(&kbbk)),
+            Some(Endgame::Kxk(Color::White))
+        );
+    }
+}
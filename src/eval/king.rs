@@ -0,0 +1,126 @@
+use super::{Params, Score};
+use crate::{movegen::Attacks, AttackMap, Board, Color, Piece, Square};
+
+// king safety
+// enemy attacks on the squares around the king, open files next to it and
+// missing pawn cover are added up to a danger value which is then turned into
+// a penalty growing quadratically, a single attacker is rarely dangerous
+// while several pieces aiming at an open king usually are
+
+// danger per attacked king zone square, indexed by piece - 1
+pub const ATTACK_WEIGHTS: [i32; 6] = [1, 2, 2, 3, 5, 0];
+pub const OPEN_FILE_DANGER: i32 = 4;
+pub const SEMI_OPEN_FILE_DANGER: i32 = 2;
+pub const MISSING_SHELTER_DANGER: i32 = 2;
+pub const MAX_DANGER_PENALTY: i32 = 600;
+
+// king safety breakdown for one color
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct KingSafety {
+    // number of enemy pieces attacking the king zone
+    pub attackers: i32,
+    // attacked king zone squares by enemy piece type, indexed by piece - 1
+    pub zone_attacks: [i32; 6],
+    // files next to the king without any pawns or without own pawns
+    pub open_files: i32,
+    pub semi_open_files: i32,
+    // files next to the king with no own pawn on the two squares in front
+    pub missing_shelter: i32,
+    pub danger: i32,
+    pub score: Score,
+}
+
+impl KingSafety {
+    pub fn new(
+        board: &Board,
+        params: &Params,
+        enemy_attacks: &AttackMap,
+        color: Color,
+    ) -> KingSafety {
+        let mut safety = KingSafety::default();
+        let Some(king) = board.king_square(color) else {
+            return safety;
+        };
+        let zone = king_zone(king, color);
+        let enemy = color.opposite();
+
+        for piece in [
+            Piece::Pawn,
+            Piece::Knight,
+            Piece::Bishop,
+            Piece::Rook,
+            Piece::Queen,
+        ] {
+            let attacks = (enemy_attacks.attacked_by(piece) & zone).count_ones() as i32;
+            safety.zone_attacks[piece as usize - 1] = attacks;
+            safety.danger += params.attack_weights[piece as usize - 1] * attacks;
+        }
+        safety.attackers = board
+            .pieces()
+            .filter(|(_, p)| p.get_color() == enemy && !p.is_king() && !p.is_pawn())
+            .filter(|&(sq, _)| Attacks::piece(board, sq) & zone != 0)
+            .count() as i32;
+
+        let king = u8::from(king);
+        let (kf, kr) = (king % 8, king / 8);
+        for file in kf.saturating_sub(1)..=(kf + 1).min(7) {
+            let pawns = |c: Color| {
+                board
+                    .pieces()
+                    .filter(move |(sq, p)| {
+                        p.is_pawn() && p.get_color() == c && u8::from(*sq) % 8 == file
+                    })
+                    .map(|(sq, _)| u8::from(sq) / 8)
+                    .collect::<Vec<u8>>()
+            };
+            let (own, theirs) = (pawns(color), pawns(enemy));
+            if own.is_empty() {
+                if theirs.is_empty() {
+                    safety.open_files += 1;
+                } else {
+                    safety.semi_open_files += 1;
+                }
+            }
+            let sheltered = own.iter().any(|&r| match color {
+                Color::White => r > kr && r <= kr + 2,
+                Color::Black => r < kr && r + 2 >= kr,
+            });
+            if !sheltered {
+                safety.missing_shelter += 1;
+            }
+        }
+        safety.danger += params.open_file_danger * safety.open_files
+            + params.semi_open_file_danger * safety.semi_open_files
+            + params.missing_shelter_danger * safety.missing_shelter;
+
+        // a lone attacker cannot do much, without the queen even less
+        if safety.attackers < 2 {
+            safety.danger /= 2;
+        }
+        let has_queen = board
+            .pieces()
+            .any(|(_, p)| p.is_queen() && p.get_color() == enemy);
+        let mut penalty = (safety.danger * safety.danger / 8).min(params.max_danger_penalty);
+        if !has_queen {
+            penalty /= 2;
+        }
+        safety.score = Score::new(-penalty, -safety.danger);
+        safety
+    }
+}
+
+// squares around the king plus the squares two ranks in front of it
+pub fn king_zone(king: Square, color: Color) -> u64 {
+    let king = u8::from(king) as i8;
+    let (kf, kr) = (king % 8, king / 8);
+    let forward = if color == Color::White { 1 } else { -1 };
+    let mut zone = 0;
+    for file in kf - 1..=kf + 1 {
+        for rank in [kr - forward, kr, kr + forward, kr + 2 * forward] {
+            if (0..8).contains(&file) && (0..8).contains(&rank) {
+                zone |= 1 << (rank * 8 + file);
+            }
+        }
+    }
+    zone
+}
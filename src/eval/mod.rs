@@ -0,0 +1,433 @@
+mod score;
+pub use self::score::*;
+
+mod psqt;
+pub use self::psqt::*;
+
+mod pawns;
+pub use self::pawns::*;
+
+mod king;
+pub use self::king::*;
+
+mod trace;
+pub use self::trace::*;
+
+mod bitbase;
+pub use self::bitbase::*;
+
+mod endgame;
+pub use self::endgame::*;
+
+mod nnue;
+pub use self::nnue::*;
+
+mod params;
+pub use self::params::*;
+
+use std::sync::Arc;
+
+use crate::{movegen::Raw, Board, Color, Move, Piece};
+
+// hand-crafted evaluation
+// every term is computed separately for each color as a (midgame, endgame)
+// pair, the difference is then tapered by the game phase
+
+// phase is the amount of non-pawn material left on the board
+pub const MAX_PHASE: i32 = 24;
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+
+pub const BISHOP_PAIR: Score = Score::new(30, 50);
+pub const ROOK_OPEN_FILE: Score = Score::new(40, 15);
+pub const ROOK_SEMI_OPEN_FILE: Score = Score::new(20, 10);
+// bonus for the side to move
+pub const TEMPO: Score = Score::new(20, 10);
+
+// bonus for every square a piece can move to, indexed by piece
+const MOBILITY: [Score; 6] = [
+    Score::ZERO,
+    Score::new(4, 4),
+    Score::new(5, 5),
+    Score::new(2, 4),
+    Score::new(1, 2),
+    Score::ZERO,
+];
+
+pub fn phase(board: &Board) -> i32 {
+    let phase = board
+        .pieces()
+        .map(|(_, p)| PHASE_WEIGHTS[p.get_piece() as usize - 1])
+        .sum::<i32>();
+    // early promotions can push the phase over the maximum
+    phase.min(MAX_PHASE)
+}
+
+// evaluator keeping caches between calls, used by the search
+#[derive(Default)]
+pub struct Evaluator {
+    pub pawns: PawnTable,
+    params: Arc<Params>,
+    // without a network the hand-crafted evaluation is used
+    nnue: Option<AccumulatorStack>,
+}
+
+impl Evaluator {
+    pub fn evaluate(&mut self, board: &Board) -> i32 {
+        if let Some(nnue) = &self.nnue {
+            return evaluate_network(board, nnue);
+        }
+        let entry = self.pawns.probe(board, &self.params);
+        evaluate_with(board, &self.params, &entry)
+    }
+
+    pub fn params(&self) -> Arc<Params> {
+        self.params.clone()
+    }
+
+    pub fn set_params(&mut self, params: Arc<Params>) {
+        if !Arc::ptr_eq(&self.params, &params) {
+            // the cached pawn terms were computed with the old ones
+            self.pawns.clear();
+            self.params = params;
+        }
+    }
+
+    pub fn network(&self) -> Option<Arc<Network>> {
+        self.nnue.as_ref().map(|nnue| nnue.network().clone())
+    }
+
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        let same = match (&self.nnue, &network) {
+            (Some(nnue), Some(network)) => Arc::ptr_eq(nnue.network(), network),
+            (None, None) => true,
+            _ => false,
+        };
+        if !same {
+            self.nnue = network.map(AccumulatorStack::new);
+        }
+    }
+
+    // the search tells the network about the moves it makes, so that it
+    // can update its accumulators instead of computing them again
+    pub fn reset(&mut self, board: &Board) {
+        if let Some(nnue) = &mut self.nnue {
+            nnue.reset(board);
+        }
+    }
+
+    pub fn push(&mut self, board: &Board, mv: Move, child: &Board) {
+        if let Some(nnue) = &mut self.nnue {
+            nnue.push(board, mv, child);
+        }
+    }
+
+    pub fn push_null(&mut self, child: &Board) {
+        if let Some(nnue) = &mut self.nnue {
+            nnue.push_null(child);
+        }
+    }
+
+    pub fn pop(&mut self) {
+        if let Some(nnue) = &mut self.nnue {
+            nnue.pop();
+        }
+    }
+}
+
+// evaluate the board from the point of view of the side to move
+// without any caching, use Evaluator when evaluating many positions
+pub fn evaluate(board: &Board) -> i32 {
+    evaluate_params(board, &Params::DEFAULT)
+}
+
+// same with other weights than the default ones
+pub fn evaluate_params(board: &Board, params: &Params) -> i32 {
+    evaluate_with(board, params, &PawnEntry::new(board, params))
+}
+
+fn evaluate_with(board: &Board, params: &Params, pawns: &PawnEntry) -> i32 {
+    let mut score = evaluate_color(board, params, pawns, Color::White)
+        - evaluate_color(board, params, pawns, Color::Black);
+    match board.turn {
+        Color::White => score += params.tempo,
+        Color::Black => score -= params.tempo,
+    }
+    let score = finish(board, score);
+    match board.turn {
+        Color::White => score,
+        Color::Black => -score,
+    }
+}
+
+// the network knows nothing of the endgames the hand-crafted evaluation
+// handles, they are left to the same code
+fn evaluate_network(board: &Board, nnue: &AccumulatorStack) -> i32 {
+    let material = Material::new(board);
+    if let Some(endgame) = Endgame::find(&material) {
+        let score = endgame.evaluate(board);
+        return match board.turn {
+            Color::White => score,
+            Color::Black => -score,
+        };
+    }
+    let score = nnue.evaluate(board);
+    let strong = if score >= 0 {
+        board.turn
+    } else {
+        board.turn.opposite()
+    };
+    score * scale_factor(board, &material, strong) / SCALE_NORMAL
+}
+
+// the tapered score from white's point of view, known endgames are
+// evaluated on their own and drawish ones have the endgame part scaled down
+pub fn finish(board: &Board, score: Score) -> i32 {
+    let material = Material::new(board);
+    if let Some(endgame) = Endgame::find(&material) {
+        return endgame.evaluate(board);
+    }
+    let strong = if score.eg >= 0 {
+        Color::White
+    } else {
+        Color::Black
+    };
+    let scale = scale_factor(board, &material, strong);
+    // no winning chances at all, whatever the middlegame part says
+    if scale == 0 {
+        return 0;
+    }
+    Score::new(score.mg, score.eg * scale / SCALE_NORMAL).taper(phase(board))
+}
+
+// sum of all terms for one color
+pub fn evaluate_color(board: &Board, params: &Params, pawns: &PawnEntry, color: Color) -> Score {
+    material(board, params, color)
+        + placement(board, params, color)
+        + pieces(board, params, color)
+        + mobility(board, params, color)
+        + pawn_structure(board, params, pawns, color)
+        + king_safety(board, params, color)
+}
+
+pub fn material(board: &Board, params: &Params, color: Color) -> Score {
+    board
+        .pieces()
+        .filter(|(_, p)| p.get_color() == color)
+        .fold(Score::ZERO, |acc, (_, p)| {
+            acc + params.piece_values[p.get_piece() as usize - 1]
+        })
+}
+
+// piece-square tables
+pub fn placement(board: &Board, params: &Params, color: Color) -> Score {
+    board
+        .pieces()
+        .filter(|(_, p)| p.get_color() == color)
+        .fold(Score::ZERO, |acc, (sq, p)| {
+            acc + psqt(params, p.get_piece(), color, sq)
+        })
+}
+
+// bishop pair and rooks on open or semi-open files
+pub fn pieces(board: &Board, params: &Params, color: Color) -> Score {
+    let pawns = pawn_files(board);
+    let mut score = Score::ZERO;
+    let mut bishops = 0;
+    for (sq, p) in board.pieces().filter(|(_, p)| p.get_color() == color) {
+        match p.get_piece() {
+            Piece::Bishop => bishops += 1,
+            Piece::Rook => {
+                let file = usize::from(sq) % 8;
+                if pawns[color as usize][file] == 0 {
+                    if pawns[color.opposite() as usize][file] == 0 {
+                        score += params.rook_open_file;
+                    } else {
+                        score += params.rook_semi_open_file;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if bishops >= 2 {
+        score += params.bishop_pair;
+    }
+    score
+}
+
+// number of pseudo-legal moves of the minor and major pieces
+pub fn mobility(board: &Board, params: &Params, color: Color) -> Score {
+    let mut score = Score::ZERO;
+    for (sq, p) in board.pieces().filter(|(_, p)| p.get_color() == color) {
+        let piece = p.get_piece();
+        let moves = match piece {
+            Piece::Knight => Raw::gen_knight_raw_moves(board, sq).len(),
+            Piece::Bishop => Raw::gen_bishop_raw_moves(board, sq).len(),
+            Piece::Rook => Raw::gen_rook_raw_moves(board, sq).len(),
+            Piece::Queen => Raw::gen_queen_raw_moves(board, sq).len(),
+            _ => continue,
+        };
+        score += params.mobility[piece as usize - 1] * moves as i32;
+    }
+    score
+}
+
+pub fn pawn_structure(board: &Board, params: &Params, pawns: &PawnEntry, color: Color) -> Score {
+    pawns.total(color) + passed_free_path(board, params, pawns, color)
+}
+
+pub fn king_safety(board: &Board, params: &Params, color: Color) -> Score {
+    KingSafety::new(board, params, &board.attacks(color.opposite()), color).score
+}
+
+// number of pawns on each file, indexed by color then file
+fn pawn_files(board: &Board) -> [[u8; 8]; 2] {
+    let mut files = [[0; 8]; 2];
+    for (sq, p) in board.pieces().filter(|(_, p)| p.is_pawn()) {
+        files[p.get_color() as usize][usize::from(sq) % 8] += 1;
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITIONS: [&str; 6] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        "6k1/5ppp/8/8/8/8/5PPP/3R2K1 b - - 0 1",
+    ];
+
+    #[test]
+    fn symmetric() {
+        let params = &Params::DEFAULT;
+        for fen in POSITIONS {
+            let board = Board::from_fen(fen.into()).unwrap();
+            let mirrored = board.mirror();
+            // same position seen from the other side, same side to move score
+            assert_eq!(evaluate(&board), evaluate(&mirrored), "{}", fen);
+            assert_eq!(
+                evaluate_color(
+                    &board,
+                    params,
+                    &PawnEntry::new(&board, params),
+                    Color::White
+                ),
+                evaluate_color(
+                    &mirrored,
+                    params,
+                    &PawnEntry::new(&mirrored, params),
+                    Color::Black
+                ),
+                "{}",
+                fen
+            );
+            let (entry, mirrored_entry) = (
+                PawnEntry::new(&board, params),
+                PawnEntry::new(&mirrored, params),
+            );
+            assert_eq!(entry.terms[0], mirrored_entry.terms[1], "{}", fen);
+            assert_eq!(entry.terms[1], mirrored_entry.terms[0], "{}", fen);
+        }
+    }
+
+    #[test]
+    fn start_position_is_tempo() {
+        let board = Board::default();
+        assert_eq!(evaluate(&board), TEMPO.taper(MAX_PHASE));
+        assert_eq!(phase(&board), MAX_PHASE);
+    }
+
+    #[test]
+    fn pawn_structure_terms() {
+        // white: doubled isolated a-pawns, passed a3 and d5, isolated g5
+        // black: g6 defended by f7, backward f7 as f6 is controlled by g5
+        let board = Board::from_fen("6k1/5p2/6p1/3P2P1/8/P7/P7/6K1 w - - 0 1".into()).unwrap();
+        let entry = PawnEntry::new(&board, &Params::DEFAULT);
+        let white = entry.terms[Color::White as usize];
+        let black = entry.terms[Color::Black as usize];
+        assert_eq!(white.isolated, ISOLATED * 4);
+        assert_eq!(white.doubled, DOUBLED);
+        assert_eq!(white.passed, PASSED[2] + PASSED[4]);
+        assert_eq!(black.passed, Score::ZERO);
+        assert_eq!(black.backward, BACKWARD);
+        assert_eq!(black.connected, CONNECTED[2]);
+    }
+
+    #[test]
+    fn pawn_table_caches_entries() {
+        let params = &Params::DEFAULT;
+        let mut table = PawnTable::new(16);
+        let mut board = Board::from_fen("4k3/pppppppp/8/8/8/8/PPP5/4K3 w - - 0 1".into()).unwrap();
+        let entry = table.probe(&board, params);
+        assert_eq!(entry, PawnEntry::new(&board, params));
+        // positions with the same pawns share the entry
+        let pieces = Board::from_fen("r3k3/pppppppp/8/8/8/5N2/PPP5/4K3 w - - 0 1".into()).unwrap();
+        assert_eq!(pieces.pawn_hash(), board.pawn_hash());
+        let shared = table.probe(&pieces, params);
+        assert_eq!(shared, PawnEntry::new(&pieces, params));
+        assert_eq!(shared, entry);
+        // same pawns, the king moved: the shield is recomputed for its new square
+        board.make_move("Kd1".into()).unwrap();
+        let moved = table.probe(&board, params);
+        assert_eq!(moved, PawnEntry::new(&board, params));
+        assert_ne!(moved.terms[0].shield, entry.terms[0].shield);
+        let mut evaluator = Evaluator::default();
+        assert_eq!(evaluator.evaluate(&board), evaluate(&board));
+    }
+
+    #[test]
+    fn king_safety_counts_attacks() {
+        // black king with no shelter facing queen, rook and bishop
+        let board = Board::from_fen("6k1/8/8/8/8/1B4Q1/5R2/6K1 w - - 0 1".into()).unwrap();
+        let params = &Params::DEFAULT;
+        let safety = KingSafety::new(&board, params, &board.attacks(Color::White), Color::Black);
+        assert_eq!(safety.attackers, 3);
+        assert_eq!(safety.open_files, 3);
+        assert_eq!(safety.missing_shelter, 3);
+        assert!(safety.zone_attacks[Piece::Queen as usize - 1] > 0);
+        assert!(safety.score.mg < 0);
+
+        // castled king behind intact pawns is safe
+        let castled =
+            Board::from_fen("rnbq1rk1/pppppppp/8/8/8/8/PPPPPPPP/RNBQ1RK1 w - - 0 1".into())
+                .unwrap();
+        let safety = KingSafety::new(
+            &castled,
+            params,
+            &castled.attacks(Color::Black),
+            Color::White,
+        );
+        assert_eq!(safety.attackers, 0);
+        assert_eq!(safety.missing_shelter, 0);
+        assert!(
+            king_safety(&castled, params, Color::White).mg
+                > king_safety(&board, params, Color::Black).mg
+        );
+    }
+
+    #[test]
+    fn trace_matches_evaluate() {
+        for fen in POSITIONS {
+            let board = Board::from_fen(fen.into()).unwrap();
+            let trace = trace(&board);
+            assert_eq!(trace.eval, evaluate(&board), "{}", fen);
+            assert!(trace
+                .to_json()
+                .contains(&format!("\"eval\":{}", trace.eval)));
+        }
+    }
+
+    #[test]
+    fn material_advantage() {
+        // white is a rook up, so white to move is winning and black to move is losing
+        let white = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1".into()).unwrap();
+        let black = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/3R2K1 b - - 0 1".into()).unwrap();
+        assert!(evaluate(&white) > 300);
+        assert!(evaluate(&black) < -300);
+    }
+}
@@ -0,0 +1,467 @@
+use std::{fs, path::Path, sync::Arc};
+
+use crate::{Board, Color, Move, NetworkError, Piece, Square};
+
+use super::KNOWN_WIN;
+
+// efficiently updatable neural network evaluation
+//
+// the inputs are one feature for every piece of a color on a square, seen
+// from both sides: from black's point of view the board is flipped
+// vertically and the colors are swapped, so both halves use the same
+// weights. every perspective has its own accumulator, the sum of the
+// weights of its active features, which only changes by a few rows when a
+// move is made. the output layer sees the accumulator of the side to move
+// and then the other one, both clipped to 0..=QA
+//
+// network files are little endian:
+//
+//   bytes       contents
+//   4           magic "CNUE"
+//   4           version, u32, currently 1
+//   4           hidden size H, u32, a multiple of 16
+//   4           scale, i32, centipawns of an output of QA * QB
+//   768 * H * 2 feature weights, i16, the H weights of feature 0 first
+//   H * 2       feature biases, i16
+//   2 * H       output weights, i8, the side to move's H first
+//   4           output bias, i32
+//
+// feature of a piece for a perspective:
+//   (relative color * 6 + piece - 1) * 64 + relative square
+// where the relative color is 0 for the perspective's own pieces and the
+// relative square is a1 = 0 to h8 = 63, flipped vertically for black
+
+pub const INPUTS: usize = 768;
+// activations of the hidden layer are clipped to this
+pub const QA: i32 = 255;
+// output weights are quantized with this factor
+pub const QB: i32 = 64;
+
+const MAGIC: &[u8] = b"CNUE";
+const VERSION: u32 = 1;
+// loops over the hidden layer work on this many values at a time, so the
+// compiler can turn them into vector instructions
+const LANES: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Network {
+    hidden: usize,
+    scale: i32,
+    feature_weights: Vec<i16>,
+    feature_biases: Vec<i16>,
+    output_weights: Vec<i8>,
+    output_bias: i32,
+}
+
+impl Network {
+    pub fn new(
+        hidden: usize,
+        scale: i32,
+        feature_weights: Vec<i16>,
+        feature_biases: Vec<i16>,
+        output_weights: Vec<i8>,
+        output_bias: i32,
+    ) -> Option<Network> {
+        let valid = hidden > 0
+            && hidden.is_multiple_of(LANES)
+            && feature_weights.len() == INPUTS * hidden
+            && feature_biases.len() == hidden
+            && output_weights.len() == 2 * hidden;
+        valid.then_some(Network {
+            hidden,
+            scale,
+            feature_weights,
+            feature_biases,
+            output_weights,
+            output_bias,
+        })
+    }
+
+    pub fn hidden(&self) -> usize {
+        self.hidden
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Arc<Network>, NetworkError> {
+        Network::read(path).map(Arc::new)
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Network, NetworkError> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)?;
+        Network::parse(&bytes).ok_or_else(|| NetworkError::InvalidFile(path.display().to_string()))
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), NetworkError> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend((self.hidden as u32).to_le_bytes());
+        bytes.extend(self.scale.to_le_bytes());
+        for w in self.feature_weights.iter().chain(&self.feature_biases) {
+            bytes.extend(w.to_le_bytes());
+        }
+        bytes.extend(self.output_weights.iter().map(|&w| w as u8));
+        bytes.extend(self.output_bias.to_le_bytes());
+        fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    fn parse(bytes: &[u8]) -> Option<Network> {
+        let mut rest = bytes.strip_prefix(MAGIC)?;
+        let mut take = |n: usize| {
+            let (head, tail) = rest.split_at_checked(n)?;
+            rest = tail;
+            Some(head)
+        };
+        let mut word = || take(4).map(|b| [b[0], b[1], b[2], b[3]]);
+        if u32::from_le_bytes(word()?) != VERSION {
+            return None;
+        }
+        let hidden = u32::from_le_bytes(word()?) as usize;
+        let scale = i32::from_le_bytes(word()?);
+        let mut int16 = |n: usize| {
+            let bytes = take(n.checked_mul(2)?)?;
+            Some(
+                bytes
+                    .chunks_exact(2)
+                    .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let feature_weights = int16(INPUTS.checked_mul(hidden)?)?;
+        let feature_biases = int16(hidden)?;
+        let output_weights = take(hidden.checked_mul(2)?)?
+            .iter()
+            .map(|&b| b as i8)
+            .collect();
+        let output_bias = i32::from_le_bytes(take(4)?.try_into().ok()?);
+        if !rest.is_empty() {
+            return None;
+        }
+        Network::new(
+            hidden,
+            scale,
+            feature_weights,
+            feature_biases,
+            output_weights,
+            output_bias,
+        )
+    }
+
+    // evaluate the board from the point of view of the side to move,
+    // computing the accumulators from scratch
+    pub fn evaluate(&self, board: &Board) -> i32 {
+        let mut accumulator = vec![0; 2 * self.hidden];
+        self.refresh(board, &mut accumulator);
+        self.output(&accumulator, board.turn)
+    }
+
+    fn row(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden]
+    }
+
+    // the accumulators of both perspectives, white's first
+    fn refresh(&self, board: &Board, accumulator: &mut [i16]) {
+        let (white, black) = accumulator.split_at_mut(self.hidden);
+        white.copy_from_slice(&self.feature_biases);
+        black.copy_from_slice(&self.feature_biases);
+        for (sq, p) in board.pieces() {
+            let (color, piece) = (p.get_color(), p.get_piece());
+            add(white, self.row(feature(Color::White, color, piece, sq)));
+            add(black, self.row(feature(Color::Black, color, piece, sq)));
+        }
+    }
+
+    // turn the feature of a piece on or off in both accumulators
+    fn toggle(
+        &self,
+        accumulator: &mut [i16],
+        (color, piece, sq): (Color, Piece, Square),
+        on: bool,
+    ) {
+        let (white, black) = accumulator.split_at_mut(self.hidden);
+        let rows = [
+            self.row(feature(Color::White, color, piece, sq)),
+            self.row(feature(Color::Black, color, piece, sq)),
+        ];
+        for (half, row) in [white, black].into_iter().zip(rows) {
+            if on {
+                add(half, row);
+            } else {
+                sub(half, row);
+            }
+        }
+    }
+
+    // change the accumulators of the board into those of the board after
+    // the move
+    fn update(&self, accumulator: &mut [i16], board: &Board, mv: Move) {
+        let piece = board[mv.from];
+        let (color, kind) = (piece.get_color(), piece.get_piece());
+        let captured = board[mv.to];
+        self.toggle(accumulator, (color, kind, mv.from), false);
+        if !captured.is_blank() {
+            let captured = (captured.get_color(), captured.get_piece(), mv.to);
+            self.toggle(accumulator, captured, false);
+        } else if kind == Piece::Pawn && Some(mv.to) == board.en_passant {
+            // the captured pawn is beside the moving one
+            let behind = (u8::from(mv.from) & !7) | (u8::from(mv.to) & 7);
+            let behind = Square::try_from(behind).unwrap();
+            self.toggle(accumulator, (color.opposite(), Piece::Pawn, behind), false);
+        }
+        if kind == Piece::King && (u8::from(mv.from) % 8).abs_diff(u8::from(mv.to) % 8) == 2 {
+            let (rook_from, rook_to) = if mv.to.file() == 'g' {
+                (mv.to.right(1), mv.to.left(1))
+            } else {
+                (mv.to.left(2), mv.to.right(1))
+            };
+            self.toggle(accumulator, (color, Piece::Rook, rook_from.unwrap()), false);
+            self.toggle(accumulator, (color, Piece::Rook, rook_to.unwrap()), true);
+        }
+        let kind = mv.promotion.unwrap_or(kind);
+        self.toggle(accumulator, (color, kind, mv.to), true);
+    }
+
+    fn output(&self, accumulator: &[i16], turn: Color) -> i32 {
+        let (white, black) = accumulator.split_at(self.hidden);
+        let (us, them) = match turn {
+            Color::White => (white, black),
+            Color::Black => (black, white),
+        };
+        let (ours, theirs) = self.output_weights.split_at(self.hidden);
+        let sum = self.output_bias as i64 + dot(us, ours) + dot(them, theirs);
+        let score = sum * self.scale as i64 / (QA * QB) as i64;
+        // keep known wins for the endgame code and the tablebases
+        score.clamp(1 - KNOWN_WIN as i64, KNOWN_WIN as i64 - 1) as i32
+    }
+}
+
+fn feature(perspective: Color, color: Color, piece: Piece, square: Square) -> usize {
+    let (relative, square) = match perspective {
+        Color::White => (color as usize, usize::from(square)),
+        Color::Black => (color.opposite() as usize, usize::from(square) ^ 56),
+    };
+    (relative * 6 + piece as usize - 1) * 64 + square
+}
+
+fn add(accumulator: &mut [i16], row: &[i16]) {
+    for (a, r) in accumulator
+        .chunks_exact_mut(LANES)
+        .zip(row.chunks_exact(LANES))
+    {
+        for i in 0..LANES {
+            a[i] = a[i].wrapping_add(r[i]);
+        }
+    }
+}
+
+fn sub(accumulator: &mut [i16], row: &[i16]) {
+    for (a, r) in accumulator
+        .chunks_exact_mut(LANES)
+        .zip(row.chunks_exact(LANES))
+    {
+        for i in 0..LANES {
+            a[i] = a[i].wrapping_sub(r[i]);
+        }
+    }
+}
+
+// clipped relu of the accumulator times the output weights
+fn dot(accumulator: &[i16], weights: &[i8]) -> i64 {
+    let mut sums = [0i32; LANES];
+    for (a, w) in accumulator
+        .chunks_exact(LANES)
+        .zip(weights.chunks_exact(LANES))
+    {
+        for i in 0..LANES {
+            sums[i] += (a[i] as i32).clamp(0, QA) * w[i] as i32;
+        }
+    }
+    sums.iter().map(|&s| s as i64).sum()
+}
+
+// accumulators of the positions on the current search path, the search
+// pushes one for every move it makes and pops it when taking the move back
+#[derive(Debug, Clone)]
+pub struct AccumulatorStack {
+    network: Arc<Network>,
+    accumulators: Vec<Vec<i16>>,
+    // accumulators in use, with none every position is evaluated from scratch
+    len: usize,
+}
+
+impl AccumulatorStack {
+    pub fn new(network: Arc<Network>) -> AccumulatorStack {
+        AccumulatorStack {
+            network,
+            accumulators: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn network(&self) -> &Arc<Network> {
+        &self.network
+    }
+
+    // start again from the root of a search
+    pub fn reset(&mut self, board: &Board) {
+        self.len = 0;
+        let network = self.network.clone();
+        network.refresh(board, self.next());
+    }
+
+    // the board is the position before the move and child the one after it
+    pub fn push(&mut self, board: &Board, mv: Move, child: &Board) {
+        let network = self.network.clone();
+        match self.len {
+            0 => network.refresh(child, self.next()),
+            len => {
+                let (done, next) = self.split();
+                next.copy_from_slice(&done[len - 1]);
+                network.update(next, board, mv);
+            }
+        }
+    }
+
+    // passing the turn changes no feature
+    pub fn push_null(&mut self, child: &Board) {
+        let network = self.network.clone();
+        match self.len {
+            0 => network.refresh(child, self.next()),
+            len => {
+                let (done, next) = self.split();
+                next.copy_from_slice(&done[len - 1]);
+            }
+        }
+    }
+
+    pub fn pop(&mut self) {
+        self.len -= 1;
+    }
+
+    pub fn evaluate(&self, board: &Board) -> i32 {
+        match self.len {
+            0 => self.network.evaluate(board),
+            len => self.network.output(&self.accumulators[len - 1], board.turn),
+        }
+    }
+
+    // the accumulators in use and the next one, now in use too
+    fn split(&mut self) -> (&[Vec<i16>], &mut [i16]) {
+        if self.accumulators.len() == self.len {
+            self.accumulators.push(vec![0; 2 * self.network.hidden]);
+        }
+        self.len += 1;
+        let (done, rest) = self.accumulators.split_at_mut(self.len - 1);
+        (done, &mut rest[0])
+    }
+
+    fn next(&mut self) -> &mut [i16] {
+        self.split().1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        eval::{evaluate, Evaluator},
+        search::Rng,
+    };
+
+    fn random_network(hidden: usize, seed: u64) -> Network {
+        let mut rng = Rng::new(seed);
+        let mut values = |n: usize, range: u64| -> Vec<i64> {
+            (0..n)
+                .map(|_| (rng.next_u64() % (2 * range + 1)) as i64 - range as i64)
+                .collect()
+        };
+        Network::new(
+            hidden,
+            400,
+            values(INPUTS * hidden, 40)
+                .into_iter()
+                .map(|v| v as i16)
+                .collect(),
+            values(hidden, 100).into_iter().map(|v| v as i16).collect(),
+            values(2 * hidden, 60)
+                .into_iter()
+                .map(|v| v as i8)
+                .collect(),
+            values(1, 1000)[0] as i32,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn write_and_read() {
+        let network = random_network(32, 1);
+        let path = std::env::temp_dir().join(format!("nnue-{}.bin", std::process::id()));
+        network.write(&path).unwrap();
+        let read = Network::read(&path).unwrap();
+        assert_eq!(read, network);
+
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(matches!(
+            Network::read(&path),
+            Err(NetworkError::InvalidFile(_))
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn incremental_updates() {
+        let network = Arc::new(random_network(16, 2));
+        // castling both ways, en passant, captures and a promotion
+        let board =
+            Board::from_fen("r3k2r/1P3ppp/8/3pP3/8/8/5PPP/R3K2R w KQkq d6 0 1".to_owned()).unwrap();
+        let mut stack = AccumulatorStack::new(network.clone());
+        stack.reset(&board);
+        let mut line = vec![board.clone_position()];
+        for mv in ["e5d6", "e8g8", "b7a8q", "f8a8", "e1c1", "a8a2"] {
+            let board = line.last().unwrap();
+            let mv = *board
+                .legal_moves()
+                .iter()
+                .find(|m| m.to_string() == mv)
+                .unwrap();
+            let mut child = board.clone_position();
+            child.do_move(mv);
+            stack.push(board, mv, &child);
+            assert_eq!(stack.evaluate(&child), network.evaluate(&child), "{}", mv);
+            line.push(child);
+        }
+        let mut child = line.last().unwrap().clone_position();
+        child.do_null_move();
+        stack.push_null(&child);
+        assert_eq!(stack.evaluate(&child), network.evaluate(&child));
+        // both sides see the board the same way
+        assert_eq!(network.evaluate(&child), network.evaluate(&child.mirror()));
+        stack.pop();
+        for board in line.iter().rev().skip(1) {
+            stack.pop();
+            assert_eq!(stack.evaluate(board), network.evaluate(board));
+        }
+    }
+
+    #[test]
+    fn falls_back_to_hand_crafted() {
+        let board = Board::default();
+        let mut evaluator = Evaluator::default();
+        assert_eq!(evaluator.evaluate(&board), evaluate(&board));
+        let network = Arc::new(random_network(16, 3));
+        evaluator.set_network(Some(network.clone()));
+        assert_eq!(evaluator.evaluate(&board), network.evaluate(&board));
+        // known endgames and drawn material are not left to the network
+        for fen in [
+            "8/4k3/8/4K3/4P3/8/8/8 w - - 0 1",
+            "8/8/8/3k4/8/8/8/Q3K3 b - - 0 1",
+            "4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1",
+        ] {
+            let board = Board::from_fen(fen.to_owned()).unwrap();
+            assert_eq!(evaluator.evaluate(&board), evaluate(&board), "{}", fen);
+        }
+        evaluator.set_network(None);
+        assert_eq!(evaluator.evaluate(&board), evaluate(&board));
+    }
+}
@@ -0,0 +1,244 @@
+use std::{fmt, fs, path::Path};
+
+use super::*;
+use crate::ParamsError;
+
+// the weights of the hand-crafted evaluation
+// the defaults are the constants of the other eval modules, a tuned set can
+// be written to and read from a text file with one line per parameter:
+// its name and all of its values, mg then eg for scores. arrays indexed by
+// piece or rank list one score per entry and the piece-square tables list
+// their 64 squares in the layout of psqt.rs, from a8 to h1
+// lines starting with # are comments and parameters left out keep their
+// default value
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Params {
+    pub piece_values: [Score; 6],
+    pub psqt: [[Score; 64]; 6],
+    pub mobility: [Score; 6],
+    pub bishop_pair: Score,
+    pub rook_open_file: Score,
+    pub rook_semi_open_file: Score,
+    pub tempo: Score,
+    pub isolated: Score,
+    pub doubled: Score,
+    pub backward: Score,
+    pub passed: [Score; 8],
+    pub passed_free_path: [Score; 8],
+    pub connected: [Score; 8],
+    pub shield: [Score; 8],
+    pub storm: [Score; 8],
+    pub attack_weights: [i32; 6],
+    pub open_file_danger: i32,
+    pub semi_open_file_danger: i32,
+    pub missing_shelter_danger: i32,
+    pub max_danger_penalty: i32,
+}
+
+impl Default for Params {
+    fn default() -> Params {
+        Params::DEFAULT
+    }
+}
+
+const PSQT_NAMES: [&str; 6] = [
+    "psqt_pawn",
+    "psqt_knight",
+    "psqt_bishop",
+    "psqt_rook",
+    "psqt_queen",
+    "psqt_king",
+];
+
+impl Params {
+    pub const DEFAULT: Params = Params {
+        piece_values: PIECE_VALUES,
+        psqt: PSQT,
+        mobility: MOBILITY,
+        bishop_pair: BISHOP_PAIR,
+        rook_open_file: ROOK_OPEN_FILE,
+        rook_semi_open_file: ROOK_SEMI_OPEN_FILE,
+        tempo: TEMPO,
+        isolated: ISOLATED,
+        doubled: DOUBLED,
+        backward: BACKWARD,
+        passed: PASSED,
+        passed_free_path: PASSED_FREE_PATH,
+        connected: CONNECTED,
+        shield: SHIELD,
+        storm: STORM,
+        attack_weights: ATTACK_WEIGHTS,
+        open_file_danger: OPEN_FILE_DANGER,
+        semi_open_file_danger: SEMI_OPEN_FILE_DANGER,
+        missing_shelter_danger: MISSING_SHELTER_DANGER,
+        max_danger_penalty: MAX_DANGER_PENALTY,
+    };
+
+    // every parameter by name, with its values in file order
+    pub fn named_values(&mut self) -> Vec<(&'static str, Vec<&mut i32>)> {
+        fn scores<'a>(scores: impl IntoIterator<Item = &'a mut Score>) -> Vec<&'a mut i32> {
+            scores
+                .into_iter()
+                .flat_map(|s| [&mut s.mg, &mut s.eg])
+                .collect()
+        }
+        let Params {
+            piece_values,
+            psqt,
+            mobility,
+            bishop_pair,
+            rook_open_file,
+            rook_semi_open_file,
+            tempo,
+            isolated,
+            doubled,
+            backward,
+            passed,
+            passed_free_path,
+            connected,
+            shield,
+            storm,
+            attack_weights,
+            open_file_danger,
+            semi_open_file_danger,
+            missing_shelter_danger,
+            max_danger_penalty,
+        } = self;
+        let mut values = vec![("piece_values", scores(piece_values))];
+        values.extend(PSQT_NAMES.into_iter().zip(psqt.iter_mut().map(scores)));
+        values.extend([
+            ("mobility", scores(mobility)),
+            ("bishop_pair", scores([bishop_pair])),
+            ("rook_open_file", scores([rook_open_file])),
+            ("rook_semi_open_file", scores([rook_semi_open_file])),
+            ("tempo", scores([tempo])),
+            ("isolated", scores([isolated])),
+            ("doubled", scores([doubled])),
+            ("backward", scores([backward])),
+            ("passed", scores(passed)),
+            ("passed_free_path", scores(passed_free_path)),
+            ("connected", scores(connected)),
+            ("shield", scores(shield)),
+            ("storm", scores(storm)),
+            ("attack_weights", attack_weights.iter_mut().collect()),
+            ("open_file_danger", vec![open_file_danger]),
+            ("semi_open_file_danger", vec![semi_open_file_danger]),
+            ("missing_shelter_danger", vec![missing_shelter_danger]),
+            ("max_danger_penalty", vec![max_danger_penalty]),
+        ]);
+        values
+    }
+
+    // all values in file order, for tuning
+    pub fn values(&self) -> Vec<i32> {
+        let mut params = self.clone();
+        let values = params.named_values();
+        values
+            .into_iter()
+            .flat_map(|(_, v)| v)
+            .map(|v| *v)
+            .collect()
+    }
+
+    pub fn set_values(&mut self, values: &[i32]) {
+        let named = self.named_values();
+        for (param, &value) in named.into_iter().flat_map(|(_, v)| v).zip(values) {
+            *param = value;
+        }
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Params, ParamsError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), ParamsError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Params {
+    type Err = ParamsError;
+
+    fn from_str(text: &str) -> Result<Params, ParamsError> {
+        let mut params = Params::DEFAULT;
+        for (number, line) in text.lines().enumerate() {
+            let invalid = |message: String| ParamsError::InvalidLine(number + 1, message);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let name = words.next().unwrap_or_default();
+            let values = words
+                .map(|w| w.parse::<i32>())
+                .collect::<Result<Vec<i32>, _>>()
+                .map_err(|e| invalid(e.to_string()))?;
+            let mut named = params.named_values();
+            let (_, params) = named
+                .iter_mut()
+                .find(|(n, _)| *n == name)
+                .ok_or_else(|| invalid(format!("unknown parameter {}", name)))?;
+            if params.len() != values.len() {
+                return Err(invalid(format!(
+                    "{} takes {} values, not {}",
+                    name,
+                    params.len(),
+                    values.len()
+                )));
+            }
+            for (param, value) in params.iter_mut().zip(values) {
+                **param = value;
+            }
+        }
+        Ok(params)
+    }
+}
+
+impl fmt::Display for Params {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# evaluation parameters, mg and eg for every score")?;
+        for (name, values) in self.clone().named_values() {
+            write!(f, "{}", name)?;
+            for value in values {
+                write!(f, " {}", value)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_and_read() {
+        let mut params = Params::DEFAULT;
+        params.tempo = Score::new(25, 5);
+        params.psqt[Piece::King as usize - 1][63] = Score::new(-7, 9);
+        params.max_danger_penalty = 500;
+        assert_eq!(params.to_string().parse::<Params>().unwrap(), params);
+
+        let mut values = params.values();
+        values[0] += 1;
+        params.set_values(&values);
+        assert_eq!(params.piece_values[0], Score::new(83, 94));
+        assert_eq!(params.values(), values);
+
+        // parameters left out keep their defaults
+        let params = "# tuned\ntempo 1 2\n".parse::<Params>().unwrap();
+        assert_eq!(params.tempo, Score::new(1, 2));
+        assert_eq!(params.passed, PASSED);
+        assert!(matches!(
+            "tempo 1".parse::<Params>(),
+            Err(ParamsError::InvalidLine(1, _))
+        ));
+        assert!(matches!(
+            "\nfoo 1 2".parse::<Params>(),
+            Err(ParamsError::InvalidLine(2, _))
+        ));
+    }
+}
@@ -0,0 +1,331 @@
+use super::{Params, Score};
+use crate::{Board, Color, Piece, Square};
+
+// pawn structure terms
+// everything here only depends on the position of the pawns, so results are
+// cached in a table keyed by the pawn zobrist hash
+// shield and storm also depend on where the kings are, they are cached in the
+// same entry together with the king squares they were computed for
+
+pub const ISOLATED: Score = Score::new(-5, -15);
+pub const DOUBLED: Score = Score::new(-10, -25);
+pub const BACKWARD: Score = Score::new(-8, -12);
+
+// indexed by rank relative to the pawn's color
+pub const PASSED: [Score; 8] = [
+    Score::new(0, 0),
+    Score::new(5, 10),
+    Score::new(10, 15),
+    Score::new(15, 25),
+    Score::new(30, 50),
+    Score::new(50, 90),
+    Score::new(80, 140),
+    Score::new(0, 0),
+];
+
+// extra bonus for a passed pawn that has nothing in front of it
+pub const PASSED_FREE_PATH: [Score; 8] = [
+    Score::new(0, 0),
+    Score::new(0, 2),
+    Score::new(2, 5),
+    Score::new(5, 10),
+    Score::new(10, 25),
+    Score::new(20, 45),
+    Score::new(35, 80),
+    Score::new(0, 0),
+];
+
+pub const CONNECTED: [Score; 8] = [
+    Score::new(0, 0),
+    Score::new(3, 0),
+    Score::new(6, 3),
+    Score::new(10, 6),
+    Score::new(18, 14),
+    Score::new(30, 28),
+    Score::new(55, 55),
+    Score::new(0, 0),
+];
+
+// indexed by the distance between the king and the closest pawn in front of
+// it on the king file or an adjacent one, 0 means there is no such pawn
+pub const SHIELD: [Score; 8] = [
+    Score::new(-25, 0),
+    Score::new(15, 0),
+    Score::new(8, 0),
+    Score::new(0, 0),
+    Score::new(-5, 0),
+    Score::new(-10, 0),
+    Score::new(-15, 0),
+    Score::new(-20, 0),
+];
+
+pub const STORM: [Score; 8] = [
+    Score::new(0, 0),
+    // an enemy pawn right in front of the king is blocked and harmless
+    Score::new(-5, 0),
+    Score::new(-30, 0),
+    Score::new(-15, 0),
+    Score::new(-5, 0),
+    Score::new(0, 0),
+    Score::new(0, 0),
+    Score::new(0, 0),
+];
+
+// pawn structure breakdown for one color
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct PawnTerms {
+    pub passed: Score,
+    pub isolated: Score,
+    pub doubled: Score,
+    pub backward: Score,
+    pub connected: Score,
+    pub shield: Score,
+    pub storm: Score,
+}
+
+impl PawnTerms {
+    pub fn total(&self) -> Score {
+        self.passed
+            + self.isolated
+            + self.doubled
+            + self.backward
+            + self.connected
+            + self.shield
+            + self.storm
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PawnEntry {
+    pub key: u64,
+    // indexed by color
+    pub terms: [PawnTerms; 2],
+    // passed pawns of each color, bit i is set if square i holds a passed pawn
+    pub passed: [u64; 2],
+    // king squares the shield and storm terms were computed for
+    king_squares: [Option<u8>; 2],
+}
+
+impl PawnEntry {
+    pub fn new(board: &Board, params: &Params) -> PawnEntry {
+        let pawns = Pawns::new(board);
+        let mut entry = PawnEntry {
+            key: board.pawn_hash(),
+            terms: [PawnTerms::default(); 2],
+            passed: [0; 2],
+            king_squares: [None; 2],
+        };
+        for color in [Color::White, Color::Black] {
+            let (terms, passed) = pawns.structure(params, color);
+            entry.terms[color as usize] = terms;
+            entry.passed[color as usize] = passed;
+        }
+        entry.update_kings(board, params, Some(&pawns));
+        entry
+    }
+
+    pub fn total(&self, color: Color) -> Score {
+        self.terms[color as usize].total()
+    }
+
+    // recompute the king dependent terms if the kings moved since, the
+    // pawns are only collected again when they did
+    fn update_kings(&mut self, board: &Board, params: &Params, pawns: Option<&Pawns>) {
+        let kings = [Color::White, Color::Black].map(|c| board.king_square(c).map(u8::from));
+        if kings == self.king_squares {
+            return;
+        }
+        let collected;
+        let pawns = match pawns {
+            Some(pawns) => pawns,
+            None => {
+                collected = Pawns::new(board);
+                &collected
+            }
+        };
+        for color in [Color::White, Color::Black] {
+            let king = kings[color as usize];
+            if self.king_squares[color as usize] == king {
+                continue;
+            }
+            let terms = &mut self.terms[color as usize];
+            (terms.shield, terms.storm) = match king {
+                Some(king) => pawns.shelter(params, color, king),
+                None => (Score::ZERO, Score::ZERO),
+            };
+            self.king_squares[color as usize] = king;
+        }
+    }
+}
+
+// bonus for passed pawns whose way to the promotion square is clear
+// this depends on all pieces so it is not cached
+pub fn passed_free_path(board: &Board, params: &Params, entry: &PawnEntry, color: Color) -> Score {
+    let mut score = Score::ZERO;
+    let mut passed = entry.passed[color as usize];
+    while passed != 0 {
+        let sq = passed.trailing_zeros() as u8;
+        passed &= passed - 1;
+        let (file, rank) = (sq % 8, sq / 8);
+        let mut path = match color {
+            Color::White => rank + 1..8,
+            Color::Black => 0..rank,
+        };
+        if path.all(|r| board[Square::try_from(r * 8 + file).unwrap()].is_blank()) {
+            score += params.passed_free_path[relative_rank(color, rank) as usize];
+        }
+    }
+    score
+}
+
+pub const DEFAULT_PAWN_TABLE_SIZE: usize = 1 << 14;
+
+pub struct PawnTable {
+    entries: Vec<Option<PawnEntry>>,
+}
+
+impl Default for PawnTable {
+    fn default() -> Self {
+        PawnTable::new(DEFAULT_PAWN_TABLE_SIZE)
+    }
+}
+
+impl PawnTable {
+    // size is rounded up to a power of two
+    pub fn new(size: usize) -> PawnTable {
+        PawnTable {
+            entries: vec![None; size.max(1).next_power_of_two()],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|e| *e = None);
+    }
+
+    // entries are only valid for one set of parameters, clear the table
+    // when changing them
+    pub fn probe(&mut self, board: &Board, params: &Params) -> PawnEntry {
+        let key = board.pawn_hash();
+        let index = key as usize & (self.entries.len() - 1);
+        match &mut self.entries[index] {
+            Some(entry) if entry.key == key => {
+                // same pawns, the kings may have moved though
+                entry.update_kings(board, params, None);
+                *entry
+            }
+            slot => {
+                let entry = PawnEntry::new(board, params);
+                *slot = Some(entry);
+                entry
+            }
+        }
+    }
+}
+
+fn relative_rank(color: Color, rank: u8) -> u8 {
+    match color {
+        Color::White => rank,
+        Color::Black => 7 - rank,
+    }
+}
+
+// pawns of both colors as (file, rank) pairs, ranks start at 0
+struct Pawns {
+    pawns: [Vec<(u8, u8)>; 2],
+}
+
+impl Pawns {
+    fn new(board: &Board) -> Pawns {
+        let mut pawns = [vec![], vec![]];
+        for (sq, p) in board.pieces() {
+            if p.get_piece() == Piece::Pawn {
+                let sq = u8::from(sq);
+                pawns[p.get_color() as usize].push((sq % 8, sq / 8));
+            }
+        }
+        Pawns { pawns }
+    }
+
+    fn structure(&self, params: &Params, color: Color) -> (PawnTerms, u64) {
+        let own = &self.pawns[color as usize];
+        let enemy = &self.pawns[color.opposite() as usize];
+        // is rank b in front of rank a for this color
+        let ahead = |a: u8, b: u8| relative_rank(color, b) > relative_rank(color, a);
+        let forward = |r: u8| match color {
+            Color::White => r as i8 + 1,
+            Color::Black => r as i8 - 1,
+        };
+
+        let mut terms = PawnTerms::default();
+        let mut passed = 0;
+        for &(f, r) in own {
+            let rr = relative_rank(color, r) as usize;
+            let adjacent = |of: u8| of.abs_diff(f) == 1;
+
+            let blocked_by_own = own.iter().any(|&(of, or)| of == f && ahead(r, or));
+            let stoppers = enemy
+                .iter()
+                .any(|&(ef, er)| ef.abs_diff(f) <= 1 && ahead(r, er));
+            if !stoppers && !blocked_by_own {
+                terms.passed += params.passed[rr];
+                passed |= 1 << (r * 8 + f);
+            }
+
+            if blocked_by_own {
+                terms.doubled += params.doubled;
+            }
+
+            let isolated = !own.iter().any(|&(of, _)| adjacent(of));
+            if isolated {
+                terms.isolated += params.isolated;
+                continue;
+            }
+
+            // side by side or defended by another pawn
+            let connected = own
+                .iter()
+                .any(|&(of, or)| adjacent(of) && (or == r || forward(or) == r as i8));
+            if connected {
+                terms.connected += params.connected[rr];
+                continue;
+            }
+
+            // all neighbours have advanced and the square in front is
+            // controlled by an enemy pawn, so this pawn cannot be supported
+            let unsupported = own
+                .iter()
+                .filter(|&&(of, _)| adjacent(of))
+                .all(|&(_, or)| ahead(r, or));
+            let stop = forward(r) as u8;
+            let stop_attacked = enemy
+                .iter()
+                .any(|&(ef, er)| adjacent(ef) && forward(stop) == er as i8);
+            if unsupported && stop_attacked {
+                terms.backward += params.backward;
+            }
+        }
+        (terms, passed)
+    }
+
+    // pawn shield and pawn storm in front of the king
+    fn shelter(&self, params: &Params, color: Color, king: u8) -> (Score, Score) {
+        let (kf, kr) = (king % 8, king / 8);
+        let distance = |pawns: &Vec<(u8, u8)>, file: u8| {
+            pawns
+                .iter()
+                .filter(|&&(f, r)| f == file && relative_rank(color, r) > relative_rank(color, kr))
+                .map(|&(_, r)| r.abs_diff(kr))
+                .min()
+                .unwrap_or(0)
+                .min(7) as usize
+        };
+
+        let mut shield = Score::ZERO;
+        let mut storm = Score::ZERO;
+        for file in kf.saturating_sub(1)..=(kf + 1).min(7) {
+            shield += params.shield[distance(&self.pawns[color as usize], file)];
+            storm += params.storm[distance(&self.pawns[color.opposite() as usize], file)];
+        }
+        (shield, storm)
+    }
+}
@@ -0,0 +1,69 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+// a pair of midgame and endgame values
+// every evaluation term produces one of these and the final
+// score is interpolated between the two depending on game phase
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Score {
+    pub const ZERO: Score = Score { mg: 0, eg: 0 };
+
+    pub const fn new(mg: i32, eg: i32) -> Score {
+        Score { mg, eg }
+    }
+
+    // phase goes from 0 (only kings and pawns) to MAX_PHASE (all pieces)
+    pub fn taper(&self, phase: i32) -> i32 {
+        (self.mg * phase + self.eg * (super::MAX_PHASE - phase)) / super::MAX_PHASE
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+
+    fn add(self, rhs: Score) -> Score {
+        Score::new(self.mg + rhs.mg, self.eg + rhs.eg)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, rhs: Score) {
+        self.mg += rhs.mg;
+        self.eg += rhs.eg;
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+
+    fn sub(self, rhs: Score) -> Score {
+        Score::new(self.mg - rhs.mg, self.eg - rhs.eg)
+    }
+}
+
+impl SubAssign for Score {
+    fn sub_assign(&mut self, rhs: Score) {
+        self.mg -= rhs.mg;
+        self.eg -= rhs.eg;
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+
+    fn neg(self) -> Score {
+        Score::new(-self.mg, -self.eg)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+
+    fn mul(self, rhs: i32) -> Score {
+        Score::new(self.mg * rhs, self.eg * rhs)
+    }
+}
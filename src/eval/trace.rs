@@ -0,0 +1,184 @@
+use std::fmt;
+
+use super::*;
+use crate::{Board, Color};
+
+// per term breakdown of the evaluation, used for tuning and debugging
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TraceTerm {
+    pub name: &'static str,
+    pub white: Score,
+    pub black: Score,
+}
+
+impl TraceTerm {
+    // contribution of the term from white's point of view
+    pub fn total(&self) -> Score {
+        self.white - self.black
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+    pub fen: String,
+    pub terms: Vec<TraceTerm>,
+    pub phase: i32,
+    // sum of all terms from white's point of view, before tapering
+    pub total: Score,
+    // the specialized evaluation used instead of the terms
+    pub endgame: Option<&'static str>,
+    // part of the endgame score kept, out of SCALE_NORMAL
+    pub scale: i32,
+    // tapered score from white's point of view
+    pub score: i32,
+    pub turn: Color,
+    // tapered score from the side to move's point of view, same as evaluate
+    pub eval: i32,
+}
+
+pub fn trace(board: &Board) -> Trace {
+    trace_params(board, &Params::DEFAULT)
+}
+
+pub fn trace_params(board: &Board, params: &Params) -> Trace {
+    let pawns = PawnEntry::new(board, params);
+    let enemy_attacks = [board.attacks(Color::Black), board.attacks(Color::White)];
+    let term = |name, f: &dyn Fn(Color) -> Score| TraceTerm {
+        name,
+        white: f(Color::White),
+        black: f(Color::Black),
+    };
+    let pawn_term = |name, f: &dyn Fn(&PawnTerms) -> Score| TraceTerm {
+        name,
+        white: f(&pawns.terms[Color::White as usize]),
+        black: f(&pawns.terms[Color::Black as usize]),
+    };
+
+    let terms = vec![
+        term("Material", &|c| material(board, params, c)),
+        term("Placement", &|c| placement(board, params, c)),
+        term("Pieces", &|c| pieces(board, params, c)),
+        term("Mobility", &|c| mobility(board, params, c)),
+        TraceTerm {
+            name: "Passed pawns",
+            white: pawns.terms[0].passed + passed_free_path(board, params, &pawns, Color::White),
+            black: pawns.terms[1].passed + passed_free_path(board, params, &pawns, Color::Black),
+        },
+        pawn_term("Isolated pawns", &|t| t.isolated),
+        pawn_term("Doubled pawns", &|t| t.doubled),
+        pawn_term("Backward pawns", &|t| t.backward),
+        pawn_term("Connected pawns", &|t| t.connected),
+        pawn_term("Pawn shield", &|t| t.shield),
+        pawn_term("Pawn storm", &|t| t.storm),
+        term("King safety", &|c| {
+            KingSafety::new(board, params, &enemy_attacks[c as usize], c).score
+        }),
+        term("Tempo", &|c| {
+            if c == board.turn {
+                params.tempo
+            } else {
+                Score::ZERO
+            }
+        }),
+    ];
+
+    let total = terms.iter().fold(Score::ZERO, |acc, t| acc + t.total());
+    let phase = phase(board);
+    let material = Material::new(board);
+    let strong = if total.eg >= 0 {
+        Color::White
+    } else {
+        Color::Black
+    };
+    let score = finish(board, total);
+    Trace {
+        fen: board.fen_notation(),
+        terms,
+        phase,
+        total,
+        endgame: Endgame::find(&material).map(|e| e.name()),
+        scale: scale_factor(board, &material, strong),
+        score,
+        turn: board.turn,
+        eval: match board.turn {
+            Color::White => score,
+            Color::Black => -score,
+        },
+    }
+}
+
+fn json_score(score: Score) -> String {
+    format!("{{\"mg\":{},\"eg\":{}}}", score.mg, score.eg)
+}
+
+impl Trace {
+    pub fn to_json(&self) -> String {
+        let terms = self
+            .terms
+            .iter()
+            .map(|t| {
+                format!(
+                    "{{\"name\":\"{}\",\"white\":{},\"black\":{},\"total\":{}}}",
+                    t.name.to_lowercase().replace(' ', "_"),
+                    json_score(t.white),
+                    json_score(t.black),
+                    json_score(t.total())
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        format!(
+            "{{\"fen\":\"{}\",\"terms\":[{}],\"phase\":{},\"max_phase\":{},\"total\":{},\"endgame\":{},\"scale\":{},\"score\":{},\"turn\":\"{}\",\"eval\":{}}}",
+            self.fen,
+            terms,
+            self.phase,
+            MAX_PHASE,
+            json_score(self.total),
+            self.endgame
+                .map_or("null".to_owned(), |e| format!("\"{}\"", e)),
+            self.scale,
+            self.score,
+            self.turn,
+            self.eval
+        )
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>16} | {:^13} | {:^13} | {:^13}",
+            "Term", "White", "Black", "Total"
+        )?;
+        writeln!(
+            f,
+            "{:>16} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}",
+            "", "MG", "EG", "MG", "EG", "MG", "EG"
+        )?;
+        writeln!(f, "{:-<17}+{:-<15}+{:-<15}+{:-<14}", "", "", "", "")?;
+        for t in &self.terms {
+            let total = t.total();
+            writeln!(
+                f,
+                "{:>16} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}",
+                t.name, t.white.mg, t.white.eg, t.black.mg, t.black.eg, total.mg, total.eg
+            )?;
+        }
+        writeln!(f, "{:-<17}+{:-<15}+{:-<15}+{:-<14}", "", "", "", "")?;
+        writeln!(
+            f,
+            "{:>16} | {:>13} | {:>13} | {:>6} {:>6}",
+            "Total", "", "", self.total.mg, self.total.eg
+        )?;
+        writeln!(f)?;
+        writeln!(f, "Phase: {}/{}", self.phase, MAX_PHASE)?;
+        match self.endgame {
+            Some(endgame) => writeln!(f, "Endgame: {}", endgame)?,
+            None => writeln!(f, "Scale factor: {}/{}", self.scale, SCALE_NORMAL)?,
+        }
+        writeln!(f, "Final evaluation (white side): {}", self.score)?;
+        write!(f, "Final evaluation (side to move): {}", self.eval)
+    }
+}
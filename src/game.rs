@@ -1 +1,83 @@
+use crate::{Board, BoardError, Move};
 
+// a game in progress: the current position and every position before it,
+// which the search needs to see repetitions and the protocols need to take
+// moves back
+#[derive(Debug, Clone, Default)]
+pub struct Game {
+    board: Board,
+    previous: Vec<(Board, Move)>,
+}
+
+impl Game {
+    pub fn new(board: Board) -> Game {
+        Game {
+            board,
+            previous: Vec::new(),
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    // moves played since the starting position
+    pub fn moves(&self) -> impl Iterator<Item = Move> + '_ {
+        self.previous.iter().map(|&(_, mv)| mv)
+    }
+
+    pub fn play(&mut self, mv: Move) -> Result<(), BoardError> {
+        let before = self.board.clone();
+        self.board.play(mv)?;
+        self.previous.push((before, mv));
+        Ok(())
+    }
+
+    // play a move in coordinate notation, eg. e2e4 or e7e8q
+    pub fn play_str(&mut self, mv: &str) -> Result<(), BoardError> {
+        self.play(Move::try_from(mv)?)
+    }
+
+    // take back the last move, returning it
+    pub fn undo(&mut self) -> Option<Move> {
+        let (board, mv) = self.previous.pop()?;
+        self.board = board;
+        Some(mv)
+    }
+
+    // hashes of the positions before the current one, oldest first
+    pub fn hashes(&self) -> Vec<u64> {
+        self.previous
+            .iter()
+            .map(|(board, _)| board.hash())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn play_and_undo() {
+        let mut game = Game::default();
+        for mv in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            game.play_str(mv).unwrap();
+        }
+        assert!(game.play_str("e2e5").is_err());
+        assert_eq!(game.moves().count(), 4);
+        assert_eq!(game.hashes()[0], game.board().hash());
+
+        assert_eq!(game.undo(), Some(Move::try_from("f6g8").unwrap()));
+        assert_eq!(game.hashes().len(), 3);
+        assert_eq!(
+            game.board().fen_notation(),
+            "rnbqkb1r/pppppppp/5n2/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 3 2"
+        );
+        game.undo();
+        game.undo();
+        game.undo();
+        assert_eq!(game.undo(), None);
+        assert_eq!(game.board(), &Board::default());
+    }
+}
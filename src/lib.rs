@@ -7,6 +7,9 @@ pub use crate::board::*;
 mod game;
 pub use crate::game::*;
 
+mod pgn;
+pub use crate::pgn::*;
+
 mod error;
 pub use crate::error::*;
 
@@ -23,6 +26,27 @@ mod chess_move;
 pub use crate::chess_move::*;
 
 mod movegen;
+pub use crate::movegen::AttackMap;
+
+mod zobrist;
+
+pub mod book;
+
+pub mod eval;
+
+pub mod mcts;
+
+pub mod search;
+
+pub mod solve;
+
+pub mod tablebase;
+
+pub mod tune;
+
+pub mod uci;
+
+pub mod xboard;
 
 #[cfg(test)]
 mod tests {
@@ -41,5 +65,57 @@ mod tests {
         let mut board2 = Board::from_fen("2p5/1P6/8/8/8/8/5k2/K7 w KQkq - 0 1".into()).unwrap();
         board2.make_move("xc8=Q".into()).unwrap();
         assert_eq!(board2.fen_notation(), "2Q5/8/8/8/8/8/5k2/K7 b KQkq - 0 1");
+        assert_eq!(board2.piece_count(), 3);
+    }
+
+    fn perft(board: &Board, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        board
+            .legal_moves()
+            .into_iter()
+            .map(|mv| {
+                let mut b = board.clone();
+                b.do_move(mv);
+                perft(&b, depth - 1)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn move_generation() {
+        // well known perft results
+        let positions = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", vec![20, 400, 8902]),
+            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", vec![48, 2039]),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", vec![14, 191, 2812]),
+            ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", vec![6, 264]),
+            ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", vec![44, 1486]),
+        ];
+        for (fen, counts) in positions {
+            let board = Board::from_fen(fen.into()).unwrap();
+            for (depth, &count) in counts.iter().enumerate() {
+                assert_eq!(perft(&board, depth as u32 + 1), count, "{} depth {}", fen, depth + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn special_moves() {
+        let mut board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1".into()).unwrap();
+        board.make_move("O-O".into()).unwrap();
+        assert_eq!(board.fen_notation(), "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1");
+        board.play(Move::try_from("e8c8").unwrap()).unwrap();
+        assert_eq!(board.fen_notation(), "2kr3r/8/8/8/8/8/8/R4RK1 w - - 2 2");
+
+        let mut board = Board::from_fen("4k3/8/8/8/3p4/8/4P3/4K3 w - - 0 1".into()).unwrap();
+        board.make_move("e4".into()).unwrap();
+        board.make_move("dxe3".into()).unwrap();
+        assert_eq!(board.fen_notation(), "4k3/8/8/8/8/4p3/8/4K3 w - - 0 2");
+        assert_eq!(board.piece_count(), 3);
+
+        // nothing stands behind an en passant square on the back rank
+        let _ = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - a1 0 1".into());
     }
 }
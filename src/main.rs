@@ -1,11 +1,367 @@
-use chess::Board;
+use chess::{
+    book::{BookBuilder, BookFilter},
+    eval::{self, Params},
+    parse_pgn, search, solve, tablebase, tune,
+    uci::Uci,
+    xboard::XBoard,
+    Board,
+};
+use clap::{Parser, Subcommand};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+#[derive(Parser)]
+#[command(version, about = "Simple chess engine")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Play a game in the terminal
+    Play,
+    /// Talk to a GUI with the Universal Chess Interface
+    Uci,
+    /// Talk to a GUI with the XBoard protocol
+    Xboard,
+    /// Evaluate a position
+    Eval {
+        /// Position to evaluate, defaults to the starting position
+        #[arg(long)]
+        fen: Option<String>,
+        /// Print the contribution of every evaluation term
+        #[arg(long)]
+        trace: bool,
+        /// Print the trace as JSON
+        #[arg(long)]
+        json: bool,
+        /// Evaluate with this network instead of the hand-crafted evaluation
+        #[arg(long)]
+        eval_file: Option<PathBuf>,
+        /// Weights of the hand-crafted evaluation, as written by tune
+        #[arg(long)]
+        params: Option<PathBuf>,
+    },
+    /// Work with Polyglot opening books
+    Book {
+        #[command(subcommand)]
+        command: BookCommand,
+    },
+    /// Work with endgame tablebases
+    Tablebase {
+        #[command(subcommand)]
+        command: TablebaseCommand,
+    },
+    /// Solve chess problems
+    Solve {
+        #[command(subcommand)]
+        command: SolveCommand,
+    },
+    /// Prove a forced mate for either side with proof-number search
+    Prove {
+        /// Position to prove
+        #[arg(long)]
+        fen: String,
+        /// Give up after this many nodes
+        #[arg(long, default_value_t = 100_000)]
+        nodes: u64,
+    },
+    /// Tune the evaluation weights on positions labeled with game results
+    Tune {
+        /// EPD or FEN files with one position and the result of its game per line
+        #[arg(required = true)]
+        positions: Vec<PathBuf>,
+        /// File to write the weights to, after every pass
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Weights to start from, defaults to the built-in ones
+        #[arg(long)]
+        params: Option<PathBuf>,
+        /// Stop after this many passes over all weights
+        #[arg(long, default_value_t = 100)]
+        passes: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum BookCommand {
+    /// Build a book from PGN files
+    Build {
+        /// PGN files to read
+        #[arg(required = true)]
+        pgn: Vec<PathBuf>,
+        /// Book file to write
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Plies of every game to use
+        #[arg(long, default_value_t = BookFilter::default().max_ply)]
+        depth: usize,
+        /// Leave out moves played in fewer games
+        #[arg(long, default_value_t = BookFilter::default().min_games)]
+        min_games: u32,
+        /// Only use the moves of players rated at least this
+        #[arg(long)]
+        min_rating: Option<u32>,
+        /// Only use the moves of the side that won
+        #[arg(long)]
+        winners_only: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TablebaseCommand {
+    /// Generate tables by retrograde analysis, with the tables they lead to
+    Generate {
+        /// Material signatures, white pieces first, eg. KRK or KRKP
+        #[arg(required = true)]
+        signatures: Vec<String>,
+        /// Directory to write the tables to
+        #[arg(short, long, default_value = ".")]
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum SolveCommand {
+    /// Prove or refute that the side to move mates in N moves
+    Mate {
+        /// Number of moves
+        moves: u32,
+        /// Position of the problem
+        #[arg(long)]
+        fen: String,
+    },
+    /// Find every way the side to move helps the other side to mate it in N moves
+    Helpmate {
+        /// Number of moves
+        moves: u32,
+        /// Position of the problem
+        #[arg(long)]
+        fen: String,
+    },
+    /// Prove or refute that the side to move forces the other side to mate it in N moves
+    Selfmate {
+        /// Number of moves
+        moves: u32,
+        /// Position of the problem
+        #[arg(long)]
+        fen: String,
+    },
+}
 
 fn main() {
+    let cli = Cli::parse();
+    match cli.command.unwrap_or(Command::Play) {
+        Command::Play => play(),
+        Command::Uci => uci(None),
+        Command::Xboard => xboard(None),
+        Command::Eval {
+            fen,
+            trace,
+            json,
+            eval_file,
+            params,
+        } => {
+            let board = fen.map_or_else(Board::default, parse_fen);
+            let params = params.map_or_else(Params::default, read_params);
+            if let Some(path) = eval_file {
+                match eval::Network::read(&path) {
+                    Ok(network) => println!("{}", network.evaluate(&board)),
+                    Err(e) => {
+                        eprintln!("{}: {}", path.display(), e);
+                        std::process::exit(1);
+                    }
+                }
+            } else if json {
+                println!("{}", eval::trace_params(&board, &params).to_json());
+            } else if trace {
+                println!("{}", eval::trace_params(&board, &params));
+            } else {
+                println!("{}", eval::evaluate_params(&board, &params));
+            }
+        }
+        Command::Book {
+            command:
+                BookCommand::Build {
+                    pgn,
+                    output,
+                    depth,
+                    min_games,
+                    min_rating,
+                    winners_only,
+                },
+        } => build_book(
+            &pgn,
+            &output,
+            BookFilter {
+                max_ply: depth,
+                min_games,
+                min_rating,
+                winners_only,
+            },
+        ),
+        Command::Tablebase {
+            command: TablebaseCommand::Generate { signatures, dir },
+        } => generate_tablebases(&signatures, &dir),
+        Command::Solve {
+            command: SolveCommand::Mate { moves, fen },
+        } => print!("{}", solve::solve_mate(&parse_fen(fen), moves)),
+        Command::Solve {
+            command: SolveCommand::Helpmate { moves, fen },
+        } => print!("{}", solve::solve_helpmate(&parse_fen(fen), moves)),
+        Command::Solve {
+            command: SolveCommand::Selfmate { moves, fen },
+        } => print!("{}", solve::solve_selfmate(&parse_fen(fen), moves)),
+        Command::Prove { fen, nodes } => print!("{}", search::prove(&parse_fen(fen), nodes)),
+        Command::Tune {
+            positions,
+            output,
+            params,
+            passes,
+        } => tune(
+            &positions,
+            &output,
+            params.map_or_else(Params::default, read_params),
+            passes,
+        ),
+    }
+}
+
+fn parse_fen(fen: String) -> Board {
+    match Board::from_fen(fen) {
+        Ok(board) => board,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn read_params(path: PathBuf) -> Params {
+    match Params::read(&path) {
+        Ok(params) => params,
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn tune(files: &[PathBuf], output: &Path, params: Params, passes: usize) {
+    let mut positions = Vec::new();
+    for file in files {
+        match tune::read_positions(file, &params) {
+            Ok(read) => positions.extend(read),
+            Err(e) => {
+                eprintln!("{}: {}", file.display(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+    println!("{} quiet positions", positions.len());
+    let mut tuner = tune::Tuner::new(positions, params);
+    println!("k {:.4}, error {:.6}", tuner.k(), tuner.current_error());
+    for pass in 1..=passes {
+        let improved = tuner.pass();
+        println!("pass {}: error {:.6}", pass, tuner.current_error());
+        if let Err(e) = tuner.params().write(output) {
+            eprintln!("{}: {}", output.display(), e);
+            std::process::exit(1);
+        }
+        if !improved {
+            break;
+        }
+    }
+}
+
+fn generate_tablebases(signatures: &[String], dir: &Path) {
+    for signature in signatures {
+        if let Err(e) = tablebase::generate(signature) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    for signature in tablebase::loaded() {
+        let path = dir.join(tablebase::file_name(&signature));
+        let written = tablebase::get(&signature).map(|table| table.write(&path));
+        if let Some(Err(e)) = written {
+            eprintln!("{}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+        println!("{}", path.display());
+    }
+}
+
+fn build_book(files: &[PathBuf], output: &PathBuf, filter: BookFilter) {
+    let mut builder = BookBuilder::new(filter);
+    let mut games = 0;
+    for file in files {
+        let text = match std::fs::read(file) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) => {
+                eprintln!("{}: {}", file.display(), e);
+                std::process::exit(1);
+            }
+        };
+        for (i, game) in parse_pgn(&text).iter().enumerate() {
+            // the moves before a bad one still count
+            if let Err(e) = builder.add_game(game) {
+                eprintln!("{}: game {}: {}", file.display(), i + 1, e);
+            }
+            games += 1;
+        }
+    }
+    match builder.write(output) {
+        Ok(entries) => println!("{} games, {} entries", games, entries),
+        Err(e) => {
+            eprintln!("{}: {}", output.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn play() {
     let mut board = Board::default();
     loop {
-        println!("{}\n{:?} to move", board, board.turn);
+        // stderr, a GUI starting the engine without arguments only reads stdout
+        eprintln!("{}\n{:?} to move", board, board.turn);
         let mut input = String::new();
-        std::io::stdin().read_line(&mut input).unwrap();
-        board.make_move(input.trim().to_owned()).unwrap();
+        match std::io::stdin().read_line(&mut input) {
+            Ok(0) => return,
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        }
+        match input.trim() {
+            "uci" => return uci(Some("uci")),
+            "xboard" => return xboard(Some("xboard")),
+            "" => {}
+            mv => {
+                if let Err(e) = board.make_move(mv.to_owned()) {
+                    eprintln!("{}", e);
+                }
+            }
+        }
+    }
+}
+
+fn uci(first: Option<&str>) {
+    let mut uci = Uci::new(Arc::new(Mutex::new(std::io::stdout())));
+    if let Some(command) = first {
+        uci.handle(command);
+    }
+    uci.run(std::io::stdin().lock());
+}
+
+fn xboard(first: Option<&str>) {
+    let mut xboard = XBoard::new(Arc::new(Mutex::new(std::io::stdout())));
+    if let Some(command) = first {
+        xboard.handle(command);
     }
+    xboard.run(std::io::stdin().lock());
 }
@@ -0,0 +1,659 @@
+mod tree;
+use self::tree::*;
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use crate::{
+    eval::{Evaluator, Network, Params, PIECE_VALUES},
+    search::{
+        captured, Bound, CancellationToken, PvLine, Reporter, Rng, SearchEvent, SearchInfo,
+        SearchLimits, SearchOptions, SearchResult, Searcher, TimeManager, MATE, MATE_BOUND,
+    },
+    Board, Move, Piece,
+};
+
+// monte carlo tree search
+// every iteration walks down the tree picking the child with the best mix
+// of result and uncertainty, expands the leaf it reaches and scores it with
+// a playout, random moves to the end of the game, or with the evaluation,
+// and adds the result to every node on the way back. the move played is the
+// most visited one, and the part of the tree below the moves played is kept
+// for the next search
+//
+// nodes are scored with results between 0 and 1 for the side that moved
+// into them, evaluations are turned into results with a logistic curve
+//
+// the tree takes as much memory as the hash option allows, once it is full
+// leaves are scored without adding their children
+
+// centipawns for a result of about 0.73
+const RESULT_SCALE: f64 = 200.0;
+
+// random playouts longer than this are scored by the evaluation
+const PLAYOUT_PLIES: usize = 200;
+
+// the stop flag and the clock are checked this often
+const CHECK_PLAYOUTS: u64 = 64;
+
+// info lines are sent after this many playouts, then every time they double
+const FIRST_REPORT: u64 = 256;
+
+// how much the priors of puct are spread over the moves, in centipawns
+const PRIOR_TEMPERATURE: f64 = 100.0;
+
+// memory taken by a node, with its index in the children of its parent
+const NODE_SIZE: usize = std::mem::size_of::<Node>() + std::mem::size_of::<usize>();
+
+// how a child to descend into is picked
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Selection {
+    // upper confidence bound, trying every child once first
+    Uct,
+    // upper confidence bound weighted by priors from the captures and
+    // promotions of the moves
+    Puct,
+}
+
+// how a new leaf is scored
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Playout {
+    // random legal moves until the game ends
+    Random,
+    // the evaluation of the leaf instead of a playout
+    Evaluation,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MctsOptions {
+    pub selection: Selection,
+    pub playout: Playout,
+    // weight of uncertainty against results when selecting
+    pub exploration: f64,
+}
+
+impl Default for MctsOptions {
+    fn default() -> Self {
+        MctsOptions {
+            selection: Selection::Puct,
+            playout: Playout::Evaluation,
+            exploration: 1.4,
+        }
+    }
+}
+
+pub struct Mcts {
+    pub options: SearchOptions,
+    tree: Tree,
+    // the position at the root of the tree
+    root: Option<Board>,
+    evaluator: Evaluator,
+    rng: Rng,
+    stop: CancellationToken,
+    pondering: Arc<AtomicBool>,
+    pondered: bool,
+    reporter: Option<Reporter>,
+    // hashes of the positions played before the root
+    history: Vec<u64>,
+    playouts: u64,
+    seldepth: usize,
+    start: Instant,
+}
+
+impl Mcts {
+    pub fn new(options: SearchOptions) -> Mcts {
+        Mcts::with_flags(options, CancellationToken::default(), Arc::default())
+    }
+
+    // sharing the stop and ponder flags of the alpha-beta search
+    pub fn with_flags(
+        options: SearchOptions,
+        stop: CancellationToken,
+        pondering: Arc<AtomicBool>,
+    ) -> Mcts {
+        Mcts {
+            options,
+            tree: Tree::new(),
+            root: None,
+            evaluator: Evaluator::default(),
+            rng: Rng::from_time(),
+            stop,
+            pondering,
+            pondered: false,
+            reporter: None,
+            history: Vec::new(),
+            playouts: 0,
+            seldepth: 0,
+            start: Instant::now(),
+        }
+    }
+
+    // make the random playouts repeatable
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    // evaluate with the network instead of the hand-crafted evaluation
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.evaluator.set_network(network);
+    }
+
+    pub fn set_params(&mut self, params: Arc<Params>) {
+        self.evaluator.set_params(params);
+    }
+
+    // keep the part of the tree below the position, if it is the root or
+    // comes from it after one or two moves
+    fn reuse(&mut self, board: &Board) {
+        let hash = board.hash();
+        let found = self.root.as_ref().and_then(|root| {
+            let mut queue = vec![(Tree::ROOT, root.clone_position(), 0)];
+            while let Some((node, position, depth)) = queue.pop() {
+                if position.hash() == hash {
+                    return Some(node);
+                }
+                if depth < 2 {
+                    for &child in &self.tree.nodes[node].children {
+                        let mut next = position.clone_position();
+                        next.do_move(self.tree.nodes[child].mv.unwrap());
+                        queue.push((child, next, depth + 1));
+                    }
+                }
+            }
+            None
+        });
+        self.tree = match found {
+            Some(node) => self.tree.subtree(node),
+            None => Tree::new(),
+        };
+        // a draw by repetition below the old root is no draw at the new one,
+        // mates and stalemates are found again when it is expanded
+        let root = &mut self.tree.nodes[Tree::ROOT];
+        if root.terminal.take().is_some() {
+            root.expanded = false;
+        }
+        self.root = Some(board.clone_position());
+    }
+
+    fn search(
+        &mut self,
+        board: &Board,
+        limits: &SearchLimits,
+        mut time: Option<TimeManager>,
+    ) -> SearchResult {
+        self.reuse(board);
+        // search moves that are not legal are ignored
+        let legal = board.legal_moves();
+        let search_moves: Vec<Move> = limits
+            .search_moves
+            .iter()
+            .copied()
+            .filter(|mv| legal.contains(mv))
+            .collect();
+        self.playouts = 0;
+        self.seldepth = 0;
+        self.start = Instant::now();
+        self.pondered = false;
+        let mut next_report = FIRST_REPORT;
+        loop {
+            self.playout(board, &search_moves);
+            self.playouts += 1;
+            let root = self.tree.root();
+            // nothing left to learn, and no need to think about a forced move
+            if root.terminal.is_some() || time.is_some() && root.children.len() == 1 {
+                break;
+            }
+            if limits.nodes.is_some_and(|n| self.playouts >= n)
+                || limits
+                    .depth
+                    .is_some_and(|d| self.seldepth >= d.max(1) as usize)
+            {
+                break;
+            }
+            if self.playouts >= next_report {
+                next_report *= 2;
+                self.report();
+            }
+            if self.playouts.is_multiple_of(CHECK_PLAYOUTS) {
+                if self.stop.is_cancelled() {
+                    break;
+                }
+                if self.pondering.load(Ordering::Relaxed) {
+                    self.pondered = true;
+                } else if let Some(time) = time.as_mut() {
+                    if std::mem::take(&mut self.pondered) {
+                        time.restart();
+                    }
+                    if time.should_stop() {
+                        break;
+                    }
+                }
+            }
+        }
+        self.report();
+        let result = self.result();
+        if let Some(reporter) = self.reporter.as_mut() {
+            reporter(&SearchEvent::Finished(result.clone()));
+        }
+        result
+    }
+
+    // one walk down the tree and back
+    fn playout(&mut self, board: &Board, search_moves: &[Move]) {
+        let mut position = board.clone_position();
+        let mut hashes = self.history.clone();
+        let mut path = vec![Tree::ROOT];
+        let mut node = Tree::ROOT;
+        while self.tree.nodes[node].expanded && self.tree.nodes[node].terminal.is_none() {
+            let allowed = if node == Tree::ROOT {
+                search_moves
+            } else {
+                &[]
+            };
+            let Some(child) = self.select(node, allowed) else {
+                break;
+            };
+            hashes.push(position.hash());
+            position.do_move(self.tree.nodes[child].mv.unwrap());
+            path.push(child);
+            node = child;
+        }
+        self.seldepth = self.seldepth.max(path.len() - 1);
+        // the result for the side that moved into the leaf
+        let mut result = match self.tree.nodes[node].terminal {
+            Some(result) => result,
+            None => self.expand(node, &position, &hashes),
+        };
+        for &node in path.iter().rev() {
+            let node = &mut self.tree.nodes[node];
+            node.visits += 1;
+            node.value += result;
+            result = 1.0 - result;
+        }
+    }
+
+    fn select(&self, node: usize, allowed: &[Move]) -> Option<usize> {
+        let parent = &self.tree.nodes[node];
+        let c = self.options.mcts.exploration;
+        let visits = parent.visits.max(1) as f64;
+        let score = |child: &Node| match self.options.mcts.selection {
+            Selection::Uct if child.visits == 0 => f64::INFINITY,
+            Selection::Uct => child.q() + c * (visits.ln() / child.visits as f64).sqrt(),
+            Selection::Puct => {
+                child.q() + c * child.prior * visits.sqrt() / (1.0 + child.visits as f64)
+            }
+        };
+        parent
+            .children
+            .iter()
+            .copied()
+            .filter(|&i| {
+                allowed.is_empty()
+                    || self.tree.nodes[i]
+                        .mv
+                        .is_some_and(|mv| allowed.contains(&mv))
+            })
+            .max_by(|&a, &b| score(&self.tree.nodes[a]).total_cmp(&score(&self.tree.nodes[b])))
+    }
+
+    // add the children of the leaf and score it
+    fn expand(&mut self, node: usize, position: &Board, hashes: &[u64]) -> f64 {
+        let hash = position.hash();
+        let repeated = hashes
+            .iter()
+            .rev()
+            .take(position.halfmove_clock as usize)
+            .any(|&h| h == hash);
+        let moves = position.legal_moves();
+        let terminal = if moves.is_empty() {
+            // mated, or stalemate
+            Some(if position.in_check() { 1.0 } else { 0.5 })
+        } else if node != Tree::ROOT && (repeated || position.halfmove_clock >= 100) {
+            // the root is searched whatever happened before it, there has to
+            // be a move to play
+            Some(0.5)
+        } else {
+            None
+        };
+        if let Some(result) = terminal {
+            self.tree.nodes[node].expanded = true;
+            self.tree.nodes[node].terminal = Some(result);
+            return result;
+        }
+        // a full tree leaves the node a leaf, scored again on every visit
+        if self.tree.nodes.len() + moves.len() <= self.max_nodes() {
+            let priors = match self.options.mcts.selection {
+                Selection::Uct => vec![1.0; moves.len()],
+                Selection::Puct => priors(position, &moves),
+            };
+            for (&mv, prior) in moves.iter().zip(priors) {
+                let child = self.tree.add(Node::new(Some(mv), prior));
+                self.tree.nodes[node].children.push(child);
+            }
+            self.tree.nodes[node].expanded = true;
+        }
+        let result = match self.options.mcts.playout {
+            Playout::Random => self.random_playout(position),
+            Playout::Evaluation => result(self.evaluator.evaluate(position)),
+        };
+        // the result for the side to move is the opposite for the other one
+        1.0 - result
+    }
+
+    // the nodes that fit in the hash size
+    fn max_nodes(&self) -> usize {
+        self.options.hash_size * 1024 * 1024 / NODE_SIZE
+    }
+
+    // the result for the side to move of random moves from the position
+    // random moves hardly ever make progress, so the fifty move rule would
+    // make nearly every playout a draw and is left to the tree
+    fn random_playout(&mut self, position: &Board) -> f64 {
+        let mut board = position.clone_position();
+        for ply in 0..PLAYOUT_PLIES {
+            let moves = board.legal_moves();
+            if moves.is_empty() {
+                let result = if board.in_check() { 0.0 } else { 0.5 };
+                // the result for the side to move at the start
+                return if ply % 2 == 0 { result } else { 1.0 - result };
+            }
+            let mv = moves[(self.rng.next_u64() % moves.len() as u64) as usize];
+            board.do_move(mv);
+        }
+        let result = result(self.evaluator.evaluate(&board));
+        if PLAYOUT_PLIES.is_multiple_of(2) {
+            result
+        } else {
+            1.0 - result
+        }
+    }
+
+    // the score of a child of the root for the side to move
+    fn score(&self, child: usize) -> i32 {
+        let node = &self.tree.nodes[child];
+        // the move mates
+        if node.terminal == Some(1.0) {
+            MATE - 1
+        } else {
+            centipawns(node.q())
+        }
+    }
+
+    fn result(&self) -> SearchResult {
+        let ranked = self.tree.ranked(Tree::ROOT);
+        let lines: Vec<PvLine> = ranked
+            .iter()
+            .take(self.options.multi_pv.max(1))
+            .filter_map(|&child| {
+                let node = &self.tree.nodes[child];
+                let mut pv = vec![node.mv?];
+                pv.extend(self.tree.pv(child));
+                Some(PvLine {
+                    best_move: node.mv?,
+                    score: self.score(child),
+                    pv,
+                })
+            })
+            .collect();
+        let best = lines.first();
+        SearchResult {
+            best_move: best.map(|l| l.best_move),
+            score: best.map_or(0, |l| l.score),
+            depth: best.map_or(0, |l| l.pv.len() as i32),
+            nodes: self.playouts,
+            pv: best.map(|l| l.pv.clone()).unwrap_or_default(),
+            lines,
+        }
+    }
+
+    fn report(&mut self) {
+        if self.reporter.is_none() {
+            return;
+        }
+        let result = self.result();
+        for (index, line) in result.lines.iter().enumerate() {
+            let info = SearchInfo {
+                depth: line.pv.len() as i32,
+                seldepth: self.seldepth,
+                multipv: index + 1,
+                score: line.score,
+                bound: Bound::Exact,
+                nodes: self.playouts,
+                time: self.start.elapsed(),
+                pv: line.pv.clone(),
+                hashfull: self.tree.nodes.len() * 1000 / self.max_nodes().max(1),
+            };
+            if let Some(reporter) = self.reporter.as_mut() {
+                reporter(&SearchEvent::Info(info));
+            }
+        }
+    }
+}
+
+// a softmax of the material won by each move, captures by most valuable
+// victim and least valuable attacker, and promotions
+// evaluating every child would cost more than the rest of the playout
+fn priors(position: &Board, moves: &[Move]) -> Vec<f64> {
+    let value = |piece: Piece| PIECE_VALUES[piece as usize - 1].mg as f64;
+    let scores: Vec<f64> = moves
+        .iter()
+        .map(|&mv| {
+            let mut score = 0.0;
+            if let Some(victim) = captured(position, mv) {
+                score += value(victim) - value(position[mv.from].get_piece()) / 10.0;
+            }
+            if let Some(piece) = mv.promotion {
+                score += value(piece) - value(Piece::Pawn);
+            }
+            score
+        })
+        .collect();
+    let max = scores.iter().copied().fold(f64::MIN, f64::max);
+    let weights: Vec<f64> = scores
+        .iter()
+        .map(|s| ((s - max) / PRIOR_TEMPERATURE).exp())
+        .collect();
+    let sum: f64 = weights.iter().sum();
+    weights.into_iter().map(|w| w / sum).collect()
+}
+
+// the expected result of an evaluation
+fn result(centipawns: i32) -> f64 {
+    1.0 / (1.0 + (-centipawns as f64 / RESULT_SCALE).exp())
+}
+
+// the evaluation of an expected result, short of a mate score
+fn centipawns(result: f64) -> i32 {
+    let result = result.clamp(1e-6, 1.0 - 1e-6);
+    let score = RESULT_SCALE * (result / (1.0 - result)).ln();
+    (score.round() as i32).clamp(1 - MATE_BOUND, MATE_BOUND - 1)
+}
+
+impl Searcher for Mcts {
+    fn search_with_limits(&mut self, board: &Board, limits: &SearchLimits) -> SearchResult {
+        self.search(board, limits, None)
+    }
+
+    fn search_with_time(
+        &mut self,
+        board: &Board,
+        limits: &SearchLimits,
+        time: TimeManager,
+    ) -> SearchResult {
+        self.search(board, limits, Some(time))
+    }
+
+    fn set_history(&mut self, history: Vec<u64>) {
+        self.history = history;
+    }
+
+    fn set_reporter(&mut self, reporter: Reporter) {
+        self.reporter = Some(reporter);
+    }
+
+    fn clear(&mut self) {
+        self.tree = Tree::new();
+        self.root = None;
+        self.evaluator.pawns.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mcts(selection: Selection, playout: Playout) -> Mcts {
+        let mut mcts = Mcts::new(SearchOptions {
+            mcts: MctsOptions {
+                selection,
+                playout,
+                ..MctsOptions::default()
+            },
+            ..SearchOptions::default()
+        });
+        mcts.set_seed(7);
+        mcts
+    }
+
+    fn nodes(n: u64) -> SearchLimits {
+        SearchLimits {
+            nodes: Some(n),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn finds_good_moves() {
+        // a back rank mate, and a queen to take, too quiet for random playouts
+        let positions = [
+            (
+                "6k1/5ppp/8/8/8/8/8/3R2K1 w - - 0 1",
+                "d1d8",
+                Selection::Uct,
+                Playout::Random,
+            ),
+            (
+                "6k1/5ppp/8/8/8/8/8/3R2K1 w - - 0 1",
+                "d1d8",
+                Selection::Puct,
+                Playout::Evaluation,
+            ),
+            (
+                "4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1",
+                "d2d5",
+                Selection::Puct,
+                Playout::Evaluation,
+            ),
+        ];
+        for (fen, best, selection, playout) in positions {
+            let board = Board::from_fen(fen.into()).unwrap();
+            let result = mcts(selection, playout).search_with_limits(&board, &nodes(300));
+            assert_eq!(
+                result.best_move.map(|m| m.to_string()).as_deref(),
+                Some(best),
+                "{} {:?} {:?}",
+                fen,
+                selection,
+                playout
+            );
+            assert_eq!(result.nodes, 300);
+            assert_eq!(result.pv.first(), result.best_move.as_ref());
+        }
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/8/3R2K1 w - - 0 1".into()).unwrap();
+        let result =
+            mcts(Selection::Puct, Playout::Evaluation).search_with_limits(&board, &nodes(500));
+        assert_eq!(result.score, MATE - 1);
+    }
+
+    #[test]
+    fn reuses_the_tree() {
+        let mut mcts = mcts(Selection::Puct, Playout::Evaluation);
+        let mut board = Board::default();
+        let result = mcts.search_with_limits(&board, &nodes(300));
+        let (mv, reply) = (result.pv[0], result.pv[1]);
+        let child = |node: usize, mv: Move| {
+            mcts.tree.nodes[node]
+                .children
+                .iter()
+                .copied()
+                .find(|&c| mcts.tree.nodes[c].mv == Some(mv))
+                .unwrap()
+        };
+        let grandchild = child(child(Tree::ROOT, mv), reply);
+        let kept = mcts.tree.nodes[grandchild].visits;
+        assert!(kept > 0);
+
+        board.play(mv).unwrap();
+        board.play(reply).unwrap();
+        mcts.reuse(&board);
+        assert_eq!(mcts.tree.root().visits, kept);
+        // every node below it came along
+        assert_eq!(mcts.tree.nodes.len(), {
+            let mut count = 0;
+            let mut queue = vec![Tree::ROOT];
+            while let Some(node) = queue.pop() {
+                count += 1;
+                queue.extend(&mcts.tree.nodes[node].children);
+            }
+            count
+        });
+
+        // an unrelated position starts from scratch
+        mcts.reuse(&Board::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1".into()).unwrap());
+        assert_eq!(mcts.tree.root().visits, 0);
+    }
+
+    #[test]
+    fn searches_repeated_positions() {
+        let mut board = Board::default();
+        let mut history = Vec::new();
+        for mv in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            history.push(board.hash());
+            board.play(Move::try_from(mv).unwrap()).unwrap();
+        }
+        let mut search = mcts(Selection::Puct, Playout::Evaluation);
+        search.set_history(history);
+        assert!(search
+            .search_with_limits(&board, &nodes(300))
+            .best_move
+            .is_some());
+
+        // the new root was a repetition two plies below the old one
+        let mut board = Board::from_fen("3k4/8/8/8/8/8/8/3K3R w - - 0 1".into()).unwrap();
+        let mut history = Vec::new();
+        for mv in ["d1e1", "d8e8"] {
+            history.push(board.hash());
+            board.play(Move::try_from(mv).unwrap()).unwrap();
+        }
+        let mut search = mcts(Selection::Uct, Playout::Evaluation);
+        search.set_history(history.clone());
+        search.search_with_limits(&board, &nodes(2000));
+        for mv in ["e1d1", "e8d8"] {
+            history.push(board.hash());
+            board.play(Move::try_from(mv).unwrap()).unwrap();
+        }
+        search.set_history(history);
+        assert!(search
+            .search_with_limits(&board, &nodes(300))
+            .best_move
+            .is_some());
+    }
+
+    #[test]
+    fn tree_fits_in_the_hash_size() {
+        let mut mcts = mcts(Selection::Puct, Playout::Evaluation);
+        mcts.options.hash_size = 1;
+        let result = mcts.search_with_limits(&Board::default(), &nodes(2000));
+        assert_eq!(result.nodes, 2000);
+        assert!(result.best_move.is_some());
+        let max = mcts.max_nodes();
+        assert!(mcts.tree.nodes.len() <= max);
+        // it did fill up
+        assert!(mcts.tree.nodes.len() > max - 100);
+    }
+}
@@ -0,0 +1,109 @@
+use crate::Move;
+
+// the search tree, nodes live in one vector and point to their children by
+// index, so the part kept for the next move is copied into a new vector
+
+#[derive(Debug, Clone)]
+pub(crate) struct Node {
+    // the move into the node, none at the root
+    pub mv: Option<Move>,
+    // empty until the node is expanded
+    pub children: Vec<usize>,
+    pub expanded: bool,
+    pub visits: u32,
+    // sum of the results for the side that played the move into the node
+    pub value: f64,
+    // how likely the move is to be best, before any visit
+    pub prior: f64,
+    // the game is over, with this result for the side that moved into it
+    pub terminal: Option<f64>,
+}
+
+impl Node {
+    pub fn new(mv: Option<Move>, prior: f64) -> Node {
+        Node {
+            mv,
+            children: Vec::new(),
+            expanded: false,
+            visits: 0,
+            value: 0.0,
+            prior,
+            terminal: None,
+        }
+    }
+
+    // average result for the side that moved into the node
+    pub fn q(&self) -> f64 {
+        if self.visits == 0 {
+            0.5
+        } else {
+            self.value / self.visits as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Tree {
+    pub nodes: Vec<Node>,
+}
+
+impl Tree {
+    pub const ROOT: usize = 0;
+
+    pub fn new() -> Tree {
+        Tree {
+            nodes: vec![Node::new(None, 1.0)],
+        }
+    }
+
+    pub fn root(&self) -> &Node {
+        &self.nodes[Tree::ROOT]
+    }
+
+    pub fn add(&mut self, node: Node) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    // the children by visits, most visited first
+    pub fn ranked(&self, node: usize) -> Vec<usize> {
+        let mut children = self.nodes[node].children.clone();
+        children.sort_by(|&a, &b| {
+            let (a, b) = (&self.nodes[a], &self.nodes[b]);
+            b.visits.cmp(&a.visits).then(b.q().total_cmp(&a.q()))
+        });
+        children
+    }
+
+    // the most visited line from the node
+    pub fn pv(&self, mut node: usize) -> Vec<Move> {
+        let mut pv = Vec::new();
+        while let Some(&best) = self.ranked(node).first() {
+            if self.nodes[best].visits == 0 {
+                break;
+            }
+            pv.extend(self.nodes[best].mv);
+            node = best;
+        }
+        pv
+    }
+
+    // a tree of the node and everything below it, the node becoming the root
+    pub fn subtree(&self, node: usize) -> Tree {
+        let mut tree = Tree {
+            nodes: vec![self.nodes[node].clone()],
+        };
+        tree.nodes[Tree::ROOT].mv = None;
+        // nodes of the new tree with the old index of each of their children
+        let mut queue = vec![Tree::ROOT];
+        while let Some(new) = queue.pop() {
+            let children = std::mem::take(&mut tree.nodes[new].children);
+            for old in children {
+                let child = tree.add(self.nodes[old].clone());
+                tree.nodes[new].children.push(child);
+                queue.push(child);
+            }
+        }
+        tree
+    }
+}
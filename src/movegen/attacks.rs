@@ -0,0 +1,135 @@
+use crate::{Board, Color, Piece, Square};
+
+// squares attacked by the pieces of one color
+// unlike raw moves, attacks include squares occupied by friendly pieces
+// (they are defended) and pawns only attack diagonally
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttackMap {
+    pub color: Color,
+    // bit i is set if square i is attacked
+    pub all: u64,
+    // attacked squares by piece type, indexed by piece - 1
+    pub by_piece: [u64; 6],
+    // number of pieces attacking each square
+    pub count: [u8; 64],
+}
+
+impl AttackMap {
+    pub fn new(board: &Board, color: Color) -> AttackMap {
+        let mut map = AttackMap {
+            color,
+            all: 0,
+            by_piece: [0; 6],
+            count: [0; 64],
+        };
+        for (sq, p) in board.pieces().filter(|(_, p)| p.get_color() == color) {
+            let attacks = Attacks::piece(board, sq);
+            map.all |= attacks;
+            map.by_piece[p.get_piece() as usize - 1] |= attacks;
+            let mut bits = attacks;
+            while bits != 0 {
+                map.count[bits.trailing_zeros() as usize] += 1;
+                bits &= bits - 1;
+            }
+        }
+        map
+    }
+
+    pub fn is_attacked(&self, square: Square) -> bool {
+        self.all & (1 << u8::from(square)) != 0
+    }
+
+    pub fn attacked_by(&self, piece: Piece) -> u64 {
+        self.by_piece[piece as usize - 1]
+    }
+}
+
+const KNIGHT_OFFSETS: [(i8, i8); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_OFFSETS: [(i8, i8); 8] = [
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+const BISHOP_DIRS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+const ROOK_DIRS: [(i8, i8); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+
+pub(crate) struct Attacks;
+
+impl Attacks {
+    // squares attacked by the piece standing on square
+    pub(crate) fn piece(board: &Board, square: Square) -> u64 {
+        let p = board[square];
+        if p.is_blank() {
+            return 0;
+        }
+        match p.get_piece() {
+            Piece::Pawn => {
+                let dir = if p.is_white() { 1 } else { -1 };
+                Self::steps(square, &[(-1, dir), (1, dir)])
+            }
+            Piece::Knight => Self::steps(square, &KNIGHT_OFFSETS),
+            Piece::Bishop => Self::slides(board, square, &BISHOP_DIRS),
+            Piece::Rook => Self::slides(board, square, &ROOK_DIRS),
+            Piece::Queen => {
+                Self::slides(board, square, &BISHOP_DIRS) | Self::slides(board, square, &ROOK_DIRS)
+            }
+            Piece::King => Self::steps(square, &KING_OFFSETS),
+        }
+    }
+
+    // is square attacked by any piece of color
+    pub(crate) fn is_attacked(board: &Board, square: Square, by: Color) -> bool {
+        let target = 1 << u8::from(square);
+        board
+            .pieces()
+            .filter(|(_, p)| p.get_color() == by)
+            .any(|(sq, _)| Self::piece(board, sq) & target != 0)
+    }
+
+    fn offset(square: Square, (df, dr): (i8, i8)) -> Option<u8> {
+        let sq = u8::from(square) as i8;
+        let (file, rank) = (sq % 8 + df, sq / 8 + dr);
+        if (0..8).contains(&file) && (0..8).contains(&rank) {
+            Some((rank * 8 + file) as u8)
+        } else {
+            None
+        }
+    }
+
+    fn steps(square: Square, offsets: &[(i8, i8)]) -> u64 {
+        offsets
+            .iter()
+            .filter_map(|&o| Self::offset(square, o))
+            .fold(0, |acc, sq| acc | 1 << sq)
+    }
+
+    // sliding attacks stop at the first piece of either color
+    fn slides(board: &Board, square: Square, dirs: &[(i8, i8)]) -> u64 {
+        let mut attacks = 0;
+        for &dir in dirs {
+            let mut current = square;
+            while let Some(sq) = Self::offset(current, dir) {
+                attacks |= 1 << sq;
+                current = Square::try_from(sq).unwrap();
+                if !board[current].is_blank() {
+                    break;
+                }
+            }
+        }
+        attacks
+    }
+}
@@ -1,6 +1,6 @@
 use crate::Board;
 
-use super::{Raw, RawMove};
+use super::{Attacks, Raw, RawMove};
 // Legal moves = raw moves + move does not result in check or checkmate
 pub(crate) struct Legal;
 
@@ -10,16 +10,25 @@ impl Legal {
         Self::filter(board, &raw_moves)
     }
 
-    pub(crate) fn filter(board: &Board, raw_moves: &Vec<RawMove>) -> Vec<RawMove> {
+    pub(crate) fn filter(board: &Board, raw_moves: &[RawMove]) -> Vec<RawMove> {
+        let opponent = board.turn.opposite();
         raw_moves
             .iter()
             .filter_map(|&mv| {
-                let mut b = board.clone();
+                // the king cannot castle out of or through check
+                if let RawMove::Castle(king, rook) = mv {
+                    if Attacks::is_attacked(board, king.from, opponent)
+                        || Attacks::is_attacked(board, rook.to, opponent)
+                    {
+                        return None;
+                    }
+                }
+                let mut b = board.clone_position();
                 b.force_execute_raw_move(mv);
-                if !b.is_being_checked(board.turn, &Raw::gen_all_raw_moves(&b)) {
-                    Some(mv)
-                } else {
-                    None
+                let king = b.king_square(board.turn);
+                match king {
+                    Some(king) if Attacks::is_attacked(&b, king, opponent) => None,
+                    _ => Some(mv),
                 }
             })
             .collect()
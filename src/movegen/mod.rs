@@ -1,8 +1,11 @@
 mod raw;
-pub use self::raw::*;
+pub(crate) use self::raw::*;
 
 mod walk;
 pub use self::walk::*;
 
 mod legal;
-pub use self::legal::*;
+pub(crate) use self::legal::*;
+
+mod attacks;
+pub use self::attacks::*;
@@ -1,7 +1,7 @@
 use std::fmt;
 
 use super::{Walk, DD, HD, VD};
-use crate::{Board, Square, CastleMove, BitPiece};
+use crate::{BitPiece, Board, CastleMove, Move, Square, PROMOTION_PIECES};
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub(crate) struct RawMoveInfo {
@@ -33,6 +33,20 @@ pub(crate) enum RawMove {
     Castle(RawMoveInfo, RawMoveInfo)
 }
 
+impl RawMove {
+    // engine moves, a promotion expands to one move per promotion piece
+    pub(crate) fn to_moves(self) -> Vec<Move> {
+        match self {
+            RawMove::Single(info) if info.promotion => PROMOTION_PIECES
+                .iter()
+                .map(|&p| Move::new(info.from, info.to, Some(p)))
+                .collect(),
+            RawMove::Single(info) => vec![Move::new(info.from, info.to, None)],
+            RawMove::Castle(king, _) => vec![Move::new(king.from, king.to, None)],
+        }
+    }
+}
+
 impl fmt::Display for RawMove {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -52,6 +66,7 @@ impl fmt::Display for RawMove {
 pub(crate) struct Raw;
 
 impl Raw {
+    // raw moves of the side to move
     pub(crate) fn gen_all_raw_moves(board: &Board) -> Vec<RawMove> {
         let mut moves = vec![];
         let gen_fns = [
@@ -65,7 +80,7 @@ impl Raw {
         for i in 0..64 {
             let square = Square::try_from(i).unwrap();
             let p = board[square];
-            if !p.is_blank() {
+            if !p.is_blank() && p.get_color() == board.turn {
                 moves.extend(gen_fns[(p.get_piece() as usize) - 1](board, square));
             }
         }
@@ -80,7 +95,9 @@ impl Raw {
         }
         // vertical, noncapturing moves
         let dir = if piece.is_white() { VD::Up } else { VD::Down };
-        let max_offset = if piece.has_moved() { 1 } else { 2 };
+        // pawns loaded from FEN have not moved, use the starting rank instead
+        let start_rank = if piece.is_white() { 2 } else { 7 };
+        let max_offset = if square.rank() == start_rank { 2 } else { 1 };
         let n_squares = Walk::vertical(board, square, dir, max_offset);
         moves.extend(n_squares.iter().filter_map(|&sqr| {
             let p = board[sqr];
@@ -97,7 +114,10 @@ impl Raw {
                     },
                     castle: None,
                     en_passant: false,
-                    en_passant_square: if piece.is_white() {
+                    // only a double push creates an en passant square
+                    en_passant_square: if sqr.rank().abs_diff(square.rank()) != 2 {
+                        None
+                    } else if piece.is_white() {
                         square.up(1)
                     } else {
                         square.down(1)
@@ -136,15 +156,30 @@ impl Raw {
                         en_passant: false,
                         en_passant_square: None,
                     }))
-                } else if let Some(en_passant) = board.en_passant {
-                    if en_passant == sqr {
+                } else if let Some(captured) = board
+                    .en_passant
+                    .filter(|&en_passant| en_passant == sqr)
+                    // the captured pawn is behind the en passant square
+                    .and_then(|en_passant| {
+                        if piece.is_white() {
+                            en_passant.down(1)
+                        } else {
+                            en_passant.up(1)
+                        }
+                    })
+                {
+                    let target = board[captured];
+                    if !target.is_blank()
+                        && target.is_pawn()
+                        && target.get_color() != piece.get_color()
+                    {
                         Some(RawMove::Single(RawMoveInfo {
                             piece,
                             from: square,
                             to: sqr,
                             capture: Some(CaptureInfo {
-                                piece: board[en_passant.down(1).unwrap()],
-                                square: en_passant.down(1).unwrap(),
+                                piece: target,
+                                square: captured,
                             }),
                             promotion: if piece.is_white() {
                                 sqr.rank() == 8
@@ -354,80 +389,54 @@ impl Raw {
             }))
         }
         // castling
-        if !piece.has_moved() {
-            // current position of king is "e_"
-            let queen_side_rook_square =
-                Square::try_from(format!("{}{}", 'a', square.rank())).unwrap();
-            let king_side_rook_square =
-                Square::try_from(format!("{}{}", 'h', square.rank())).unwrap();
-            let queen_side_rook = board[queen_side_rook_square];
-            let king_side_rook = board[king_side_rook_square];
-            if !queen_side_rook.is_blank() && !queen_side_rook.is_rook() && !queen_side_rook.has_moved() {
-                // check if squares between king and rook are empty
-                let mut all_empty = true;
-                for file in (b'b'..=b'd').map(char::from) {
-                    let sqr = Square::try_from(format!("{}{}", file, square.rank())).unwrap();
-                    if !board[sqr].is_blank() {
-                        all_empty = false;
-                        break;
-                    }
-                }
-                if all_empty {
-                    moves.push(
-                        RawMove::Castle(RawMoveInfo {
-                        piece,
-                        from: square,
-                        to: Square::try_from(format!("{}{}", 'c', square.rank())).unwrap(),
-                        capture: None,
-                        promotion: false,
-                        castle: Some(CastleMove::QueenSide),
-                        en_passant: false,
-                        en_passant_square: None,
-                    }, RawMoveInfo {
-                        piece,
-                        from: square,
-                        to: Square::try_from(format!("{}{}", 'd', square.rank())).unwrap(),
-                        capture: None,
-                        promotion: false,
-                        castle: Some(CastleMove::QueenSide),
-                        en_passant: false,
-                        en_passant_square: None,
-                    }));
-                }
+        // rights are tracked by the board, whether the king passes through
+        // an attacked square is checked when filtering legal moves
+        let (home_rank, king_side, queen_side) = if piece.is_white() {
+            (1, 0b1000, 0b0100)
+        } else {
+            (8, 0b0010, 0b0001)
+        };
+        if square.rank() != home_rank || square.file() != 'e' {
+            return moves;
+        }
+        let sides = [
+            (king_side, CastleMove::KingSide, 'h', 'g', 'f', "fg"),
+            (queen_side, CastleMove::QueenSide, 'a', 'c', 'd', "bcd"),
+        ];
+        for (right, castle, rook_file, king_to, rook_to, between) in sides {
+            if board.castling & right == 0 {
+                continue;
             }
-            if !king_side_rook.is_blank() && !king_side_rook.is_rook() && !king_side_rook.has_moved() {
-                // check if squares between king and rook are empty
-                let mut all_empty = true;
-                for file in (b'f'..=b'g').map(char::from) {
-                    let sqr = Square::try_from(format!("{}{}", file, square.rank())).unwrap();
-                    if !board[sqr].is_blank() {
-                        all_empty = false;
-                        break;
-                    }
-                }
-                if all_empty {
-                    moves.push(
-                        RawMove::Castle(RawMoveInfo {
-                        piece,
-                        from: square,
-                        to: Square::try_from(format!("{}{}", 'g', square.rank())).unwrap(),
-                        capture: None,
-                        promotion: false,
-                        castle: Some(CastleMove::KingSide),
-                        en_passant: false,
-                        en_passant_square: None,
-                    }, RawMoveInfo {
-                        piece,
-                        from: square,
-                        to: Square::try_from(format!("{}{}", 'f', square.rank())).unwrap(),
-                        capture: None,
-                        promotion: false,
-                        castle: Some(CastleMove::KingSide),
-                        en_passant: false,
-                        en_passant_square: None,
-                    }));
-                }
+            let at = |file: char| Square::try_from(format!("{}{}", file, home_rank)).unwrap();
+            let rook = board[at(rook_file)];
+            if rook.is_blank() || !rook.is_rook() || rook.get_color() != piece.get_color() {
+                continue;
             }
+            if between.chars().any(|file| !board[at(file)].is_blank()) {
+                continue;
+            }
+            moves.push(RawMove::Castle(
+                RawMoveInfo {
+                    piece,
+                    from: square,
+                    to: at(king_to),
+                    capture: None,
+                    promotion: false,
+                    castle: Some(castle),
+                    en_passant: false,
+                    en_passant_square: None,
+                },
+                RawMoveInfo {
+                    piece: rook,
+                    from: at(rook_file),
+                    to: at(rook_to),
+                    capture: None,
+                    promotion: false,
+                    castle: Some(castle),
+                    en_passant: false,
+                    en_passant_square: None,
+                },
+            ));
         }
         moves
     }
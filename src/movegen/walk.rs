@@ -1,4 +1,3 @@
-use std::cmp::{min, max};
 use crate::{Board, Square};
 
 // vertical direction
@@ -36,7 +35,7 @@ impl Walk {
             return squares;
         }
         let color = piece.get_color();
-        let mut max_offset = max(min(max_offset, 7), 1);
+        let mut max_offset = max_offset.clamp(1, 7);
         while let Some(next_square) = {
             if dir == VD::Up {
                 square.up(1)
@@ -70,7 +69,7 @@ impl Walk {
             return squares;
         }
         let color = piece.get_color();
-        let mut max_offset = max(min(max_offset, 7), 1);
+        let mut max_offset = max_offset.clamp(1, 7);
         while let Some(next_square) = {
             if dir == HD::Left {
                 square.left(1)
@@ -104,7 +103,7 @@ impl Walk {
             return squares;
         }
         let color = piece.get_color();
-        let mut max_offset = max(min(max_offset, 7), 1);
+        let mut max_offset = max_offset.clamp(1, 7);
         while let Some(Some(next_square)) = {
             match dir {
                 DD::UpLeft => square.up(1).map(|s| s.left(1)),
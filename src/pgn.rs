@@ -0,0 +1,326 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::{Board, BoardError, Color, Move, Piece};
+
+// portable game notation
+// games are read as their tags and the moves of the main line in standard
+// algebraic notation. comments, variations and annotation glyphs are
+// skipped, the moves are only checked when they are played on a board
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>,
+    // 1-0, 0-1, 1/2-1/2 or * when unknown
+    pub result: String,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    // none for draws and unfinished games
+    pub fn winner(&self) -> Option<Color> {
+        match self.result.as_str() {
+            "1-0" => Some(Color::White),
+            "0-1" => Some(Color::Black),
+            _ => None,
+        }
+    }
+
+    pub fn rating(&self, color: Color) -> Option<u32> {
+        let tag = match color {
+            Color::White => "WhiteElo",
+            Color::Black => "BlackElo",
+        };
+        self.tag(tag)?.parse().ok()
+    }
+
+    // the starting position, given by the FEN tag for games not starting
+    // from the initial position
+    pub fn start(&self) -> Result<Board, BoardError> {
+        match self.tag("FEN") {
+            Some(fen) => Board::from_fen(fen.to_owned()),
+            None => Ok(Board::default()),
+        }
+    }
+}
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+// a tag pair, eg. [Event "Casual game"]
+static TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^\[(\w+)\s+"((?:[^"\\]|\\.)*)"\s*\]"#).unwrap());
+// a move other than castling, eg. Nbxd7 or e8=Q
+static SAN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^([NBRQK])?([a-h])?([1-8])?x?([a-h][1-8])(?:=?([NBRQ]))?$").unwrap()
+});
+
+// all games in the text, games without moves or tags are skipped
+pub fn parse_pgn(text: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::default();
+    let mut variations = 0;
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_whitespace() {
+            c.len_utf8()
+        } else if c == '{' {
+            rest.find('}').map_or(rest.len(), |i| i + 1)
+        } else if c == ';' || (c == '%' && rest_starts_line(text, rest)) {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if c == '(' || c == ')' {
+            variations = if c == '(' {
+                variations + 1
+            } else {
+                variations.max(1) - 1
+            };
+            1
+        } else if c == '[' && variations == 0 {
+            match TAG.captures(rest) {
+                Some(cap) => {
+                    // tags after moves start the next game
+                    if !game.moves.is_empty() {
+                        games.push(std::mem::take(&mut game));
+                    }
+                    let value = cap[2].replace("\\\"", "\"").replace("\\\\", "\\");
+                    game.tags.push((cap[1].to_owned(), value));
+                    cap[0].len()
+                }
+                None => rest.find('\n').unwrap_or(rest.len()),
+            }
+        } else {
+            let len = rest
+                .find(|c: char| c.is_whitespace() || "{}();[".contains(c))
+                .unwrap_or(rest.len())
+                .max(c.len_utf8());
+            if variations == 0 {
+                let token = &rest[..len];
+                if RESULTS.contains(&token) {
+                    game.result = token.to_owned();
+                    games.push(std::mem::take(&mut game));
+                } else if let Some(mv) = san_token(token) {
+                    game.moves.push(mv.to_owned());
+                }
+            }
+            len
+        };
+        rest = &rest[len..];
+    }
+    if !game.moves.is_empty() {
+        game.result = "*".to_owned();
+        games.push(game);
+    }
+    games.retain(|g| !g.moves.is_empty() || !g.tags.is_empty());
+    games
+}
+
+// escape lines start with % in the first column
+fn rest_starts_line(text: &str, rest: &str) -> bool {
+    let at = text.len() - rest.len();
+    at == 0 || text.as_bytes()[at - 1] == b'\n'
+}
+
+// the move of a movetext token, without move numbers and annotations
+fn san_token(token: &str) -> Option<&str> {
+    if token.starts_with('$') {
+        return None;
+    }
+    // 12. or 12... before the move, with or without a space
+    let token = token.rsplit('.').next().unwrap_or(token);
+    let token = token.trim_end_matches(['+', '#', '!', '?']);
+    (!token.is_empty() && !token.bytes().all(|b| b.is_ascii_digit())).then_some(token)
+}
+
+// the legal move written in standard algebraic notation, eg. Nbd7, exd6,
+// e8=Q or O-O. check marks and annotations are ignored
+pub fn parse_san(board: &Board, san: &str) -> Result<Move, BoardError> {
+    let illegal = || BoardError::IllegalMove(san.to_owned());
+    let token = san.trim_end_matches(['+', '#', '!', '?']);
+    let castle = match token {
+        "O-O" | "0-0" => Some(6),
+        "O-O-O" | "0-0-0" => Some(2),
+        _ => None,
+    };
+    let moves = board.legal_moves();
+    let piece_at = |mv: &Move| board[mv.from].get_piece();
+    if let Some(file) = castle {
+        return moves
+            .into_iter()
+            .find(|mv| {
+                piece_at(mv) == Piece::King
+                    && u8::from(mv.from) % 8 == 4
+                    && u8::from(mv.to) % 8 == file
+            })
+            .ok_or_else(illegal);
+    }
+
+    let Some(cap) = SAN.captures(token) else {
+        return Err(BoardError::InvalidPattern(san.to_owned()));
+    };
+    let piece = cap.get(1).map_or(Ok(Piece::Pawn), |p| {
+        Piece::try_from(p.as_str().chars().next().unwrap())
+    });
+    let piece = piece.map_err(|_| illegal())?;
+    let to = cap[4].to_owned();
+    let file = cap.get(2).map(|f| f.as_str().chars().next().unwrap());
+    let rank = cap.get(3).map(|r| r.as_str().chars().next().unwrap());
+    let promotion = match cap.get(5) {
+        Some(p) => {
+            Some(Piece::try_from(p.as_str().chars().next().unwrap()).map_err(|_| illegal())?)
+        }
+        None => None,
+    };
+    let mut candidates = moves.into_iter().filter(|mv| {
+        piece_at(mv) == piece
+            && mv.to.to_string() == to
+            && file.is_none_or(|f| mv.from.file() == f)
+            && rank.is_none_or(|r| mv.from.to_string().ends_with(r))
+            && mv.promotion == promotion
+    });
+    match (candidates.next(), candidates.next()) {
+        (Some(mv), None) => Ok(mv),
+        (Some(_), Some(_)) => Err(BoardError::AmbiguousMove(san.to_owned())),
+        (None, _) => Err(illegal()),
+    }
+}
+
+// the legal move in standard algebraic notation, with the file or rank of
+// the piece when another piece of the same kind can go to the same square
+// and a check or mate mark
+pub fn to_san(board: &Board, mv: Move) -> String {
+    let piece = board[mv.from].get_piece();
+    let mut san = if piece == Piece::King && u8::from(mv.from).abs_diff(u8::from(mv.to)) == 2 {
+        if mv.to.file() == 'g' {
+            "O-O".to_owned()
+        } else {
+            "O-O-O".to_owned()
+        }
+    } else {
+        let capture =
+            !board[mv.to].is_blank() || (piece == Piece::Pawn && Some(mv.to) == board.en_passant);
+        let mut san = String::new();
+        if piece == Piece::Pawn {
+            if capture {
+                san.push(mv.from.file());
+            }
+        } else {
+            san.push_str(&piece.to_string().to_uppercase());
+            let others: Vec<Move> = board
+                .legal_moves()
+                .into_iter()
+                .filter(|m| {
+                    m.to == mv.to && m.from != mv.from && board[m.from].get_piece() == piece
+                })
+                .collect();
+            if !others.is_empty() {
+                let from = mv.from.to_string();
+                if others.iter().all(|m| m.from.file() != mv.from.file()) {
+                    san.push(mv.from.file());
+                } else if others.iter().all(|m| m.from.rank() != mv.from.rank()) {
+                    san.push_str(&from[1..]);
+                } else {
+                    san.push_str(&from);
+                }
+            }
+        }
+        if capture {
+            san.push('x');
+        }
+        san.push_str(&mv.to.to_string());
+        if let Some(promotion) = mv.promotion {
+            san.push('=');
+            san.push_str(&promotion.to_string().to_uppercase());
+        }
+        san
+    };
+    let mut after = board.clone_position();
+    after.do_move(mv);
+    if after.in_check() {
+        san.push(if after.legal_moves().is_empty() {
+            '#'
+        } else {
+            '+'
+        });
+    }
+    san
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_games() {
+        let text = r#"[Event "Casual"]
+[White "Anderssen, A."]
+[WhiteElo "2600"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 {the knight} Nc6 (2... d6 3. d4) 3. Bb5 $1 a6?! 4.Ba4 Nf6
+5. 0-0 Be7 ; a rest of line comment
+1-0
+
+[Event "Second"]
+[FEN "4k3/P7/8/8/8/8/8/4K3 w - - 0 1"]
+
+1. a8=Q+ Kd7 2 . Qb7+ 1/2-1/2
+"#;
+        let games = parse_pgn(text);
+        assert_eq!(games.len(), 2);
+        let first = &games[0];
+        assert_eq!(first.tag("White"), Some("Anderssen, A."));
+        assert_eq!(first.rating(Color::White), Some(2600));
+        assert_eq!(first.rating(Color::Black), None);
+        assert_eq!(first.winner(), Some(Color::White));
+        assert_eq!(
+            first.moves,
+            ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4", "Nf6", "0-0", "Be7"]
+        );
+        assert_eq!(games[1].moves, ["a8=Q", "Kd7", "Qb7"]);
+        assert_eq!(games[1].winner(), None);
+
+        let mut board = first.start().unwrap();
+        for san in &first.moves {
+            board.play(parse_san(&board, san).unwrap()).unwrap();
+        }
+        assert_eq!(
+            board.fen_notation(),
+            "r1bqk2r/1pppbppp/p1n2n2/4p3/B3P3/5N2/PPPP1PPP/RNBQ1RK1 w kq - 4 6"
+        );
+    }
+
+    #[test]
+    fn san() {
+        let board =
+            Board::from_fen("r3k2r/1P6/8/3pP3/8/2N3N1/8/R3K2R w KQkq d6 0 1".into()).unwrap();
+        let parse = |san| parse_san(&board, san).map(|mv| mv.to_string());
+        assert_eq!(parse("exd6").unwrap(), "e5d6");
+        assert_eq!(parse("Nce4").unwrap(), "c3e4");
+        assert_eq!(parse("Ng3e4+").unwrap(), "g3e4");
+        assert_eq!(parse("bxa8=Q#").unwrap(), "b7a8q");
+        assert_eq!(parse("b8N").unwrap(), "b7b8n");
+        assert_eq!(parse("O-O-O").unwrap(), "e1c1");
+        assert!(matches!(parse("Ne4"), Err(BoardError::AmbiguousMove(_))));
+        assert_eq!(parse("Ke2").unwrap(), "e1e2");
+        assert!(matches!(parse("Qd1"), Err(BoardError::IllegalMove(_))));
+        assert!(matches!(parse("hello"), Err(BoardError::InvalidPattern(_))));
+
+        // back to the same notation
+        for mv in board.legal_moves() {
+            assert_eq!(parse_san(&board, &to_san(&board, mv)).unwrap(), mv);
+        }
+        let san = |mv| to_san(&board, Move::try_from(mv).unwrap());
+        assert_eq!(san("c3e4"), "Nce4");
+        assert_eq!(san("b7a8q"), "bxa8=Q+");
+        assert_eq!(san("e5d6"), "exd6");
+        assert_eq!(san("e1g1"), "O-O");
+        assert_eq!(san("a1a8"), "Rxa8+");
+    }
+}
@@ -4,7 +4,7 @@ use crate::PieceError;
 type PieceResult<T> = Result<T, PieceError>;
 
 #[repr(u8)]
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Piece {
     Pawn = 1,
     Knight = 2,
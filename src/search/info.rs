@@ -0,0 +1,88 @@
+use std::{fmt, time::Duration};
+
+use super::{Bound, SearchResult, MATE, MATE_BOUND};
+use crate::Move;
+
+// progress of a running search, reported after every iteration and
+// whenever the root score falls outside the aspiration window
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchInfo {
+    pub depth: i32,
+    pub seldepth: usize,
+    // rank of the line among the best moves, starting at 1
+    pub multipv: usize,
+    pub score: i32,
+    // Lower if the search failed high, Upper if it failed low
+    pub bound: Bound,
+    pub nodes: u64,
+    pub time: Duration,
+    pub pv: Vec<Move>,
+    pub hashfull: usize,
+}
+
+// what a running search tells whoever is watching
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchEvent {
+    // after every iteration and every aspiration window failure
+    Info(SearchInfo),
+    // the root move about to be searched, numbered from 1
+    CurrentMove {
+        depth: i32,
+        mv: Move,
+        number: usize,
+        time: Duration,
+    },
+    // always the last event of a search
+    Finished(SearchResult),
+}
+
+impl SearchInfo {
+    pub fn nps(&self) -> u64 {
+        let millis = self.time.as_millis().max(1) as u64;
+        self.nodes * 1000 / millis
+    }
+}
+
+// score in UCI format, centipawns or moves to mate
+pub fn format_score(score: i32) -> String {
+    if score >= MATE_BOUND {
+        format!("mate {}", (MATE - score + 1) / 2)
+    } else if score <= -MATE_BOUND {
+        format!("mate -{}", (MATE + score) / 2)
+    } else {
+        format!("cp {}", score)
+    }
+}
+
+impl fmt::Display for SearchInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "info depth {} seldepth {} multipv {} score {}",
+            self.depth,
+            self.seldepth,
+            self.multipv,
+            format_score(self.score)
+        )?;
+        match self.bound {
+            Bound::Lower => write!(f, " lowerbound")?,
+            Bound::Upper => write!(f, " upperbound")?,
+            Bound::Exact => {}
+        }
+        write!(
+            f,
+            " nodes {} nps {} hashfull {} time {}",
+            self.nodes,
+            self.nps(),
+            self.hashfull,
+            self.time.as_millis()
+        )?;
+        if !self.pv.is_empty() {
+            write!(f, " pv")?;
+            for mv in &self.pv {
+                write!(f, " {}", mv)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1376 @@
+mod tt;
+pub use self::tt::*;
+
+mod ordering;
+pub use self::ordering::*;
+
+mod info;
+pub use self::info::*;
+
+mod time;
+pub use self::time::*;
+
+mod thread;
+pub use self::thread::*;
+
+mod skill;
+pub use self::skill::*;
+
+mod proof;
+pub use self::proof::*;
+
+mod searcher;
+pub use self::searcher::*;
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use crate::{
+    eval::{Evaluator, Network, Params, KNOWN_WIN},
+    mcts::{Mcts, MctsOptions},
+    tablebase::TablebaseResult,
+    Board, Move, Piece,
+};
+
+// alpha-beta search
+// iterative deepening principal variation search with aspiration windows,
+// a transposition table, quiescence search and the usual selectivity:
+// check extensions, null move pruning, reverse futility pruning, futility
+// pruning and late move reductions
+//
+// with more than one thread the search is lazy smp: helper threads run the
+// same search on their own copy of the board, sharing only the
+// transposition table, and the threads vote on the move to play
+//
+// for analysis another thread can hunt for mates with proof-number search,
+// a mate it proves replaces the result when alpha-beta has not found one
+
+pub const INFINITY: i32 = 32000;
+pub const MATE: i32 = 31000;
+// scores above this are mates found within MAX_PLY moves
+pub const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+
+// margins are indexed by depth
+const REVERSE_FUTILITY_MARGIN: i32 = 120;
+const FUTILITY_MARGINS: [i32; 3] = [0, 150, 300];
+
+// half width of the first aspiration window around the previous score
+const ASPIRATION_WINDOW: i32 = 25;
+const ASPIRATION_MIN_DEPTH: i32 = 4;
+
+// every selectivity feature can be switched off to compare them in matches
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SearchOptions {
+    pub null_move_pruning: bool,
+    pub late_move_reductions: bool,
+    pub reverse_futility_pruning: bool,
+    pub futility_pruning: bool,
+    pub check_extensions: bool,
+    // transposition table size in megabytes
+    pub hash_size: usize,
+    // number of best moves to find, each with its own score and pv
+    pub multi_pv: usize,
+    pub threads: usize,
+    // prove mates on a thread of their own
+    pub mate_search: bool,
+    // searches can be run by monte carlo tree search instead
+    pub algorithm: Algorithm,
+    pub mcts: MctsOptions,
+    // below the maximum level the search plays weaker on purpose
+    pub skill: Skill,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            null_move_pruning: true,
+            late_move_reductions: true,
+            reverse_futility_pruning: true,
+            futility_pruning: true,
+            check_extensions: true,
+            hash_size: 16,
+            multi_pv: 1,
+            threads: 1,
+            mate_search: false,
+            algorithm: Algorithm::AlphaBeta,
+            mcts: MctsOptions::default(),
+            skill: Skill::default(),
+        }
+    }
+}
+
+// what bounds a search besides the clock and the stop flag
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchLimits {
+    pub depth: Option<i32>,
+    // node limited searches run on one thread and always give the same
+    // result from the same state, so tests can rely on them
+    pub nodes: Option<u64>,
+    // stop when a mate in at most this many moves is found
+    pub mate: Option<i32>,
+    // search only these root moves, all if empty
+    pub search_moves: Vec<Move>,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: i32) -> SearchLimits {
+        SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        }
+    }
+}
+
+// one of the best moves of a multi pv search
+#[derive(Debug, Clone, PartialEq)]
+pub struct PvLine {
+    pub best_move: Move,
+    pub score: i32,
+    pub pv: Vec<Move>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: i32,
+    pub nodes: u64,
+    pub pv: Vec<Move>,
+    // the best moves ranked by score, the first is the same as best_move
+    pub lines: Vec<PvLine>,
+}
+
+pub type Reporter = Box<dyn FnMut(&SearchEvent) + Send>;
+
+// state shared by the threads of a search
+#[derive(Default)]
+struct Shared {
+    // set when the main thread is done, to stop the helpers
+    done: AtomicBool,
+    // nodes searched by the helpers, added in batches
+    helper_nodes: AtomicU64,
+    // the last mate proven by the mate search
+    proof: Mutex<Option<Proof>>,
+}
+
+// the helpers add their nodes to the shared count this often
+const NODE_BATCH: u64 = 1024;
+
+pub struct Search {
+    pub options: SearchOptions,
+    tt: Arc<TranspositionTable>,
+    evaluator: Evaluator,
+    ordering: MoveOrdering,
+    stop: CancellationToken,
+    shared: Arc<Shared>,
+    // 0 for the main thread
+    id: usize,
+    helpers: Vec<Search>,
+    // set when a limit of the search is reached, unlike the stop flag this
+    // belongs to the current search only
+    aborted: bool,
+    nodes: u64,
+    limits: SearchLimits,
+    // the root moves allowed by the limits
+    root_moves: Vec<Move>,
+    seldepth: usize,
+    start: Instant,
+    reporter: Option<Reporter>,
+    time: Option<TimeManager>,
+    // while set the time manager is ignored, the search is thinking on the
+    // opponent's time about the move it expects
+    pondering: Arc<AtomicBool>,
+    pondered: bool,
+    // nodes spent on every root move in the current iteration
+    root_nodes: Vec<(Move, u64)>,
+    // root moves skipped because an earlier pv line already has them
+    excluded: Vec<Move>,
+    // hashes of the positions played before the current node,
+    // used to detect repetitions
+    history: Vec<u64>,
+    // late move reductions indexed by depth and move number
+    reductions: Vec<[i32; 64]>,
+    // picks the moves of a limited skill level
+    rng: Rng,
+    // made on first use, keeping its tree between searches
+    mcts: Option<Box<Mcts>>,
+}
+
+impl Search {
+    pub fn new(options: SearchOptions) -> Search {
+        let tt = Arc::new(TranspositionTable::new(options.hash_size));
+        Search::with_table(options, tt, CancellationToken::default(), Arc::default(), 0)
+    }
+
+    fn with_table(
+        options: SearchOptions,
+        tt: Arc<TranspositionTable>,
+        stop: CancellationToken,
+        shared: Arc<Shared>,
+        id: usize,
+    ) -> Search {
+        let mut reductions = vec![[0; 64]; MAX_PLY + 1];
+        for (depth, row) in reductions.iter_mut().enumerate().skip(1) {
+            for (i, r) in row.iter_mut().enumerate().skip(1) {
+                *r = (0.75 + (depth as f64).ln() * (i as f64).ln() / 2.25) as i32;
+            }
+        }
+        Search {
+            options,
+            tt,
+            evaluator: Evaluator::default(),
+            ordering: MoveOrdering::default(),
+            stop,
+            shared,
+            id,
+            helpers: Vec::new(),
+            aborted: false,
+            nodes: 0,
+            limits: SearchLimits::default(),
+            root_moves: Vec::new(),
+            seldepth: 0,
+            start: Instant::now(),
+            reporter: None,
+            time: None,
+            pondering: Arc::default(),
+            pondered: false,
+            root_nodes: Vec::new(),
+            excluded: Vec::new(),
+            history: Vec::new(),
+            reductions,
+            rng: Rng::from_time(),
+            mcts: None,
+        }
+    }
+
+    // forget everything learned from previous searches
+    pub fn clear(&mut self) {
+        self.tt.clear();
+        self.ordering.clear();
+        self.evaluator.pawns.clear();
+        for helper in &mut self.helpers {
+            helper.clear();
+        }
+        if let Some(mcts) = self.mcts.as_mut() {
+            mcts.clear();
+        }
+    }
+
+    // the search to run with the algorithm of the options
+    pub fn searcher(&mut self) -> &mut dyn Searcher {
+        match self.options.algorithm {
+            Algorithm::AlphaBeta => self,
+            Algorithm::Mcts => {
+                let (stop, pondering) = (self.stop.clone(), self.pondering.clone());
+                let mcts = self.mcts.get_or_insert_with(|| {
+                    Box::new(Mcts::with_flags(self.options, stop, pondering))
+                });
+                mcts.options = self.options;
+                mcts.set_network(self.evaluator.network());
+                mcts.set_params(self.evaluator.params());
+                mcts.as_mut()
+            }
+        }
+    }
+
+    // cancelling the token makes a running search return as soon as possible
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.stop.clone()
+    }
+
+    // set the flag before starting a search on the opponent's time and
+    // clear it when they play the expected move to start the clock
+    pub fn ponder_flag(&self) -> Arc<AtomicBool> {
+        self.pondering.clone()
+    }
+
+    // evaluate with the network instead of the hand-crafted evaluation,
+    // the helpers get it when they start
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.evaluator.set_network(network);
+    }
+
+    // weights of the hand-crafted evaluation, like the network the helpers
+    // get them when they start
+    pub fn set_params(&mut self, params: Arc<Params>) {
+        self.evaluator.set_params(params);
+    }
+
+    // resize the transposition table, clearing it
+    pub fn set_hash_size(&mut self, megabytes: usize) {
+        self.options.hash_size = megabytes;
+        self.tt = Arc::new(TranspositionTable::new(megabytes));
+        // they still share the old table
+        self.helpers.clear();
+    }
+
+    // hashes of the positions of the game before the position to search
+    pub fn set_history(&mut self, history: Vec<u64>) {
+        self.history = history;
+    }
+
+    // make the moves picked by a limited skill level repeatable
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    // called with the progress of the search, eg. to print UCI info lines
+    pub fn set_reporter(&mut self, reporter: impl FnMut(&SearchEvent) + Send + 'static) {
+        self.reporter = Some(Box::new(reporter));
+    }
+
+    // the progress of the following searches as a stream of events,
+    // replacing the reporter
+    pub fn events(&mut self) -> mpsc::Receiver<SearchEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.set_reporter(move |event| {
+            // nobody is listening anymore
+            let _ = sender.send(event.clone());
+        });
+        receiver
+    }
+
+    pub fn search(&mut self, board: &Board, depth: i32) -> SearchResult {
+        self.search_with_limits(board, &SearchLimits::depth(depth))
+    }
+
+    // search until the time manager says to stop or a limit is reached
+    pub fn search_with_time(
+        &mut self,
+        board: &Board,
+        limits: &SearchLimits,
+        time: TimeManager,
+    ) -> SearchResult {
+        self.time = Some(time);
+        let result = self.search_with_limits(board, limits);
+        self.time = None;
+        result
+    }
+
+    pub fn search_with_limits(&mut self, board: &Board, limits: &SearchLimits) -> SearchResult {
+        // a limited skill level searches less, and finds a few good moves
+        // to choose from
+        let skill = self.options.skill;
+        let multi_pv = self.options.multi_pv;
+        self.limits.clone_from(limits);
+        if skill.is_limited() {
+            self.limits.depth = self
+                .limits
+                .depth
+                .into_iter()
+                .chain(skill.depth_limit())
+                .min();
+            self.limits.nodes = self
+                .limits
+                .nodes
+                .into_iter()
+                .chain(skill.node_limit())
+                .min();
+            self.options.multi_pv = multi_pv.max(skill.candidates());
+        }
+        let helper_count = if self.limits.nodes.is_some() {
+            0
+        } else {
+            self.options.threads.max(1) - 1
+        };
+        self.helpers.truncate(helper_count);
+        while self.helpers.len() < helper_count {
+            let options = SearchOptions {
+                multi_pv: 1,
+                ..self.options
+            };
+            let id = self.helpers.len() + 1;
+            let (tt, stop, shared) = (self.tt.clone(), self.stop.clone(), self.shared.clone());
+            self.helpers
+                .push(Search::with_table(options, tt, stop, shared, id));
+        }
+        self.shared.done.store(false, Ordering::Relaxed);
+        self.shared.helper_nodes.store(0, Ordering::Relaxed);
+        *self.shared.proof.lock().unwrap() = None;
+
+        let mut helpers = std::mem::take(&mut self.helpers);
+        let prove = self.options.mate_search && limits.nodes.is_none();
+        let (mut result, helper_results) = std::thread::scope(|scope| {
+            if prove {
+                let (stop, shared) = (self.stop.clone(), self.shared.clone());
+                let mut proof_search =
+                    ProofSearch::with_hash_size(u64::MAX, self.options.hash_size);
+                scope.spawn(move || {
+                    let stopped = || stop.is_cancelled() || shared.done.load(Ordering::Relaxed);
+                    let proof = proof_search.prove_until(board, &stopped);
+                    if proof.result != ProofResult::Unknown {
+                        *shared.proof.lock().unwrap() = Some(proof);
+                    }
+                });
+            }
+            let handles: Vec<_> = helpers
+                .iter_mut()
+                .map(|helper| {
+                    helper.options = SearchOptions {
+                        multi_pv: 1,
+                        ..self.options
+                    };
+                    helper.history.clone_from(&self.history);
+                    helper.evaluator.set_network(self.evaluator.network());
+                    helper.evaluator.set_params(self.evaluator.params());
+                    helper.limits.clone_from(&self.limits);
+                    scope.spawn(move || helper.iterate(board))
+                })
+                .collect();
+            let result = self.iterate(board);
+            self.shared.done.store(true, Ordering::Relaxed);
+            let helper_results: Vec<SearchResult> = handles
+                .into_iter()
+                .map(|h| h.join().expect("search thread panicked"))
+                .collect();
+            (result, helper_results)
+        });
+        self.helpers = helpers;
+
+        // with multi pv the lines of the main thread are what was asked for
+        if self.options.multi_pv <= 1 && !helper_results.is_empty() {
+            let mut results = vec![result];
+            results.extend(helper_results);
+            result = vote(results);
+        }
+        self.use_proof(&mut result);
+        self.options.multi_pv = multi_pv;
+        if let Some(line) = skill.pick(&result.lines, &mut self.rng) {
+            result.best_move = Some(line.best_move);
+            result.score = line.score;
+            result.pv.clone_from(&line.pv);
+        }
+        result.nodes = self.total_nodes();
+        self.emit(|| SearchEvent::Finished(result.clone()));
+        result
+    }
+
+    fn total_nodes(&self) -> u64 {
+        self.nodes + self.shared.helper_nodes.load(Ordering::Relaxed)
+    }
+
+    // iterative deepening, run by every thread
+    fn iterate(&mut self, board: &Board) -> SearchResult {
+        self.nodes = 0;
+        self.seldepth = 0;
+        self.start = Instant::now();
+        self.aborted = false;
+        self.pondered = false;
+        let board = board.clone_position();
+        self.evaluator.reset(&board);
+        let legal = board.legal_moves();
+        self.root_moves = legal
+            .iter()
+            .copied()
+            .filter(|mv| self.limits.search_moves.contains(mv))
+            .collect();
+        if self.root_moves.is_empty() {
+            self.root_moves = legal;
+        }
+        let root_moves = self.root_moves.len();
+        let multi_pv = self.options.multi_pv.clamp(1, root_moves.max(1));
+        let depth = self
+            .limits
+            .depth
+            .unwrap_or(MAX_PLY as i32)
+            .min(MAX_PLY as i32);
+        let root_history = self.history.len();
+        let mut result = SearchResult {
+            best_move: self.root_moves.first().copied(),
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            pv: Vec::new(),
+            lines: Vec::new(),
+        };
+
+        // half the helpers start one ply deeper so the threads are spread
+        // over two depths instead of all searching the same tree
+        let first_depth = 1 + self.id as i32 % 2;
+        'deepening: for d in first_depth..=depth.max(first_depth) {
+            self.root_nodes.clear();
+            self.excluded.clear();
+            let mut lines = Vec::new();
+            for index in 0..multi_pv {
+                let previous = result.lines.get(index).map_or(result.score, |l| l.score);
+                let (score, pv) = self.aspiration(&board, d, index, previous);
+                self.history.truncate(root_history);
+                if self.stopped() && d > 1 {
+                    break 'deepening;
+                }
+                let Some(&best_move) = pv.first() else { break };
+                self.excluded.push(best_move);
+                lines.push(PvLine {
+                    best_move,
+                    score,
+                    pv,
+                });
+                if self.stopped() {
+                    break;
+                }
+            }
+            // later lines can turn out better than earlier ones
+            lines.sort_by_key(|l| -l.score);
+            for (index, line) in lines.iter().enumerate() {
+                self.report(d, index, line.score, Bound::Exact, &line.pv);
+            }
+            let score = lines.first().map_or(result.score, |l| l.score);
+            result = SearchResult {
+                best_move: lines.first().map(|l| l.best_move).or(result.best_move),
+                score,
+                depth: d,
+                nodes: self.nodes,
+                pv: lines.first().map(|l| l.pv.clone()).unwrap_or_default(),
+                lines,
+            };
+            if self.id == 0 && self.use_proof(&mut result) {
+                self.report(d, 0, result.score, Bound::Exact, &result.pv);
+            }
+            if self.stopped() {
+                break;
+            }
+            if let Some(mate) = self.limits.mate {
+                if score >= MATE - (2 * mate - 1) {
+                    break;
+                }
+            }
+            let total = self.root_nodes.iter().map(|(_, n)| n).sum::<u64>().max(1);
+            let best = self
+                .root_nodes
+                .iter()
+                .filter(|(mv, _)| Some(*mv) == result.best_move)
+                .map(|(_, n)| n)
+                .sum::<u64>();
+            if let Some(time) = self.clock() {
+                time.update(result.best_move, score, best as f64 / total as f64);
+                // no need to think about a forced move
+                if time.should_stop() || root_moves == 1 {
+                    break;
+                }
+            }
+        }
+        if self.id > 0 {
+            let unbatched = self.nodes % NODE_BATCH;
+            self.shared
+                .helper_nodes
+                .fetch_add(unbatched, Ordering::Relaxed);
+        }
+        result.nodes = self.nodes;
+        result
+    }
+
+    // search the root with a narrow window around the previous score,
+    // widening it on the side the score fell out of until it fits
+    fn aspiration(
+        &mut self,
+        board: &Board,
+        depth: i32,
+        line: usize,
+        previous: i32,
+    ) -> (i32, Vec<Move>) {
+        let mut delta = ASPIRATION_WINDOW;
+        let (mut alpha, mut beta) = if depth >= ASPIRATION_MIN_DEPTH && previous.abs() < MATE_BOUND
+        {
+            (previous - delta, previous + delta)
+        } else {
+            (-INFINITY, INFINITY)
+        };
+        loop {
+            let mut pv = Vec::new();
+            let score = self.negamax(board, depth, alpha, beta, 0, &mut pv);
+            if self.stopped() {
+                return (score, pv);
+            }
+            if score <= alpha {
+                self.report(depth, line, score, Bound::Upper, &pv);
+                // pull beta down too, the real score is likely close
+                beta = (alpha + beta) / 2;
+                alpha = (score - delta).max(-INFINITY);
+            } else if score >= beta {
+                self.report(depth, line, score, Bound::Lower, &pv);
+                beta = (score + delta).min(INFINITY);
+            } else {
+                return (score, pv);
+            }
+            delta += delta / 2;
+        }
+    }
+
+    // put the mate proven by the mate search first, unless the search found
+    // a mate itself, which is the shortest one
+    fn use_proof(&self, result: &mut SearchResult) -> bool {
+        let proof = self.shared.proof.lock().unwrap().clone();
+        let Some((score, proof)) = proof.and_then(|p| Some((p.score()?, p))) else {
+            return false;
+        };
+        let Some(&best_move) = proof.pv.first() else {
+            return false;
+        };
+        if result.score.abs() >= MATE_BOUND || !self.root_moves.contains(&best_move) {
+            return false;
+        }
+        result.lines.retain(|l| l.best_move != best_move);
+        result.lines.insert(
+            0,
+            PvLine {
+                best_move,
+                score,
+                pv: proof.pv.clone(),
+            },
+        );
+        result.lines.truncate(self.options.multi_pv.max(1));
+        result.best_move = Some(best_move);
+        result.score = score;
+        result.pv = proof.pv;
+        true
+    }
+
+    fn report(&mut self, depth: i32, line: usize, score: i32, bound: Bound, pv: &[Move]) {
+        let info = SearchInfo {
+            depth,
+            seldepth: self.seldepth,
+            multipv: line + 1,
+            score,
+            bound,
+            nodes: self.total_nodes(),
+            time: self.start.elapsed(),
+            pv: pv.to_vec(),
+            hashfull: self.tt.hashfull(),
+        };
+        self.emit(|| SearchEvent::Info(info));
+    }
+
+    // events are only made when someone listens
+    fn emit(&mut self, event: impl FnOnce() -> SearchEvent) {
+        if let Some(reporter) = self.reporter.as_mut() {
+            reporter(&event());
+        }
+    }
+
+    fn stopped(&self) -> bool {
+        self.aborted || self.stop.is_cancelled() || self.shared.done.load(Ordering::Relaxed)
+    }
+
+    // the time manager, unless the search is pondering
+    // its clock starts again when pondering ends
+    fn clock(&mut self) -> Option<&mut TimeManager> {
+        if self.pondering.load(Ordering::Relaxed) {
+            self.pondered = true;
+            return None;
+        }
+        let time = self.time.as_mut()?;
+        if std::mem::take(&mut self.pondered) {
+            time.restart();
+        }
+        Some(time)
+    }
+
+    // abort the search if the hard time limit or the node limit is reached
+    fn check_limits(&mut self) {
+        if self
+            .limits
+            .nodes
+            .is_some_and(|limit| self.total_nodes() >= limit)
+        {
+            self.aborted = true;
+        }
+        if self.nodes.is_multiple_of(NODE_BATCH) {
+            if self.id > 0 {
+                self.shared
+                    .helper_nodes
+                    .fetch_add(NODE_BATCH, Ordering::Relaxed);
+            }
+            if let Some(time) = self.clock() {
+                if time.out_of_time() {
+                    self.aborted = true;
+                }
+            }
+        }
+    }
+
+    fn is_draw(&self, board: &Board) -> bool {
+        if board.halfmove_clock >= 100 {
+            return true;
+        }
+        // positions can only repeat since the last pawn move or capture
+        let hash = board.hash();
+        self.history
+            .iter()
+            .rev()
+            .take(board.halfmove_clock as usize)
+            .skip(1)
+            .step_by(2)
+            .any(|&h| h == hash)
+    }
+
+    fn negamax(
+        &mut self,
+        board: &Board,
+        mut depth: i32,
+        mut alpha: i32,
+        beta: i32,
+        ply: usize,
+        pv: &mut Vec<Move>,
+    ) -> i32 {
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+        self.check_limits();
+        if self.stopped() {
+            return 0;
+        }
+        // nodes searched with an open window can become part of the pv
+        let pv_node = beta - alpha > 1;
+        if ply > 0 && self.is_draw(board) {
+            return 0;
+        }
+        if ply >= MAX_PLY {
+            return self.evaluator.evaluate(board);
+        }
+        // the tablebases know the distance to mate
+        if ply > 0 {
+            match board.tablebase_result() {
+                Some(TablebaseResult::Win(n)) => return MATE - (ply as i32 + 2 * n as i32 - 1),
+                Some(TablebaseResult::Loss(n)) => return -MATE + ply as i32 + 2 * n as i32,
+                Some(TablebaseResult::Draw) => return 0,
+                None => {}
+            }
+        }
+
+        let in_check = board.in_check();
+        if self.options.check_extensions && in_check {
+            depth += 1;
+        }
+        if depth <= 0 {
+            return self.quiescence(board, alpha, beta, ply);
+        }
+
+        let hash = board.hash();
+        let entry = self.tt.probe(hash);
+        if let Some(entry) = entry {
+            let score = score_from_tt(entry.score, ply);
+            // exact scores are not trusted in pv nodes to keep the pv complete
+            if !pv_node && entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+        }
+
+        let static_eval = if in_check {
+            -INFINITY
+        } else {
+            self.evaluator.evaluate(board)
+        };
+
+        if !pv_node && !in_check {
+            // the position is so good that even giving back a margin per
+            // remaining ply still beats beta
+            if self.options.reverse_futility_pruning
+                && depth <= 3
+                && beta.abs() < MATE_BOUND
+                && static_eval - REVERSE_FUTILITY_MARGIN * depth >= beta
+                // known endgame wins still have to find the mate
+                && static_eval < KNOWN_WIN
+            {
+                return static_eval;
+            }
+
+            // if passing the turn still fails high, a real move will too
+            // not done with only king and pawns, where zugzwang is common
+            if self.options.null_move_pruning
+                && depth >= 3
+                && static_eval >= beta
+                && has_non_pawn_material(board)
+                && self.history.last() != Some(&0)
+            {
+                let r = 2 + depth / 4;
+                let mut child = board.clone_position();
+                child.do_null_move();
+                // a zero marks the null move in the history
+                self.history.push(0);
+                self.evaluator.push_null(&child);
+                let score = -self.negamax(
+                    &child,
+                    depth - 1 - r,
+                    -beta,
+                    -beta + 1,
+                    ply + 1,
+                    &mut Vec::new(),
+                );
+                self.evaluator.pop();
+                self.history.pop();
+                if self.stopped() {
+                    return 0;
+                }
+                if score >= beta {
+                    return if score >= MATE_BOUND { beta } else { score };
+                }
+            }
+        }
+
+        let mut moves = board.legal_moves();
+        if moves.is_empty() {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+        if ply == 0 {
+            moves.retain(|mv| self.root_moves.contains(mv) && !self.excluded.contains(mv));
+        }
+        let hash_move = entry.and_then(|e| e.best_move);
+        self.ordering.sort(board, &mut moves, hash_move, ply);
+
+        // near the leaves, quiet moves cannot raise a hopeless score
+        let futile = self.options.futility_pruning
+            && !pv_node
+            && !in_check
+            && depth < FUTILITY_MARGINS.len() as i32
+            && alpha.abs() < MATE_BOUND
+            && static_eval > -KNOWN_WIN
+            && static_eval + FUTILITY_MARGINS[depth as usize] <= alpha;
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        self.history.push(hash);
+        for (i, &mv) in moves.iter().enumerate() {
+            let quiet = is_quiet(board, mv);
+            let mut child = board.clone_position();
+            child.do_move(mv);
+            let gives_check = child.in_check();
+
+            if futile && quiet && !gives_check && i > 0 {
+                continue;
+            }
+
+            if ply == 0 && self.id == 0 {
+                let time = self.start.elapsed();
+                self.emit(|| SearchEvent::CurrentMove {
+                    depth,
+                    mv,
+                    number: i + 1,
+                    time,
+                });
+            }
+            self.evaluator.push(board, mv, &child);
+            let nodes_before = self.nodes;
+            let mut child_pv = Vec::new();
+            let mut score;
+            if i == 0 {
+                score = -self.negamax(&child, depth - 1, -beta, -alpha, ply + 1, &mut child_pv);
+            } else {
+                // late quiet moves are unlikely to be good, search them with
+                // less depth first
+                let r = if self.options.late_move_reductions
+                    && depth >= 3
+                    && i >= 3
+                    && quiet
+                    && !in_check
+                    && !gives_check
+                    && static_eval.abs() < KNOWN_WIN
+                {
+                    self.reductions[depth as usize][i.min(63)].clamp(1, depth - 1)
+                } else {
+                    0
+                };
+                // the first move is expected to be the best, only prove that
+                // the others are not better with a null window
+                score = -self.negamax(
+                    &child,
+                    depth - 1 - r,
+                    -alpha - 1,
+                    -alpha,
+                    ply + 1,
+                    &mut child_pv,
+                );
+                if score > alpha && r > 0 && !self.stopped() {
+                    child_pv.clear();
+                    score = -self.negamax(
+                        &child,
+                        depth - 1,
+                        -alpha - 1,
+                        -alpha,
+                        ply + 1,
+                        &mut child_pv,
+                    );
+                }
+                // it is better after all, find out by how much
+                if score > alpha && score < beta && !self.stopped() {
+                    child_pv.clear();
+                    score = -self.negamax(&child, depth - 1, -beta, -alpha, ply + 1, &mut child_pv);
+                }
+            }
+            self.evaluator.pop();
+
+            if self.stopped() {
+                self.history.pop();
+                return 0;
+            }
+            if ply == 0 {
+                self.root_nodes.push((mv, self.nodes - nodes_before));
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(mv);
+                if score > alpha {
+                    alpha = score;
+                    pv.clear();
+                    pv.push(mv);
+                    pv.extend(child_pv);
+                    if score >= beta {
+                        if quiet {
+                            self.ordering.update(board.turn, mv, depth, ply);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+        self.history.pop();
+
+        // every move was pruned
+        if best_move.is_none() {
+            return static_eval;
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        // the root score without the excluded moves is not the real one
+        if ply == 0 && !self.excluded.is_empty() {
+            return best_score;
+        }
+        self.tt.store(TTEntry {
+            key: hash,
+            best_move,
+            score: score_to_tt(best_score, ply),
+            depth,
+            bound,
+        });
+        best_score
+    }
+
+    // only captures and promotions are searched until the position is quiet
+    fn quiescence(&mut self, board: &Board, mut alpha: i32, beta: i32, ply: usize) -> i32 {
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+        self.check_limits();
+        if self.stopped() {
+            return 0;
+        }
+        let stand_pat = self.evaluator.evaluate(board);
+        if stand_pat >= beta || ply >= MAX_PLY {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut moves = board
+            .legal_moves()
+            .into_iter()
+            .filter(|&mv| !is_quiet(board, mv))
+            .collect::<Vec<Move>>();
+        self.ordering.sort(board, &mut moves, None, ply);
+
+        let mut best_score = stand_pat;
+        for mv in moves {
+            let mut child = board.clone_position();
+            child.do_move(mv);
+            self.evaluator.push(board, mv, &child);
+            let score = -self.quiescence(&child, -beta, -alpha, ply + 1);
+            self.evaluator.pop();
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                    if score >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+        best_score
+    }
+}
+
+// every thread votes for its best move with a weight growing with the depth
+// it reached and its score, the deepest thread with the most voted move wins
+// and the main thread, which comes first, wins ties
+fn vote(results: Vec<SearchResult>) -> SearchResult {
+    let finished: Vec<&SearchResult> = results.iter().filter(|r| r.depth > 0).collect();
+    let worst = finished.iter().map(|r| r.score).min().unwrap_or(0);
+    let mut votes = HashMap::new();
+    for r in &finished {
+        *votes.entry(r.best_move).or_insert(0) += (r.score - worst + 14) as i64 * r.depth as i64;
+    }
+    let best = results
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| r.depth > 0)
+        .max_by_key(|(i, r)| (votes[&r.best_move], r.depth, std::cmp::Reverse(*i)))
+        .map_or(0, |(i, _)| i);
+    results.into_iter().nth(best).unwrap()
+}
+
+// side to move has something else than king and pawns
+fn has_non_pawn_material(board: &Board) -> bool {
+    board.pieces().any(|(_, p)| {
+        p.get_color() == board.turn && !matches!(p.get_piece(), Piece::Pawn | Piece::King)
+    })
+}
+
+// mate scores are stored relative to the node instead of the root
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_options() -> Vec<SearchOptions> {
+        let on = SearchOptions::default();
+        let off = SearchOptions {
+            null_move_pruning: false,
+            late_move_reductions: false,
+            reverse_futility_pruning: false,
+            futility_pruning: false,
+            check_extensions: false,
+            ..on
+        };
+        vec![on, off]
+    }
+
+    #[test]
+    fn finds_mates() {
+        // back rank mate in 1 and a two rooks ladder mate in 2
+        let positions = [
+            ("6k1/5ppp/8/8/8/8/8/3R2K1 w - - 0 1", 2, MATE - 1),
+            ("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", 4, MATE - 3),
+        ];
+        for options in all_options() {
+            for (fen, depth, score) in positions {
+                let board = Board::from_fen(fen.into()).unwrap();
+                let result = Search::new(options).search(&board, depth);
+                assert_eq!(result.score, score, "{} {:?}", fen, options);
+                assert_eq!(
+                    result.pv.len() as i32,
+                    MATE - score,
+                    "{} {:?}",
+                    fen,
+                    options
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reports_every_iteration() {
+        let board = Board::from_fen(
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3".into(),
+        )
+        .unwrap();
+        let infos = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut search = Search::new(SearchOptions::default());
+        let sink = infos.clone();
+        search.set_reporter(move |event| {
+            if let SearchEvent::Info(info) = event {
+                sink.lock().unwrap().push(info.clone())
+            }
+        });
+        let result = search.search(&board, 4);
+
+        let infos = infos.lock().unwrap();
+        let exact = infos
+            .iter()
+            .filter(|i| i.bound == Bound::Exact)
+            .map(|i| i.depth)
+            .collect::<Vec<i32>>();
+        assert_eq!(exact, vec![1, 2, 3, 4]);
+        let last = infos.last().unwrap();
+        assert_eq!(last.score, result.score);
+        assert_eq!(last.pv, result.pv);
+        assert!(last.seldepth >= 4);
+    }
+
+    #[test]
+    fn event_stream() {
+        let mut search = Search::new(SearchOptions::default());
+        let events = search.events();
+        let token = search.cancellation_token();
+        let searching = std::thread::spawn(move || search.search(&Board::default(), 100));
+        let first = events.recv().unwrap();
+        assert!(matches!(
+            first,
+            SearchEvent::CurrentMove {
+                depth: 1,
+                number: 1,
+                ..
+            }
+        ));
+        // stop once the second iteration is reported, however long it took
+        while !matches!(events.recv().unwrap(), SearchEvent::Info(i) if i.depth == 2) {}
+        token.cancel();
+        let result = searching.join().unwrap();
+
+        let events: Vec<SearchEvent> = events.try_iter().collect();
+        assert_eq!(events.last(), Some(&SearchEvent::Finished(result)));
+    }
+
+    #[test]
+    fn info_line() {
+        let mut info = SearchInfo {
+            depth: 5,
+            seldepth: 9,
+            multipv: 1,
+            score: 35,
+            bound: Bound::Lower,
+            nodes: 2000,
+            time: std::time::Duration::from_millis(500),
+            pv: vec![
+                Move::try_from("e2e4").unwrap(),
+                Move::try_from("e7e5").unwrap(),
+            ],
+            hashfull: 3,
+        };
+        assert_eq!(
+            info.to_string(),
+            "info depth 5 seldepth 9 multipv 1 score cp 35 lowerbound nodes 2000 nps 4000 hashfull 3 time 500 pv e2e4 e7e5"
+        );
+        info.score = -MATE + 4;
+        info.bound = Bound::Upper;
+        assert!(info.to_string().contains("score mate -2 upperbound"));
+        assert_eq!(format_score(MATE - 3), "mate 2");
+    }
+
+    #[test]
+    fn multi_pv() {
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/8/3R2K1 w - - 0 1".into()).unwrap();
+        let options = SearchOptions {
+            multi_pv: 3,
+            ..Default::default()
+        };
+        let mut search = Search::new(options);
+        let lines = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reported = lines.clone();
+        search.set_reporter(move |event| {
+            if let SearchEvent::Info(info) = event {
+                reported.lock().unwrap().push(info.multipv)
+            }
+        });
+        let result = search.search(&board, 3);
+        assert_eq!(result.lines.len(), 3);
+        assert_eq!(result.lines[0].best_move, Move::try_from("d1d8").unwrap());
+        assert_eq!(result.lines[0].score, MATE - 1);
+        assert_eq!(result.best_move, Some(result.lines[0].best_move));
+        assert!(result.lines[1].score < MATE_BOUND);
+        assert!(result.lines.windows(2).all(|w| w[0].score >= w[1].score));
+        assert_ne!(result.lines[1].best_move, result.lines[2].best_move);
+        assert_eq!(lines.lock().unwrap()[..3], [1, 2, 3]);
+    }
+
+    #[test]
+    fn lazy_smp() {
+        let board = Board::from_fen("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1".into()).unwrap();
+        let options = SearchOptions {
+            threads: 4,
+            ..Default::default()
+        };
+        let mut search = Search::new(options);
+        let result = search.search(&board, 4);
+        assert_eq!(result.score, MATE - 3);
+        assert_eq!(search.helpers.len(), 3);
+        let main_nodes = search.nodes;
+        assert!(result.nodes > main_nodes);
+
+        // the helpers go away with the threads
+        search.options.threads = 1;
+        let result = search.search(&board, 4);
+        assert_eq!(result.score, MATE - 3);
+        assert!(search.helpers.is_empty());
+        assert_eq!(result.nodes, search.nodes);
+    }
+
+    #[test]
+    fn limits() {
+        // node limited searches are reproducible
+        let board = Board::from_fen(
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4".into(),
+        )
+        .unwrap();
+        let limits = SearchLimits {
+            nodes: Some(3000),
+            ..Default::default()
+        };
+        let mut search = Search::new(SearchOptions::default());
+        let first = search.search_with_limits(&board, &limits);
+        assert!(first.nodes >= 3000 && first.nodes < 3100);
+        search.clear();
+        assert_eq!(search.search_with_limits(&board, &limits), first);
+        let options = SearchOptions {
+            threads: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            Search::new(options).search_with_limits(&board, &limits),
+            first
+        );
+
+        // only the given root moves are searched
+        let a3 = Move::try_from("a2a3").unwrap();
+        let h3 = Move::try_from("h2h3").unwrap();
+        let limits = SearchLimits {
+            depth: Some(3),
+            search_moves: vec![a3, h3],
+            ..Default::default()
+        };
+        let result = search.search_with_limits(&board, &limits);
+        assert!(result.best_move == Some(a3) || result.best_move == Some(h3));
+
+        // the search ends as soon as the mate is found
+        let board = Board::from_fen("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1".into()).unwrap();
+        let limits = SearchLimits {
+            mate: Some(2),
+            ..Default::default()
+        };
+        let result = search.search_with_limits(&board, &limits);
+        assert_eq!(result.score, MATE - 3);
+        assert!(result.depth <= 4);
+    }
+
+    #[test]
+    fn skill_level() {
+        let board = Board::default();
+        let options = SearchOptions {
+            skill: Skill::new(0),
+            ..Default::default()
+        };
+        let mut search = Search::new(options);
+        search.set_seed(1);
+        let mut played = Vec::new();
+        for _ in 0..20 {
+            let result = search.search(&board, 6);
+            // the weakest level looks one move ahead at most
+            assert_eq!(result.depth, 1);
+            assert_eq!(result.lines.len(), 5);
+            assert!(result
+                .lines
+                .iter()
+                .any(|l| Some(l.best_move) == result.best_move));
+            played.push(result.best_move.unwrap());
+        }
+        played.sort_by_key(|mv| mv.to_string());
+        played.dedup();
+        assert!(played.len() > 1);
+        assert_eq!(search.options.multi_pv, 1);
+
+        // the node limit of the skill keeps the search on one thread
+        search.options.threads = 4;
+        search.search(&board, 6);
+        assert!(search.helpers.is_empty());
+
+        // a mate is still found
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/8/3R2K1 w - - 0 1".into()).unwrap();
+        let result = search.search(&board, 6);
+        assert_eq!(result.best_move, Some(Move::try_from("d1d8").unwrap()));
+    }
+
+    #[test]
+    fn stops_on_time() {
+        let board = Board::default();
+        let tc = TimeControl {
+            movetime: Some(std::time::Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let clock = Box::new(SystemClock::default());
+        let time = TimeManager::new(&tc, board.turn, Default::default(), clock).unwrap();
+        let start = Instant::now();
+        let limits = SearchLimits::default();
+        let result = Search::new(SearchOptions::default()).search_with_time(&board, &limits, time);
+        assert!(start.elapsed() < std::time::Duration::from_secs(2));
+        assert!(result.best_move.is_some());
+    }
+
+    #[test]
+    fn stalemate_is_a_draw() {
+        let board = Board::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1".into()).unwrap();
+        let result = Search::new(SearchOptions::default()).search(&board, 2);
+        assert_eq!(result.best_move, None);
+        assert_eq!(result.score, 0);
+    }
+
+    #[test]
+    fn no_null_move_with_only_pawns() {
+        let board = Board::from_fen("8/8/4k3/4p3/4P3/4K3/8/8 w - - 0 1".into()).unwrap();
+        assert!(!has_non_pawn_material(&board));
+        let board = Board::from_fen("8/8/4k3/4p3/4P3/4K3/8/7N w - - 0 1".into()).unwrap();
+        assert!(has_non_pawn_material(&board));
+    }
+
+    #[test]
+    fn mate_search() {
+        let board = Board::from_fen(
+            "r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - 0 1".into(),
+        )
+        .unwrap();
+        let mut search = Search::new(SearchOptions {
+            mate_search: true,
+            ..SearchOptions::default()
+        });
+        let result = search.search_with_limits(
+            &board,
+            &SearchLimits {
+                mate: Some(4),
+                ..Default::default()
+            },
+        );
+        assert!(result.score >= MATE_BOUND, "{:?}", result);
+
+        // a proven mate replaces a score without one, not a mate found by
+        // the search
+        let proof = prove(&board, 100_000);
+        *search.shared.proof.lock().unwrap() = Some(proof.clone());
+        search.root_moves = board.legal_moves();
+        let mut result = SearchResult {
+            best_move: None,
+            score: 100,
+            depth: 1,
+            nodes: 0,
+            pv: Vec::new(),
+            lines: Vec::new(),
+        };
+        assert!(search.use_proof(&mut result));
+        assert_eq!(result.best_move, proof.pv.first().copied());
+        assert_eq!(Some(result.score), proof.score());
+        assert_eq!(result.lines.len(), 1);
+        result.score = MATE - 5;
+        assert!(!search.use_proof(&mut result));
+    }
+}
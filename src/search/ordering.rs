@@ -0,0 +1,97 @@
+use crate::{Board, Color, Move, Piece};
+
+// move ordering
+// searching the best move first makes alpha-beta cut off much earlier
+// order: hash move, captures by most valuable victim / least valuable
+// attacker, promotions, killer moves, then quiet moves by history score
+
+pub const MAX_PLY: usize = 128;
+
+const HASH_MOVE: i32 = 1_000_000;
+const CAPTURE: i32 = 100_000;
+const PROMOTION: i32 = 90_000;
+const KILLER: i32 = 80_000;
+
+const VALUES: [i32; 6] = [1, 3, 3, 5, 9, 20];
+
+pub struct MoveOrdering {
+    // two quiet moves per ply that caused a beta cutoff in sibling nodes
+    killers: Vec<[Option<Move>; 2]>,
+    // how often a quiet move caused a cutoff, indexed by color, from and to
+    history: Vec<[[i32; 64]; 64]>,
+}
+
+impl Default for MoveOrdering {
+    fn default() -> Self {
+        MoveOrdering {
+            killers: vec![[None; 2]; MAX_PLY + 1],
+            history: vec![[[0; 64]; 64]; 2],
+        }
+    }
+}
+
+impl MoveOrdering {
+    pub fn clear(&mut self) {
+        *self = MoveOrdering::default();
+    }
+
+    pub fn sort(&self, board: &Board, moves: &mut [Move], hash_move: Option<Move>, ply: usize) {
+        moves.sort_by_cached_key(|&mv| -self.score(board, mv, hash_move, ply));
+    }
+
+    fn score(&self, board: &Board, mv: Move, hash_move: Option<Move>, ply: usize) -> i32 {
+        if Some(mv) == hash_move {
+            return HASH_MOVE;
+        }
+        if let Some(victim) = captured(board, mv) {
+            let attacker = board[mv.from].get_piece();
+            return CAPTURE + VALUES[victim as usize - 1] * 100 - VALUES[attacker as usize - 1];
+        }
+        if let Some(p) = mv.promotion {
+            return PROMOTION + VALUES[p as usize - 1];
+        }
+        let killers = &self.killers[ply.min(MAX_PLY)];
+        if killers[0] == Some(mv) {
+            return KILLER;
+        }
+        if killers[1] == Some(mv) {
+            return KILLER - 1;
+        }
+        self.history[board.turn as usize][usize::from(mv.from)][usize::from(mv.to)]
+    }
+
+    // a quiet move caused a beta cutoff
+    pub fn update(&mut self, color: Color, mv: Move, depth: i32, ply: usize) {
+        let killers = &mut self.killers[ply.min(MAX_PLY)];
+        if killers[0] != Some(mv) {
+            killers[1] = killers[0];
+            killers[0] = Some(mv);
+        }
+        let entry = &mut self.history[color as usize][usize::from(mv.from)][usize::from(mv.to)];
+        *entry += depth * depth;
+        // keep history below killers and captures
+        if *entry > KILLER / 2 {
+            self.history
+                .iter_mut()
+                .flatten()
+                .flatten()
+                .for_each(|h| *h /= 2);
+        }
+    }
+}
+
+// piece captured by a move, if any
+pub fn captured(board: &Board, mv: Move) -> Option<Piece> {
+    let target = board[mv.to];
+    if !target.is_blank() {
+        Some(target.get_piece())
+    } else if board[mv.from].is_pawn() && Some(mv.to) == board.en_passant {
+        Some(Piece::Pawn)
+    } else {
+        None
+    }
+}
+
+pub fn is_quiet(board: &Board, mv: Move) -> bool {
+    mv.promotion.is_none() && captured(board, mv).is_none()
+}
@@ -0,0 +1,413 @@
+use std::{collections::HashMap, fmt};
+
+use super::{MATE, MAX_PLY};
+use crate::{Board, Color, Move, Piece};
+
+// depth-first proof-number search
+// proves that the attacker mates against any defence, or that it cannot,
+// without a depth limit: every node has a proof number, how many leaves
+// still have to be proven for the attacker to mate, and a disproof number,
+// how many for the defender to escape, and the search always expands the
+// most proving node below thresholds handed down from the root. the numbers
+// are kept as phi and delta, the proof and disproof numbers for the side to
+// move, so attacker and defender nodes are handled alike
+//
+// the tree lives in a hash table, positions repeated on the current path
+// count as draws without being stored, which can be wrong when the same
+// position is reached through another path, the usual price of df-pn
+
+// proof numbers saturate here, a node with phi at infinity is lost for the
+// side to move
+const INFINITE: u32 = u32::MAX / 2;
+
+// the stop callback is checked this often
+const STOP_CHECK: u64 = 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ProofResult {
+    // the side to move mates in at most so many moves
+    Win(u32),
+    // the side to move is mated in at most so many moves
+    Loss(u32),
+    // neither proven within the limits
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proof {
+    pub result: ProofResult,
+    // the mating line with the longest defence found, empty when unknown
+    pub pv: Vec<Move>,
+    pub nodes: u64,
+}
+
+impl Proof {
+    // the result as a search score
+    pub fn score(&self) -> Option<i32> {
+        match self.result {
+            ProofResult::Win(moves) => Some(MATE - (2 * moves as i32 - 1)),
+            ProofResult::Loss(moves) => Some(-MATE + 2 * moves as i32),
+            ProofResult::Unknown => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Entry {
+    phi: u32,
+    delta: u32,
+    // plies to the end of the proof once phi or delta is 0
+    plies: u32,
+}
+
+const UNEXPLORED: Entry = Entry {
+    phi: 1,
+    delta: 1,
+    plies: 0,
+};
+
+// the side to move has reached its goal, or failed to
+const WON: Entry = Entry {
+    phi: 0,
+    delta: INFINITE,
+    plies: 0,
+};
+const LOST: Entry = Entry {
+    phi: INFINITE,
+    delta: 0,
+    plies: 0,
+};
+
+pub struct ProofSearch {
+    table: HashMap<u64, Entry>,
+    attacker: Color,
+    // hashes of the positions on the current path
+    path: Vec<u64>,
+    pub nodes: u64,
+    node_limit: u64,
+    // the table is never pruned, the search gives up when it is full
+    max_entries: usize,
+    aborted: bool,
+}
+
+impl ProofSearch {
+    pub fn new(node_limit: u64, max_entries: usize) -> ProofSearch {
+        ProofSearch {
+            table: HashMap::new(),
+            attacker: Color::White,
+            path: Vec::new(),
+            nodes: 0,
+            node_limit,
+            max_entries,
+            aborted: false,
+        }
+    }
+
+    // a table of about this many megabytes
+    pub fn with_hash_size(node_limit: u64, megabytes: usize) -> ProofSearch {
+        let entry = std::mem::size_of::<(u64, Entry)>() * 2;
+        ProofSearch::new(node_limit, megabytes * 1024 * 1024 / entry)
+    }
+
+    pub fn prove(&mut self, board: &Board) -> Proof {
+        self.prove_until(board, &|| false)
+    }
+
+    // prove a mate for the side to move and, failing that, for the other
+    // side, until the limits are reached or stopped returns true
+    pub fn prove_until(&mut self, board: &Board, stopped: &dyn Fn() -> bool) -> Proof {
+        self.nodes = 0;
+        self.aborted = false;
+        let board = board.clone_position();
+        for attacker in [board.turn, board.turn.opposite()] {
+            self.attacker = attacker;
+            self.table.clear();
+            let (root, _) = self.mid(&board, INFINITE, INFINITE, stopped);
+            if self.aborted {
+                break;
+            }
+            let plies = root.plies;
+            let result = match (attacker == board.turn, root.phi, root.delta) {
+                (true, 0, _) => ProofResult::Win(plies.div_ceil(2)),
+                (false, _, 0) => ProofResult::Loss(plies / 2),
+                _ => continue,
+            };
+            return Proof {
+                result,
+                pv: self.pv(&board),
+                nodes: self.nodes,
+            };
+        }
+        Proof {
+            result: ProofResult::Unknown,
+            pv: Vec::new(),
+            nodes: self.nodes,
+        }
+    }
+
+    // the value of a position decided without its moves, mates and
+    // stalemates are found when it is expanded
+    fn terminal(&self, board: &Board) -> Option<Entry> {
+        let draw = if board.turn == self.attacker {
+            LOST
+        } else {
+            WON
+        };
+        // a bare king cannot mate
+        let mating_material = board
+            .pieces()
+            .any(|(_, p)| p.get_color() == self.attacker && p.get_piece() != Piece::King);
+        if board.halfmove_clock >= 100 || !mating_material {
+            return Some(draw);
+        }
+        None
+    }
+
+    // what is known of a position, and whether it only is a draw because
+    // it repeats the path or is too deep to search
+    fn entry(&self, board: &Board, hash: u64, terminal: Option<Entry>) -> (Entry, bool) {
+        if let Some(entry) = terminal {
+            return (entry, false);
+        }
+        // the attacker gives up on lines this long, the recursion would
+        // otherwise follow them as deep as the stack goes
+        if self.path.len() >= MAX_PLY || self.path.contains(&hash) {
+            let draw = if board.turn == self.attacker {
+                LOST
+            } else {
+                WON
+            };
+            return (draw, true);
+        }
+        let entry = self.table.get(&hash).copied();
+        (entry.unwrap_or(UNEXPLORED), false)
+    }
+
+    fn lookup(&self, board: &Board) -> (Entry, bool) {
+        self.entry(board, board.hash(), self.terminal(board))
+    }
+
+    // expand the position until its phi or delta reaches the threshold,
+    // also telling whether the result depends on the path
+    //
+    // only the attacker's failures can come from repetitions, they are
+    // returned to the parent but not stored, so the same position reached
+    // another way is searched again
+    fn mid(
+        &mut self,
+        board: &Board,
+        phi: u32,
+        delta: u32,
+        stopped: &dyn Fn() -> bool,
+    ) -> (Entry, bool) {
+        self.nodes += 1;
+        if self.nodes >= self.node_limit
+            || self.table.len() >= self.max_entries
+            || (self.nodes.is_multiple_of(STOP_CHECK) && stopped())
+        {
+            self.aborted = true;
+        }
+        if let Some(entry) = self.terminal(board) {
+            return (entry, false);
+        }
+        let hash = board.hash();
+        let moves = board.legal_moves();
+        if moves.is_empty() {
+            let entry = match (board.in_check(), board.turn == self.attacker) {
+                (false, false) => WON,
+                _ => LOST,
+            };
+            self.table.insert(hash, entry);
+            return (entry, false);
+        }
+        // hashed and checked once, the loop below looks at them many times
+        let children: Vec<(Board, u64, Option<Entry>)> = moves
+            .into_iter()
+            .map(|mv| {
+                let mut child = board.clone_position();
+                child.do_move(mv);
+                let (hash, terminal) = (child.hash(), self.terminal(&child));
+                (child, hash, terminal)
+            })
+            .collect();
+        // results of the children that could not be stored
+        let mut unstored: Vec<Option<(Entry, bool)>> = vec![None; children.len()];
+        self.path.push(hash);
+        let (mut entry, mut dependent);
+        loop {
+            // the side to move wins with one child lost for the other side
+            // and has to lose them all to lose
+            entry = Entry {
+                phi: INFINITE,
+                delta: 0,
+                plies: 0,
+            };
+            let (mut best, mut second) = (0, INFINITE);
+            let mut best_phi = 0;
+            let mut won = u32::MAX;
+            let mut lost = 0;
+            let (mut won_dependent, mut lost_dependent) = (false, false);
+            for (i, (child, child_hash, terminal)) in children.iter().enumerate() {
+                let (e, child_dependent) =
+                    unstored[i].unwrap_or_else(|| self.entry(child, *child_hash, *terminal));
+                if e.delta < entry.phi {
+                    second = entry.phi;
+                    entry.phi = e.delta;
+                    best = i;
+                    best_phi = e.phi;
+                } else if e.delta < second {
+                    second = e.delta;
+                }
+                entry.delta = entry.delta.saturating_add(e.phi).min(INFINITE);
+                if e.delta == 0 && e.plies + 1 < won {
+                    won = e.plies + 1;
+                    won_dependent = child_dependent;
+                }
+                lost = lost.max(e.plies + 1);
+                lost_dependent |= child_dependent;
+            }
+            dependent = false;
+            if entry.phi == 0 {
+                entry.plies = won;
+                dependent = won_dependent;
+            } else if entry.delta == 0 {
+                entry.plies = lost;
+                dependent = lost_dependent;
+            }
+            if !dependent {
+                self.table.insert(hash, entry);
+            }
+            if entry.phi >= phi || entry.delta >= delta || self.aborted {
+                break;
+            }
+            // the child may use what the other children leave of the delta
+            // threshold and must stay below the second best
+            let child_phi = (delta - entry.delta).saturating_add(best_phi).min(INFINITE);
+            let child_delta = phi.min(second.saturating_add(1)).min(INFINITE);
+            let result = self.mid(&children[best].0, child_phi, child_delta, stopped);
+            unstored[best] = result.1.then_some(result);
+        }
+        self.path.pop();
+        (entry, dependent)
+    }
+
+    // follow the proof from the root, the quickest win for the winner and
+    // the longest defence for the loser
+    fn pv(&mut self, board: &Board) -> Vec<Move> {
+        let mut pv = Vec::new();
+        let mut board = board.clone_position();
+        while pv.len() < MAX_PLY {
+            let (entry, _) = self.lookup(&board);
+            let children = board.legal_moves().into_iter().map(|mv| {
+                let mut child = board.clone_position();
+                child.do_move(mv);
+                (mv, self.lookup(&child).0.plies, child)
+            });
+            let next = if entry.phi == 0 {
+                children
+                    .filter(|(_, _, child)| self.lookup(child).0.delta == 0)
+                    .min_by_key(|(_, plies, _)| *plies)
+            } else if entry.delta == 0 {
+                children
+                    .filter(|(_, _, child)| self.lookup(child).0.phi == 0)
+                    .max_by_key(|(_, plies, _)| *plies)
+            } else {
+                None
+            };
+            let Some((mv, _, child)) = next else { break };
+            self.path.push(board.hash());
+            pv.push(mv);
+            board = child;
+        }
+        self.path.clear();
+        pv
+    }
+}
+
+// prove or refute a mate for either side within the nodes
+pub fn prove(board: &Board, nodes: u64) -> Proof {
+    ProofSearch::new(nodes, usize::MAX).prove(board)
+}
+
+impl fmt::Display for Proof {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.result {
+            ProofResult::Win(moves) => write!(f, "Win, mate in {}", moves)?,
+            ProofResult::Loss(moves) => write!(f, "Loss, mated in {}", moves)?,
+            ProofResult::Unknown => write!(f, "Unknown")?,
+        }
+        writeln!(f, " ({} nodes)", self.nodes)?;
+        if !self.pv.is_empty() {
+            write!(f, "pv")?;
+            for mv in &self.pv {
+                write!(f, " {}", mv)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BoardStatus;
+
+    fn prove_fen(fen: &str) -> Proof {
+        prove(&Board::from_fen(fen.into()).unwrap(), 1_000_000)
+    }
+
+    fn play(fen: &str, pv: &[Move]) -> Board {
+        let mut board = Board::from_fen(fen.into()).unwrap();
+        for &mv in pv {
+            board.play(mv).unwrap();
+        }
+        board
+    }
+
+    #[test]
+    fn proves_mates() {
+        // the two rooks ladder mate in 2
+        let fen = "7k/8/8/8/8/8/R7/1R4K1 w - - 0 1";
+        let proof = prove_fen(fen);
+        assert_eq!(proof.result, ProofResult::Win(2));
+        assert_eq!(proof.score(), Some(MATE - 3));
+        assert_eq!(
+            play(fen, &proof.pv).status,
+            BoardStatus::Checkmate(Color::Black)
+        );
+
+        // and for the side about to be mated, only in at most so many moves
+        let fen = "7k/8/8/8/8/8/R7/1R4K1 b - - 0 1";
+        let proof = prove_fen(fen);
+        let ProofResult::Loss(moves) = proof.result else {
+            panic!("{}", proof)
+        };
+        assert_eq!(proof.pv.len() as u32, 2 * moves);
+        assert_eq!(
+            play(fen, &proof.pv).status,
+            BoardStatus::Checkmate(Color::Black)
+        );
+
+        // a king hunt with black sacrificing the bishop
+        let fen = "r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - 0 1";
+        let proof = prove_fen(fen);
+        assert!(matches!(proof.result, ProofResult::Win(_)), "{}", proof);
+        assert_eq!(
+            play(fen, &proof.pv).status,
+            BoardStatus::Checkmate(Color::White)
+        );
+
+        // a king alone cannot mate
+        assert_eq!(
+            prove_fen("8/8/8/4k3/8/8/8/4K3 w - - 0 1").result,
+            ProofResult::Unknown
+        );
+
+        // nor can the rooks with the path already as deep as it goes
+        let mut search = ProofSearch::new(1_000_000, usize::MAX);
+        search.path = vec![0; MAX_PLY];
+        let board = Board::from_fen("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1".into()).unwrap();
+        assert_eq!(search.prove(&board).result, ProofResult::Unknown);
+    }
+}
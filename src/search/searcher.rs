@@ -0,0 +1,57 @@
+use super::{Reporter, Search, SearchLimits, SearchResult, TimeManager};
+use crate::Board;
+
+// what the protocol frontends need of a search algorithm, so they can play
+// with alpha-beta or monte carlo tree search alike
+pub trait Searcher: Send {
+    fn search_with_limits(&mut self, board: &Board, limits: &SearchLimits) -> SearchResult;
+
+    fn search_with_time(
+        &mut self,
+        board: &Board,
+        limits: &SearchLimits,
+        time: TimeManager,
+    ) -> SearchResult;
+
+    // hashes of the positions of the game before the position to search
+    fn set_history(&mut self, history: Vec<u64>);
+
+    fn set_reporter(&mut self, reporter: Reporter);
+
+    // forget everything learned from previous searches
+    fn clear(&mut self);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    #[default]
+    AlphaBeta,
+    Mcts,
+}
+
+impl Searcher for Search {
+    fn search_with_limits(&mut self, board: &Board, limits: &SearchLimits) -> SearchResult {
+        Search::search_with_limits(self, board, limits)
+    }
+
+    fn search_with_time(
+        &mut self,
+        board: &Board,
+        limits: &SearchLimits,
+        time: TimeManager,
+    ) -> SearchResult {
+        Search::search_with_time(self, board, limits, time)
+    }
+
+    fn set_history(&mut self, history: Vec<u64>) {
+        Search::set_history(self, history)
+    }
+
+    fn set_reporter(&mut self, reporter: Reporter) {
+        self.reporter = Some(reporter);
+    }
+
+    fn clear(&mut self) {
+        Search::clear(self)
+    }
+}
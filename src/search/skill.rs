@@ -0,0 +1,195 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{PvLine, MATE_BOUND};
+
+// playing strength below the full strength of the engine
+// weaker levels search shallower and fewer nodes, then pick among the best
+// few moves at random, preferring moves that lose little. bad moves are
+// possible but moves losing more than a level dependent margin never are
+
+pub const MAX_SKILL_LEVEL: u8 = 20;
+// the range of UCI_Elo, roughly calibrated for the weakest and strongest
+// limited levels
+pub const MIN_ELO: u32 = 800;
+pub const MAX_ELO: u32 = 2200;
+
+// number of moves considered by the weaker levels
+const CANDIDATES: usize = 5;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Skill {
+    level: u8,
+}
+
+impl Default for Skill {
+    fn default() -> Self {
+        Skill {
+            level: MAX_SKILL_LEVEL,
+        }
+    }
+}
+
+impl Skill {
+    pub fn new(level: u8) -> Skill {
+        Skill {
+            level: level.min(MAX_SKILL_LEVEL),
+        }
+    }
+
+    pub fn from_elo(elo: u32) -> Skill {
+        let elo = elo.clamp(MIN_ELO, MAX_ELO);
+        let level = (elo - MIN_ELO) * (MAX_SKILL_LEVEL as u32 - 1) / (MAX_ELO - MIN_ELO);
+        Skill::new(level as u8)
+    }
+
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    // full strength plays the best move it finds
+    pub fn is_limited(&self) -> bool {
+        self.level < MAX_SKILL_LEVEL
+    }
+
+    pub fn depth_limit(&self) -> Option<i32> {
+        self.is_limited().then_some(1 + self.level as i32 / 2)
+    }
+
+    pub fn node_limit(&self) -> Option<u64> {
+        self.is_limited()
+            .then(|| (1000.0 * 1.4f64.powi(self.level as i32)) as u64)
+    }
+
+    pub fn candidates(&self) -> usize {
+        if self.is_limited() {
+            CANDIDATES
+        } else {
+            1
+        }
+    }
+
+    // how willing the level is to play worse moves, in centipawns
+    fn temperature(&self) -> f64 {
+        200.0 - 10.0 * self.level as f64
+    }
+
+    // moves losing more than this are never played
+    fn max_loss(&self) -> i32 {
+        400 - 20 * self.level as i32
+    }
+
+    // pick one of the lines, sorted best first, with a chance falling
+    // exponentially with how much worse than the best line it is
+    pub fn pick<'a>(&self, lines: &'a [PvLine], rng: &mut Rng) -> Option<&'a PvLine> {
+        let best = lines.first()?;
+        if !self.is_limited() {
+            return Some(best);
+        }
+        let candidates: Vec<(&PvLine, f64)> = lines
+            .iter()
+            .filter(|l| best.score < MATE_BOUND || l.score == best.score)
+            .map(|l| (l, best.score - l.score))
+            .filter(|&(_, loss)| loss <= self.max_loss())
+            .map(|(l, loss)| (l, (-loss as f64 / self.temperature()).exp()))
+            .collect();
+        let total: f64 = candidates.iter().map(|(_, w)| w).sum();
+        let mut choice = rng.next_f64() * total;
+        for (line, weight) in &candidates {
+            if choice < *weight {
+                return Some(line);
+            }
+            choice -= weight;
+        }
+        Some(best)
+    }
+}
+
+// xorshift64*, good enough to pick moves
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed.max(1))
+    }
+
+    // seeded from the clock, so games differ
+    pub fn from_time() -> Rng {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(1, |d| d.as_nanos() as u64);
+        Rng::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{search::MATE, Move};
+
+    fn lines(scores: &[i32]) -> Vec<PvLine> {
+        let moves = ["e2e4", "d2d4", "g1f3", "c2c4", "b2b3"];
+        scores
+            .iter()
+            .zip(moves)
+            .map(|(&score, mv)| PvLine {
+                best_move: Move::try_from(mv).unwrap(),
+                score,
+                pv: vec![Move::try_from(mv).unwrap()],
+            })
+            .collect()
+    }
+
+    // how often each line is picked out of 1000 tries
+    fn picks(skill: Skill, lines: &[PvLine]) -> Vec<usize> {
+        let mut rng = Rng::new(7);
+        let mut counts = vec![0; lines.len()];
+        for _ in 0..1000 {
+            let line = skill.pick(lines, &mut rng).unwrap();
+            counts[lines.iter().position(|l| l == line).unwrap()] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn levels() {
+        assert!(!Skill::default().is_limited());
+        assert_eq!(Skill::default().depth_limit(), None);
+        assert_eq!(Skill::new(50), Skill::default());
+        assert_eq!(Skill::new(0).depth_limit(), Some(1));
+        assert!(Skill::new(3).node_limit() < Skill::new(10).node_limit());
+        assert_eq!(Skill::from_elo(0), Skill::new(0));
+        assert_eq!(Skill::from_elo(MAX_ELO), Skill::new(MAX_SKILL_LEVEL - 1));
+        assert!(Skill::from_elo(1500).level() > Skill::from_elo(1000).level());
+    }
+
+    #[test]
+    fn picks_by_score_loss() {
+        let quiet = lines(&[50, 0, -60, -150, -500]);
+        // full strength always plays the best move
+        assert_eq!(picks(Skill::default(), &quiet), [1000, 0, 0, 0, 0]);
+
+        let weak = picks(Skill::new(0), &quiet);
+        let strong = picks(Skill::new(15), &quiet);
+        // worse moves are played less often, blunders never
+        assert!(weak[0] > weak[1] && weak[1] > weak[2] && weak[2] > weak[3]);
+        assert_eq!(weak[4], 0);
+        assert_eq!(strong[3], 0);
+        assert!(strong[0] > weak[0]);
+
+        // a mate is never missed
+        assert_eq!(picks(Skill::new(0), &lines(&[MATE - 1, 900])), [1000, 0]);
+    }
+}
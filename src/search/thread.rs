@@ -0,0 +1,89 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use super::Search;
+
+// cancels a running search from any thread
+// the search never resets it, that is up to whoever cancelled it
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+// runs searches in the background so the protocol loops stay responsive
+// the search is moved into the thread and handed back when it finishes,
+// keeping its hash table and history between moves
+pub struct SearchThread {
+    search: Option<Search>,
+    handle: Option<JoinHandle<Search>>,
+    stop: CancellationToken,
+}
+
+impl SearchThread {
+    pub fn new(search: Search) -> SearchThread {
+        SearchThread {
+            stop: search.cancellation_token(),
+            search: Some(search),
+            handle: None,
+        }
+    }
+
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.stop.clone()
+    }
+
+    // is a search running or waiting to be stopped
+    pub fn is_searching(&self) -> bool {
+        self.handle.as_ref().is_some_and(|h| !h.is_finished())
+    }
+
+    // wait for the running search to finish and return the idle search
+    pub fn wait(&mut self) -> &mut Search {
+        if let Some(handle) = self.handle.take() {
+            self.search = Some(handle.join().expect("search thread panicked"));
+        }
+        self.search.as_mut().unwrap()
+    }
+
+    // stop the running search and wait for it
+    pub fn stop(&mut self) -> &mut Search {
+        if self.handle.is_some() {
+            self.stop.cancel();
+        }
+        self.wait()
+    }
+
+    // run f with the search on a new thread, after the previous one finished
+    pub fn start(&mut self, f: impl FnOnce(&mut Search) + Send + 'static) {
+        self.wait();
+        self.stop.reset();
+        let mut search = self.search.take().unwrap();
+        self.handle = Some(thread::spawn(move || {
+            f(&mut search);
+            search
+        }));
+    }
+}
+
+impl Drop for SearchThread {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{Color, Move};
+
+// time management
+// every move gets a soft limit, checked between iterations, and a hard limit
+// the search is aborted at. the soft limit is stretched when the best move
+// keeps changing or the score drops, and shrunk when one move is clearly best
+
+// moves left in the game when the time control does not say
+const DEFAULT_MOVES_TO_GO: u32 = 25;
+// the hard limit is this many times the soft limit
+const HARD_LIMIT_FACTOR: u32 = 4;
+
+pub trait Clock: Send {
+    // time since the search started
+    fn elapsed(&self) -> Duration;
+}
+
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+// clock that only moves when told to, for tests
+#[derive(Debug, Clone, Default)]
+pub struct FakeClock {
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl FakeClock {
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().unwrap() += by;
+    }
+}
+
+impl Clock for FakeClock {
+    fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+// clock state sent by the GUI
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct TimeControl {
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    pub movestogo: Option<u32>,
+    // think exactly this long
+    pub movetime: Option<Duration>,
+}
+
+pub struct TimeManager {
+    clock: Box<dyn Clock>,
+    soft: Duration,
+    hard: Duration,
+    // multiplier of the soft limit, updated after every iteration
+    scale: f64,
+    best_move: Option<Move>,
+    // how often the best move changed recently, decays every iteration
+    instability: f64,
+    // number of iterations in a row with the same best move
+    stable_iterations: u32,
+    previous_score: Option<i32>,
+    // clock time before the last restart
+    offset: Duration,
+}
+
+impl TimeManager {
+    // None if the time control does not limit the search
+    pub fn new(
+        tc: &TimeControl,
+        color: Color,
+        move_overhead: Duration,
+        clock: Box<dyn Clock>,
+    ) -> Option<TimeManager> {
+        let (soft, hard) = if let Some(movetime) = tc.movetime {
+            let time = movetime.saturating_sub(move_overhead);
+            (time, time)
+        } else {
+            let (time, inc) = match color {
+                Color::White => (tc.wtime?, tc.winc.unwrap_or_default()),
+                Color::Black => (tc.btime?, tc.binc.unwrap_or_default()),
+            };
+            let moves = tc
+                .movestogo
+                .unwrap_or(DEFAULT_MOVES_TO_GO)
+                .clamp(1, DEFAULT_MOVES_TO_GO);
+            // never plan to use more than what is left after the overhead
+            let available = time.saturating_sub(move_overhead);
+            let soft = (time / moves + inc * 3 / 4).min(available / 2);
+            let hard = (soft * HARD_LIMIT_FACTOR).min(available * 3 / 4).max(soft);
+            (soft.saturating_sub(move_overhead), hard)
+        };
+        Some(TimeManager {
+            clock,
+            soft,
+            hard,
+            scale: 1.0,
+            best_move: None,
+            instability: 0.0,
+            stable_iterations: 0,
+            previous_score: None,
+            offset: Duration::ZERO,
+        })
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.clock.elapsed().saturating_sub(self.offset)
+    }
+
+    // start counting from zero again, eg. when a ponder search
+    // becomes the real one
+    pub fn restart(&mut self) {
+        self.offset = self.clock.elapsed();
+    }
+
+    pub fn soft_limit(&self) -> Duration {
+        self.soft.mul_f64(self.scale).min(self.hard)
+    }
+
+    pub fn hard_limit(&self) -> Duration {
+        self.hard
+    }
+
+    // called after every finished iteration
+    // best_move_effort is the fraction of the nodes spent on the best move
+    pub fn update(&mut self, best_move: Option<Move>, score: i32, best_move_effort: f64) {
+        self.instability *= 0.5;
+        if best_move != self.best_move {
+            if self.best_move.is_some() {
+                self.instability += 1.0;
+            }
+            self.stable_iterations = 0;
+            self.best_move = best_move;
+        } else {
+            self.stable_iterations += 1;
+        }
+
+        // the best move keeps changing, think longer
+        let mut scale = 1.0 + self.instability * 0.5;
+
+        // the score is falling, look for something better
+        if let Some(previous) = self.previous_score {
+            let drop = (previous - score).clamp(0, 100);
+            scale *= 1.0 + drop as f64 / 200.0;
+        }
+        self.previous_score = Some(score);
+
+        // nearly all the effort went into one move that keeps being best
+        if self.stable_iterations >= 3 && best_move_effort > 0.9 {
+            scale *= 0.5;
+        }
+        self.scale = scale;
+    }
+
+    // should another iteration be started
+    pub fn should_stop(&self) -> bool {
+        self.elapsed() >= self.soft_limit()
+    }
+
+    // must the search be aborted now
+    pub fn out_of_time(&self) -> bool {
+        self.elapsed() >= self.hard
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn manager(tc: TimeControl, overhead: u64) -> (TimeManager, FakeClock) {
+        let clock = FakeClock::default();
+        let tm = TimeManager::new(&tc, Color::White, ms(overhead), Box::new(clock.clone()));
+        (tm.unwrap(), clock)
+    }
+
+    #[test]
+    fn allocation() {
+        let tc = TimeControl {
+            wtime: Some(ms(60_000)),
+            btime: Some(ms(1_000)),
+            winc: Some(ms(1_000)),
+            ..Default::default()
+        };
+        let (tm, _) = manager(tc, 0);
+        assert_eq!(tm.soft_limit(), ms(60_000 / 25 + 750));
+        assert_eq!(tm.hard_limit(), ms((60_000 / 25 + 750) * 4));
+
+        // overhead is taken from every move
+        let (tm, _) = manager(tc, 50);
+        assert_eq!(tm.soft_limit(), ms(60_000 / 25 + 750 - 50));
+
+        // few moves to the next time control means more time per move
+        let (tm, _) = manager(
+            TimeControl {
+                movestogo: Some(2),
+                ..tc
+            },
+            0,
+        );
+        assert_eq!(tm.soft_limit(), ms(30_000));
+
+        let tc = TimeControl {
+            movetime: Some(ms(500)),
+            ..Default::default()
+        };
+        let (tm, _) = manager(tc, 20);
+        assert_eq!(tm.soft_limit(), ms(480));
+        assert_eq!(tm.hard_limit(), ms(480));
+
+        // nothing to manage
+        let clock = Box::new(FakeClock::default());
+        assert!(TimeManager::new(&TimeControl::default(), Color::White, ms(0), clock).is_none());
+    }
+
+    #[test]
+    fn limits() {
+        let tc = TimeControl {
+            wtime: Some(ms(10_000)),
+            ..Default::default()
+        };
+        let (mut tm, clock) = manager(tc, 0);
+        assert!(!tm.should_stop());
+        clock.advance(ms(400));
+        assert!(tm.should_stop());
+        assert!(!tm.out_of_time());
+        clock.advance(ms(1_200));
+        assert!(tm.out_of_time());
+
+        // time spent pondering does not count
+        tm.restart();
+        assert!(!tm.should_stop());
+        assert_eq!(tm.elapsed(), ms(0));
+    }
+
+    #[test]
+    fn adapts_to_the_search() {
+        let tc = TimeControl {
+            wtime: Some(ms(10_000)),
+            ..Default::default()
+        };
+        let e4 = Move::try_from("e2e4").ok();
+        let d4 = Move::try_from("d2d4").ok();
+
+        // best move changing and score dropping extend the soft limit
+        let (mut tm, _) = manager(tc, 0);
+        let base = tm.soft_limit();
+        tm.update(e4, 50, 0.5);
+        tm.update(d4, 50, 0.5);
+        assert!(tm.soft_limit() > base);
+        let unstable = tm.soft_limit();
+        tm.update(d4, -30, 0.5);
+        assert!(tm.soft_limit() > base);
+        assert!(tm.soft_limit() < unstable * 2);
+
+        let (mut tm, _) = manager(tc, 0);
+        tm.update(e4, 50, 0.5);
+        tm.update(e4, 0, 0.5);
+        assert!(tm.soft_limit() > base);
+
+        // a single clearly best move stops early
+        let (mut tm, _) = manager(tc, 0);
+        for _ in 0..4 {
+            tm.update(e4, 50, 0.95);
+        }
+        assert!(tm.soft_limit() < base);
+    }
+}
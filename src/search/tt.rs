@@ -0,0 +1,194 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{Move, Piece, Square};
+
+// transposition table
+// stores the result of searched positions keyed by their zobrist hash
+// one entry per bucket, deeper or newer results replace older ones
+//
+// the table is shared by all search threads without locking. an entry is
+// packed into one word of data and stored next to its key xored with the
+// data, so an entry torn by two threads writing at once fails the key check
+// and reads as a miss
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Bound {
+    // score is exact, the position was searched with an open window
+    Exact,
+    // score failed high, the real score is at least this
+    Lower,
+    // score failed low, the real score is at most this
+    Upper,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TTEntry {
+    pub key: u64,
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: i32,
+    pub bound: Bound,
+}
+
+// data layout:
+// bits 0-5 from, 6-11 to, 12-14 promotion, 15 has move,
+// bits 16-31 score, 32-39 depth, 40-41 bound, 63 used
+const HAS_MOVE: u64 = 1 << 15;
+const USED: u64 = 1 << 63;
+
+impl TTEntry {
+    fn pack(&self) -> u64 {
+        let mv = match self.best_move {
+            Some(mv) => {
+                let promotion = mv.promotion.map_or(0, |p| p as u64);
+                HAS_MOVE
+                    | u8::from(mv.from) as u64
+                    | (u8::from(mv.to) as u64) << 6
+                    | promotion << 12
+            }
+            None => 0,
+        };
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        USED | mv
+            | (self.score as i16 as u16 as u64) << 16
+            | (self.depth.clamp(0, 255) as u64) << 32
+            | bound << 40
+    }
+
+    fn unpack(key: u64, data: u64) -> TTEntry {
+        let square = |bits: u64| Square::try_from((bits & 63) as u8).unwrap();
+        let best_move = (data & HAS_MOVE != 0).then(|| Move {
+            from: square(data),
+            to: square(data >> 6),
+            promotion: Piece::try_from((data >> 12 & 7) as u8).ok(),
+        });
+        TTEntry {
+            key,
+            best_move,
+            score: (data >> 16) as u16 as i16 as i32,
+            depth: (data >> 32 & 255) as i32,
+            bound: match data >> 40 & 3 {
+                0 => Bound::Exact,
+                1 => Bound::Lower,
+                _ => Bound::Upper,
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+struct Slot {
+    // key xor data
+    check: AtomicU64,
+    data: AtomicU64,
+}
+
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+}
+
+impl TranspositionTable {
+    // size in megabytes
+    pub fn new(size: usize) -> TranspositionTable {
+        let count = (size.max(1) << 20) / std::mem::size_of::<Slot>();
+        TranspositionTable {
+            // round down so the index can be computed with a mask
+            slots: (0..1usize << count.ilog2())
+                .map(|_| Slot::default())
+                .collect(),
+        }
+    }
+
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.check.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn slot(&self, key: u64) -> &Slot {
+        &self.slots[key as usize & (self.slots.len() - 1)]
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TTEntry> {
+        let slot = self.slot(key);
+        let data = slot.data.load(Ordering::Relaxed);
+        let check = slot.check.load(Ordering::Relaxed);
+        (data & USED != 0 && check ^ data == key).then(|| TTEntry::unpack(key, data))
+    }
+
+    pub fn store(&self, mut entry: TTEntry) {
+        // keep the deeper result of the same position, but never lose
+        // the best move when a shallower search does not have one
+        if let Some(old) = self.probe(entry.key) {
+            if old.depth > entry.depth {
+                if old.best_move.is_some() || entry.best_move.is_none() {
+                    return;
+                }
+                entry = TTEntry {
+                    best_move: entry.best_move,
+                    ..old
+                };
+            }
+        }
+        let slot = self.slot(entry.key);
+        let data = entry.pack();
+        slot.check.store(entry.key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    // permille of used entries, sampled from the start of the table
+    pub fn hashfull(&self) -> usize {
+        let sample = self.slots.len().min(1000);
+        self.slots[..sample]
+            .iter()
+            .filter(|s| s.data.load(Ordering::Relaxed) & USED != 0)
+            .count()
+            * 1000
+            / sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        let entry = TTEntry {
+            key: 0x1234_5678_9abc_def0,
+            best_move: Some(Move::try_from("a7a8n").unwrap()),
+            score: -30990,
+            depth: 7,
+            bound: Bound::Upper,
+        };
+        tt.store(entry);
+        assert_eq!(tt.probe(entry.key), Some(entry));
+        assert_eq!(tt.probe(entry.key ^ 1 << 40), None);
+
+        // a shallower result only contributes its move
+        let shallow = TTEntry {
+            best_move: None,
+            depth: 3,
+            bound: Bound::Exact,
+            ..entry
+        };
+        tt.store(shallow);
+        assert_eq!(tt.probe(entry.key), Some(entry));
+        tt.store(TTEntry {
+            depth: 9,
+            best_move: None,
+            ..entry
+        });
+        assert_eq!(tt.probe(entry.key).unwrap().best_move, None);
+
+        tt.clear();
+        assert_eq!(tt.probe(entry.key), None);
+        assert_eq!(tt.hashfull(), 0);
+    }
+}
@@ -0,0 +1,176 @@
+use std::{collections::HashMap, fmt};
+
+use super::{after, is_mate, Stipulation};
+use crate::{to_san, Board, Move};
+
+// helpmate in n
+// the side to move and the other side cooperate so that the other side
+// mates it on its nth move. every line is a solution on its own, there is
+// nothing to defend, so the solver keeps every pair of moves leading to a
+// mate and remembers the positions from which no mate follows in time
+
+#[derive(Debug, Clone)]
+pub struct HelpmateSolution {
+    pub board: Board,
+    pub moves: u32,
+    // every line of moves ending in mate, more than one is a cook
+    pub solutions: Vec<Vec<Move>>,
+    // fewer moves than asked for are enough, the problem has a short solution
+    pub shortest: Option<u32>,
+    pub nodes: u64,
+}
+
+impl HelpmateSolution {
+    // one solution and no shorter one
+    pub fn is_sound(&self) -> bool {
+        self.solutions.len() == 1 && self.shortest == Some(self.moves)
+    }
+}
+
+#[derive(Default)]
+pub struct HelpmateSolver {
+    // whether the side to move is mated in the moves with help
+    known: HashMap<(u64, u32), bool>,
+    pub nodes: u64,
+}
+
+impl HelpmateSolver {
+    pub fn new() -> HelpmateSolver {
+        Self::default()
+    }
+
+    // the pairs of moves of both sides, with the position after them
+    fn pairs(&self, board: &Board, moves: u32) -> Vec<(Move, Move, Board)> {
+        let mut pairs = Vec::new();
+        for help in board.legal_moves() {
+            let child = after(board, help);
+            // mating the other side ends the game too early
+            if is_mate(&child) {
+                continue;
+            }
+            for mv in child.legal_moves() {
+                let grandchild = after(&child, mv);
+                // only the last move may mate, and it has to
+                if is_mate(&grandchild) == (moves == 1) {
+                    pairs.push((help, mv, grandchild));
+                }
+            }
+        }
+        pairs
+    }
+
+    // whether the side to move is mated in exactly the moves with help
+    pub fn helps(&mut self, board: &Board, moves: u32) -> bool {
+        self.nodes += 1;
+        let key = (board.hash(), moves);
+        if let Some(&known) = self.known.get(&key) {
+            return known;
+        }
+        let helps = self
+            .pairs(board, moves)
+            .iter()
+            .any(|(_, _, grandchild)| moves == 1 || self.helps(grandchild, moves - 1));
+        self.known.insert(key, helps);
+        helps
+    }
+
+    // every line leading to mate in exactly the moves
+    pub fn lines(&mut self, board: &Board, moves: u32) -> Vec<Vec<Move>> {
+        let mut lines = Vec::new();
+        for (help, mv, grandchild) in self.pairs(board, moves) {
+            if moves == 1 {
+                lines.push(vec![help, mv]);
+            } else if self.helps(&grandchild, moves - 1) {
+                for rest in self.lines(&grandchild, moves - 1) {
+                    lines.push([help, mv].into_iter().chain(rest).collect());
+                }
+            }
+        }
+        lines
+    }
+
+    pub fn solve(&mut self, board: &Board, moves: u32) -> HelpmateSolution {
+        let shortest = (1..=moves).find(|&n| self.helps(board, n));
+        let solutions = if self.helps(board, moves) {
+            self.lines(board, moves)
+        } else {
+            Vec::new()
+        };
+        HelpmateSolution {
+            board: board.clone(),
+            moves,
+            solutions,
+            shortest,
+            nodes: self.nodes,
+        }
+    }
+}
+
+// find every way the side to move helps the other side to mate it in the
+// moves
+pub fn solve_helpmate(board: &Board, moves: u32) -> HelpmateSolution {
+    HelpmateSolver::new().solve(board, moves)
+}
+
+impl fmt::Display for HelpmateSolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(shortest) = self.shortest else {
+            return writeln!(f, "No helpmate in {}", self.moves);
+        };
+        write!(f, "{} in {}: ", Stipulation::Helpmate, self.moves)?;
+        match self.solutions.len() {
+            0 => write!(f, "no solution")?,
+            1 => write!(f, "1 solution")?,
+            solutions => write!(f, "{} solutions, cooked", solutions)?,
+        }
+        if shortest < self.moves {
+            write!(f, ", short helpmate in {}", shortest)?;
+        }
+        writeln!(f)?;
+        // the helping side's move comes first in each numbered pair
+        for line in &self.solutions {
+            let mut board = self.board.clone_position();
+            for (i, &mv) in line.iter().enumerate() {
+                if i % 2 == 0 {
+                    let separator = if i == 0 { "" } else { " " };
+                    write!(f, "{}{}.", separator, i / 2 + 1)?;
+                } else {
+                    write!(f, " ")?;
+                }
+                write!(f, "{}", to_san(&board, mv))?;
+                board = after(&board, mv);
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn helpmates() {
+        let board = Board::from_fen("7k/6p1/6K1/8/8/8/8/R7 b - - 0 1".into()).unwrap();
+        let solution = solve_helpmate(&board, 1);
+        assert!(solution.is_sound());
+        assert_eq!(
+            solution.to_string(),
+            "Helpmate in 1: 1 solution\n1.Kg8 Ra8#\n"
+        );
+
+        // the rook mates on any file of the back rank a move later
+        let solution = solve_helpmate(&board, 2);
+        assert_eq!(solution.shortest, Some(1));
+        assert_eq!(solution.solutions.len(), 11);
+        assert!(!solution.is_sound());
+        assert!(solution
+            .to_string()
+            .starts_with("Helpmate in 2: 11 solutions, cooked, short helpmate in 1\n"));
+        assert!(solution
+            .solutions
+            .iter()
+            .all(|line| line.len() == 4 && line[0].to_string() == "h8g8"));
+    }
+}
@@ -0,0 +1,357 @@
+use std::{collections::HashMap, fmt};
+
+use super::{after, is_mate, Stipulation};
+use crate::{to_san, Board, Color, Move};
+
+// mate and selfmate in n
+// the side to move forces mate in at most n moves against any defence, or
+// in a selfmate forces the defender to mate it. the attacker's moves are
+// tried checks first, as they leave the defender the fewest replies, and
+// every position is remembered with the moves left and whether they are
+// enough
+
+// a move of the side to mate with the defences to it, none when it mates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variation {
+    pub mv: Move,
+    pub defences: Vec<Defence>,
+}
+
+// a defence with every move that still mates in time after it, none when
+// the defence ends the problem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Defence {
+    pub mv: Move,
+    pub continuations: Vec<Variation>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MateSolution {
+    pub stipulation: Stipulation,
+    pub board: Board,
+    pub moves: u32,
+    // every first move that mates in time, more than one is a cook
+    pub keys: Vec<Variation>,
+    // fewer moves than asked for are enough, the problem has a short solution
+    pub shortest: Option<u32>,
+    pub nodes: u64,
+}
+
+impl MateSolution {
+    // defences answered by more than one move
+    pub fn duals(&self) -> usize {
+        fn count(variations: &[Variation]) -> usize {
+            variations
+                .iter()
+                .flat_map(|v| &v.defences)
+                .map(|d| (d.continuations.len() > 1) as usize + count(&d.continuations))
+                .sum()
+        }
+        count(&self.keys)
+    }
+
+    // one key and no shorter mate
+    pub fn is_sound(&self) -> bool {
+        self.keys.len() == 1 && self.shortest == Some(self.moves)
+    }
+}
+
+#[derive(Default)]
+pub struct MateSolver {
+    stipulation: Stipulation,
+    // whether the side to move mates in the moves
+    known: HashMap<(u64, u32), bool>,
+    pub nodes: u64,
+}
+
+impl MateSolver {
+    pub fn new() -> MateSolver {
+        Self::default()
+    }
+
+    pub fn selfmate() -> MateSolver {
+        MateSolver {
+            stipulation: Stipulation::Selfmate,
+            ..Self::default()
+        }
+    }
+
+    // the moves of the side to mate, checks first
+    fn attacks(&self, board: &Board, moves: u32) -> Vec<(Move, Board)> {
+        let mut children: Vec<(bool, Move, Board)> = board
+            .legal_moves()
+            .into_iter()
+            .map(|mv| {
+                let child = after(board, mv);
+                (child.in_check(), mv, child)
+            })
+            // the last move of a mate has to give check
+            .filter(|(check, _, _)| {
+                *check || moves > 1 || self.stipulation == Stipulation::Selfmate
+            })
+            .collect();
+        children.sort_by_key(|(check, _, _)| !check);
+        children
+            .into_iter()
+            .map(|(_, mv, child)| (mv, child))
+            .collect()
+    }
+
+    // whether the move leaves the defender mated now or in the moves left,
+    // in a selfmate whether every defence mates or leads to a selfmate in
+    // the moves left
+    fn forces(&mut self, child: &Board, moves: u32) -> bool {
+        if self.stipulation == Stipulation::Mate && is_mate(child) {
+            return true;
+        }
+        let defences = child.legal_moves();
+        !defences.is_empty()
+            && defences.into_iter().all(|d| {
+                let grandchild = after(child, d);
+                (self.stipulation == Stipulation::Selfmate && is_mate(&grandchild))
+                    || (moves > 1 && self.mates(&grandchild, moves - 1))
+            })
+    }
+
+    // whether the side to move mates in at most the moves
+    pub fn mates(&mut self, board: &Board, moves: u32) -> bool {
+        self.nodes += 1;
+        let key = (board.hash(), moves);
+        if let Some(&known) = self.known.get(&key) {
+            return known;
+        }
+        let mates = self
+            .attacks(board, moves)
+            .iter()
+            .any(|(_, child)| self.forces(child, moves));
+        self.known.insert(key, mates);
+        mates
+    }
+
+    // every move mating in at most the moves, with the whole tree of
+    // defences and continuations
+    pub fn variations(&mut self, board: &Board, moves: u32) -> Vec<Variation> {
+        let mut variations = Vec::new();
+        for (mv, child) in self.attacks(board, moves) {
+            if !self.forces(&child, moves) {
+                continue;
+            }
+            let defences = child
+                .legal_moves()
+                .into_iter()
+                .map(|d| {
+                    let grandchild = after(&child, d);
+                    let continuations = if moves > 1 && !is_mate(&grandchild) {
+                        self.variations(&grandchild, moves - 1)
+                    } else {
+                        Vec::new()
+                    };
+                    Defence {
+                        mv: d,
+                        continuations,
+                    }
+                })
+                .collect();
+            variations.push(Variation { mv, defences });
+        }
+        variations
+    }
+
+    pub fn solve(&mut self, board: &Board, moves: u32) -> MateSolution {
+        let shortest = (1..=moves).find(|&n| self.mates(board, n));
+        let keys = if shortest.is_some() {
+            self.variations(board, moves)
+        } else {
+            Vec::new()
+        };
+        MateSolution {
+            stipulation: self.stipulation,
+            board: board.clone(),
+            moves,
+            keys,
+            shortest,
+            nodes: self.nodes,
+        }
+    }
+}
+
+// prove or refute that the side to move mates in the moves
+pub fn solve_mate(board: &Board, moves: u32) -> MateSolution {
+    MateSolver::new().solve(board, moves)
+}
+
+// prove or refute that the side to move forces the other side to mate it
+// in the moves
+pub fn solve_selfmate(board: &Board, moves: u32) -> MateSolution {
+    MateSolver::selfmate().solve(board, moves)
+}
+
+// the number before a move, 1. for white and 1... for black
+fn number(board: &Board, move_number: u32) -> String {
+    match board.turn {
+        Color::White => format!("{}.", move_number),
+        Color::Black => format!("{}...", move_number),
+    }
+}
+
+// print variations in the usual problem layout, each defence on a line of
+// its own, followed by the answer when there is only one
+pub(crate) fn write_variations(
+    f: &mut fmt::Formatter<'_>,
+    board: &Board,
+    variations: &[Variation],
+    move_number: u32,
+    indent: usize,
+) -> fmt::Result {
+    for variation in variations {
+        write!(
+            f,
+            "{:indent$}{}{}",
+            "",
+            number(board, move_number),
+            to_san(board, variation.mv)
+        )?;
+        write_defences(f, board, variation, move_number, indent)?;
+    }
+    Ok(())
+}
+
+fn write_defences(
+    f: &mut fmt::Formatter<'_>,
+    board: &Board,
+    variation: &Variation,
+    move_number: u32,
+    indent: usize,
+) -> fmt::Result {
+    writeln!(f)?;
+    let child = after(board, variation.mv);
+    // the move number goes up after black's moves
+    let next = move_number + (child.turn == Color::Black) as u32;
+    for defence in &variation.defences {
+        let grandchild = after(&child, defence.mv);
+        write!(
+            f,
+            "{:indent$}{}{}",
+            "",
+            number(&child, move_number),
+            to_san(&child, defence.mv),
+            indent = indent + 4
+        )?;
+        match defence.continuations.as_slice() {
+            [] => writeln!(f)?,
+            [only] if only.defences.is_empty() => writeln!(
+                f,
+                " {}{}",
+                number(&grandchild, next),
+                to_san(&grandchild, only.mv)
+            )?,
+            [only] => {
+                write!(
+                    f,
+                    " {}{}",
+                    number(&grandchild, next),
+                    to_san(&grandchild, only.mv)
+                )?;
+                write_defences(f, &grandchild, only, next, indent + 4)?;
+            }
+            continuations => {
+                writeln!(f, " (dual)")?;
+                write_variations(f, &grandchild, continuations, next, indent + 8)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for MateSolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(shortest) = self.shortest else {
+            let stipulation = self.stipulation.to_string().to_lowercase();
+            return writeln!(f, "No {} in {}", stipulation, self.moves);
+        };
+        write!(f, "{} in {}: ", self.stipulation, self.moves)?;
+        match self.keys.len() {
+            1 => write!(f, "1 key")?,
+            keys => write!(f, "{} keys, cooked", keys)?,
+        }
+        if shortest < self.moves {
+            let stipulation = self.stipulation.to_string().to_lowercase();
+            write!(f, ", short {} in {}", stipulation, shortest)?;
+        }
+        match self.duals() {
+            0 => writeln!(f)?,
+            duals => writeln!(f, ", {} duals", duals)?,
+        }
+        write_variations(f, &self.board, &self.keys, 1, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve(fen: &str, moves: u32) -> MateSolution {
+        solve_mate(&Board::from_fen(fen.into()).unwrap(), moves)
+    }
+
+    fn keys(solution: &MateSolution) -> Vec<String> {
+        solution.keys.iter().map(|v| v.mv.to_string()).collect()
+    }
+
+    #[test]
+    fn mate_in_two() {
+        // Morphy's problem
+        let fen = "kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1";
+        assert_eq!(solve(fen, 1).shortest, None);
+        assert_eq!(solve(fen, 1).to_string(), "No mate in 1\n");
+        let solution = solve(fen, 2);
+        assert_eq!(keys(&solution), ["a1a6"]);
+        assert!(solution.is_sound());
+        assert_eq!(solution.duals(), 0);
+        let text = solution.to_string();
+        assert!(text.starts_with("Mate in 2: 1 key\n1.Ra6\n"), "{}", text);
+        assert!(text.contains("    1...bxa6 2.b7#\n"), "{}", text);
+        assert!(text.contains("    1...Bc7 2.Rxa7#\n"), "{}", text);
+        // every defence is in the tree
+        assert_eq!(solution.keys[0].defences.len(), 7);
+
+        // a mate in two is a mate in three with a short solution
+        let solution = solve(fen, 3);
+        assert_eq!(solution.shortest, Some(2));
+        assert!(!solution.is_sound());
+    }
+
+    #[test]
+    fn cooks_and_duals() {
+        let solution = solve("6k1/8/6K1/8/8/8/8/RR6 w - - 0 1", 1);
+        assert_eq!(keys(&solution), ["a1a8", "b1b8"]);
+        assert!(!solution.is_sound());
+        assert!(solution
+            .to_string()
+            .starts_with("Mate in 1: 2 keys, cooked\n1.Ra8#\n1.Rb8#\n"));
+
+        // a mate in one is answered by many mates in two
+        assert!(solve("7k/8/6K1/8/8/8/8/RR6 w - - 0 1", 2).duals() > 0);
+    }
+
+    #[test]
+    fn selfmate() {
+        let board = Board::from_fen("2kQ4/q4p2/2K5/7q/8/8/8/8 w - - 0 1".into()).unwrap();
+        let solution = solve_selfmate(&board, 1);
+        assert_eq!(keys(&solution), ["d8c7"]);
+        assert!(solution.is_sound());
+        assert_eq!(
+            solution.to_string(),
+            "Selfmate in 1: 1 key\n1.Qc7+\n    1...Qxc7#\n"
+        );
+        // the mates of black are not selfmates
+        assert_eq!(
+            keys(&solve("2kQ4/q4p2/2K5/7q/8/8/8/8 w - - 0 1", 1)),
+            ["d8e8", "d8f8", "d8g8"]
+        );
+        assert_eq!(
+            solve_selfmate(&Board::default(), 1).to_string(),
+            "No selfmate in 1\n"
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use std::fmt;
+
+use crate::{Board, BoardStatus, Move};
+
+mod mate;
+pub use self::mate::*;
+
+mod helpmate;
+pub use self::helpmate::*;
+
+// chess problem solvers
+// unlike the search, which looks for good moves, a solver proves what a
+// problem claims: that white forces mate in so many moves, helps black to
+// mate it or forces black to, with every solution found so that cooks,
+// other keys than the intended one, and duals, other moves than the
+// intended one later on, can be flagged
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Stipulation {
+    // the side to move forces mate
+    #[default]
+    Mate,
+    // the side to move helps the other side to mate it
+    Helpmate,
+    // the side to move forces the other side to mate it
+    Selfmate,
+}
+
+impl fmt::Display for Stipulation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Stipulation::Mate => "Mate",
+            Stipulation::Helpmate => "Helpmate",
+            Stipulation::Selfmate => "Selfmate",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// the position after a legal move, with its status
+fn after(board: &Board, mv: Move) -> Board {
+    let mut child = board.clone_position();
+    child.do_move(mv);
+    child.update_status();
+    child
+}
+
+fn is_mate(board: &Board) -> bool {
+    matches!(board.status, BoardStatus::Checkmate(_))
+}
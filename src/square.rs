@@ -2,7 +2,7 @@ use crate::SquareError;
 use std::fmt;
 
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Square(u8);
 
 impl Square {
@@ -81,15 +81,11 @@ impl TryFrom<String> for Square {
         let file = chars.next().unwrap();
         let rank = chars.next().unwrap();
 
-        if !file.is_ascii_alphabetic() {
-            return Err(SquareError::InvalidFile(file));
-        } else if !('a' <= file && file <= 'h') {
+        if !file.is_ascii_alphabetic() || !('a'..='h').contains(&file) {
             return Err(SquareError::InvalidFile(file));
         }
 
-        if !rank.is_ascii_digit() {
-            return Err(SquareError::InvalidRank(rank));
-        } else if !('1' <= rank && rank <= '8') {
+        if !rank.is_ascii_digit() || !('1'..='8').contains(&rank) {
             return Err(SquareError::InvalidRank(rank));
         }
 
@@ -0,0 +1,351 @@
+use std::sync::Arc;
+
+use super::{
+    add, get, insufficient, parse_signature, probe_position, signature, with_squares, Position,
+    Tablebase, TablebaseResult, LOSS, ORDER,
+};
+use crate::{Color, Piece, TablebaseError};
+
+// retrograde analysis
+// mates are found first, then positions are solved in order of their
+// distance to mate: a position with a move to a lost position is won, one
+// more ply away, and a position whose every move leads to a won position is
+// lost, one ply further than its longest defence. captures and promotions
+// leave the table and are looked up in the smaller tables, which are
+// generated first. what is never solved is a draw
+//
+// values while generating are plies to mate, odd when the side to move wins
+// and even when it loses
+
+const UNKNOWN: u16 = u16::MAX;
+const DRAW: u16 = u16::MAX - 1;
+
+// generate the table of the signature and every table it leads to, adding
+// them to the loaded tables
+pub fn generate(signature: &str) -> Result<Arc<Tablebase>, TablebaseError> {
+    if let Some(table) = get(signature) {
+        return Ok(table);
+    }
+    let pieces = parse_signature(signature)?;
+    for material in conversions(&with_squares(&pieces)) {
+        ensure(&material)?;
+    }
+    let mut table = Tablebase {
+        signature: signature.to_owned(),
+        pieces,
+        values: Vec::new(),
+    };
+    table.values = solve(&table)
+        .into_iter()
+        .map(|plies| match plies {
+            DRAW => 0,
+            p if p % 2 == 1 => p.div_ceil(2) as u8,
+            p => LOSS + (p / 2) as u8,
+        })
+        .collect();
+    Ok(add(table))
+}
+
+// the material after every capture and promotion
+fn conversions(pieces: &[(Piece, Color, u8)]) -> Vec<Vec<(Piece, Color, u8)>> {
+    let mut conversions = Vec::new();
+    for (i, &(piece, color, _)) in pieces.iter().enumerate() {
+        if piece != Piece::King {
+            let mut captured = pieces.to_vec();
+            captured.remove(i);
+            conversions.push(captured);
+        }
+        if piece == Piece::Pawn {
+            for promotion in [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight] {
+                let mut promoted = pieces.to_vec();
+                promoted[i] = (promotion, color, 0);
+                conversions.push(promoted);
+            }
+        }
+    }
+    // a promotion can capture too
+    let promotions: Vec<_> = conversions
+        .iter()
+        .filter(|c| c.len() == pieces.len())
+        .cloned()
+        .collect();
+    for promoted in promotions {
+        conversions.extend(conversions_without_promotion(&promoted));
+    }
+    conversions
+}
+
+fn conversions_without_promotion(pieces: &[(Piece, Color, u8)]) -> Vec<Vec<(Piece, Color, u8)>> {
+    (0..pieces.len())
+        .filter(|&i| pieces[i].0 != Piece::King)
+        .map(|i| {
+            let mut captured = pieces.to_vec();
+            captured.remove(i);
+            captured
+        })
+        .collect()
+}
+
+// make sure a table for the material is loaded, in either colors
+fn ensure(pieces: &[(Piece, Color, u8)]) -> Result<(), TablebaseError> {
+    if insufficient(pieces) {
+        return Ok(());
+    }
+    let mirror: Vec<_> = pieces
+        .iter()
+        .map(|&(p, c, s)| (p, c.opposite(), s))
+        .collect();
+    let (white, black) = (signature(pieces), signature(&mirror));
+    if get(&white).is_some() || get(&black).is_some() {
+        return Ok(());
+    }
+    // the stronger side plays white
+    let strength = |pieces: &[(Piece, Color, u8)], color| {
+        let mut side: Vec<usize> = pieces
+            .iter()
+            .filter(|&&(_, c, _)| c == color)
+            .map(|&(p, _, _)| ORDER.iter().position(|&q| q == p).unwrap())
+            .collect();
+        side.sort();
+        (std::cmp::Reverse(side.len()), side)
+    };
+    if strength(pieces, Color::White) <= strength(pieces, Color::Black) {
+        generate(&white)?;
+    } else {
+        generate(&black)?;
+    }
+    Ok(())
+}
+
+// the children that stay in the table have the same pieces
+fn in_table(position: &Position, child: &Position) -> bool {
+    child.pieces().len() == position.pieces().len()
+        && child
+            .pieces()
+            .iter()
+            .zip(position.pieces())
+            .all(|(a, b)| a.0 == b.0)
+}
+
+// plies to mate of a position outside the table
+fn outside(child: &Position) -> u16 {
+    match probe_position(child).expect("smaller tables are generated first") {
+        TablebaseResult::Win(n) => 2 * n as u16 - 1,
+        TablebaseResult::Loss(n) => 2 * n as u16,
+        TablebaseResult::Draw => DRAW,
+    }
+}
+
+fn solve(table: &Tablebase) -> Vec<u16> {
+    let size = table.size();
+    let mut values = vec![UNKNOWN; size];
+    let mut solved = vec![false; size];
+    // positions by the plies to mate they were given
+    let mut queue: Vec<Vec<usize>> = Vec::new();
+    let push = |queue: &mut Vec<Vec<usize>>, index: usize, plies: u16| {
+        let plies = plies as usize;
+        if queue.len() <= plies {
+            queue.resize(plies + 1, Vec::new());
+        }
+        queue[plies].push(index);
+    };
+
+    // mates, stalemates and positions decided by leaving the table
+    for index in 0..size {
+        let Some(position) = table.position(index) else {
+            values[index] = DRAW;
+            solved[index] = true;
+            continue;
+        };
+        let children = position.children();
+        if children.is_empty() {
+            if position.in_check() {
+                values[index] = 0;
+                push(&mut queue, index, 0);
+            } else {
+                values[index] = DRAW;
+                solved[index] = true;
+            }
+            continue;
+        }
+        let mut win = UNKNOWN;
+        let mut all_won = true;
+        let mut longest = 0;
+        for child in &children {
+            if in_table(&position, child) {
+                all_won = false;
+                continue;
+            }
+            match outside(child) {
+                DRAW => all_won = false,
+                p if p % 2 == 0 => {
+                    win = win.min(p + 1);
+                    all_won = false;
+                }
+                p => longest = longest.max(p),
+            }
+        }
+        if win != UNKNOWN {
+            values[index] = win;
+            push(&mut queue, index, win);
+        } else if all_won {
+            values[index] = longest + 1;
+            push(&mut queue, index, longest + 1);
+        }
+    }
+
+    let mut plies = 0;
+    while plies < queue.len() {
+        for index in std::mem::take(&mut queue[plies]) {
+            // solved already or given a shorter distance since
+            if solved[index] || values[index] as usize != plies {
+                continue;
+            }
+            solved[index] = true;
+            let position = table.position(index).unwrap();
+            for parent in position.parents() {
+                let p = table.index(&parent);
+                if solved[p] {
+                    continue;
+                }
+                let plies = plies as u16;
+                if plies.is_multiple_of(2) {
+                    // a move to a lost position wins
+                    if values[p] == UNKNOWN || (values[p] % 2 == 1 && values[p] > plies + 1) {
+                        values[p] = plies + 1;
+                        push(&mut queue, p, plies + 1);
+                    }
+                } else if values[p] == UNKNOWN {
+                    // lost when every move leads to a won position
+                    if let Some(longest) = longest_defence(table, &parent, &values, &solved) {
+                        values[p] = longest + 1;
+                        push(&mut queue, p, longest + 1);
+                    }
+                }
+            }
+        }
+        plies += 1;
+    }
+
+    for value in &mut values {
+        if *value == UNKNOWN {
+            *value = DRAW;
+        }
+    }
+    values
+}
+
+// the plies to mate of the longest defence when every move leads to a won
+// position that is solved
+fn longest_defence(
+    table: &Tablebase,
+    position: &Position,
+    values: &[u16],
+    solved: &[bool],
+) -> Option<u16> {
+    let mut longest = 0;
+    for child in position.children() {
+        let plies = if in_table(position, &child) {
+            let index = table.index(&child);
+            if !solved[index] {
+                return None;
+            }
+            values[index]
+        } else {
+            outside(&child)
+        };
+        if plies == DRAW || plies % 2 == 0 {
+            return None;
+        }
+        longest = longest.max(plies);
+    }
+    Some(longest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Board, Move};
+
+    fn result(fen: &str) -> Option<TablebaseResult> {
+        Board::from_fen(fen.into()).unwrap().tablebase_result()
+    }
+
+    #[test]
+    fn generates_tables() {
+        let kqk = generate("KQK").unwrap();
+        let krk = generate("KRK").unwrap();
+        // the longest mates are well known
+        let longest = |table: &Tablebase| table.values.iter().filter(|&&v| v < LOSS).max().copied();
+        assert_eq!(longest(&kqk), Some(10));
+        assert_eq!(longest(&krk), Some(16));
+
+        let path = std::env::temp_dir().join(format!("krk-{}.ctb", std::process::id()));
+        krk.write(&path).unwrap();
+        let read = Tablebase::read(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.unwrap(), *krk);
+        // a run longer than the table is turned down before it is allocated
+        let mut bytes = b"chess tablebase 1\nKRK\n".to_vec();
+        bytes.extend([0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]);
+        std::fs::write(&path, bytes).unwrap();
+        let read = Tablebase::read(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(read, Err(TablebaseError::InvalidFile(_))));
+
+        assert_eq!(
+            result("k7/8/1K6/8/8/8/7Q/8 w - - 0 1"),
+            Some(TablebaseResult::Win(1))
+        );
+        assert_eq!(
+            result("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1"),
+            Some(TablebaseResult::Loss(0))
+        );
+        // the colors the other way round
+        assert_eq!(
+            result("K7/8/1k6/8/8/8/7q/8 b - - 0 1"),
+            Some(TablebaseResult::Win(1))
+        );
+        // the queen is lost
+        assert_eq!(
+            result("8/8/8/8/8/8/1kQ5/7K b - - 0 1"),
+            Some(TablebaseResult::Draw)
+        );
+        assert_eq!(
+            result("k7/8/8/8/8/8/8/K6B w - - 0 1"),
+            Some(TablebaseResult::Draw)
+        );
+        assert_eq!(
+            result("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            None
+        );
+    }
+
+    #[test]
+    fn pawns() {
+        generate("KPK").unwrap();
+        // the king in front of the pawn wins with the opposition
+        assert_eq!(
+            result("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1"),
+            Some(TablebaseResult::Draw)
+        );
+        assert!(matches!(
+            result("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1"),
+            Some(TablebaseResult::Loss(_))
+        ));
+        // the rook pawn does not
+        assert_eq!(
+            result("k7/8/K7/P7/8/8/8/8 w - - 0 1"),
+            Some(TablebaseResult::Draw)
+        );
+        // promoting to a queen stalemates
+        let board = Board::from_fen("8/5P1k/8/6K1/8/8/8/8 w - - 0 1".into()).unwrap();
+        assert!(matches!(
+            board.tablebase_result(),
+            Some(TablebaseResult::Win(_))
+        ));
+        let mut queen = board.clone();
+        queen.play(Move::try_from("f7f8q").unwrap()).unwrap();
+        assert_eq!(queen.tablebase_result(), Some(TablebaseResult::Draw));
+    }
+}
@@ -0,0 +1,449 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use crate::{Board, Color, Piece, TablebaseError};
+
+mod position;
+pub(crate) use self::position::*;
+
+mod generate;
+pub use self::generate::*;
+
+// endgame tablebases
+// the distance to mate of every position of a material signature, eg. KRK
+// or KRKP, generated by retrograde analysis. signatures name the white
+// pieces and then the black ones, positions with the colors the other way
+// round are looked up in the table of the mirrored position
+//
+// a table stores one byte per position: 0 for draws, n for a win in n moves
+// and 128 + n for a loss in n moves. the position is indexed by the square of
+// the white king, folded into a corner by the symmetries of the board, the
+// squares of the other pieces and the side to move
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TablebaseResult {
+    // the side to move mates in this many moves
+    Win(u32),
+    // the side to move is mated in this many moves
+    Loss(u32),
+    Draw,
+}
+
+const LOSS: u8 = 128;
+// files start with this, followed by the signature, a newline and the
+// values with runs of the same value stored as the value and the run length
+const MAGIC: &[u8] = b"chess tablebase 1\n";
+
+const ORDER: [Piece; 6] = [
+    Piece::King,
+    Piece::Queen,
+    Piece::Rook,
+    Piece::Bishop,
+    Piece::Knight,
+    Piece::Pawn,
+];
+
+fn letter(piece: Piece) -> char {
+    match piece {
+        Piece::King => 'K',
+        Piece::Queen => 'Q',
+        Piece::Rook => 'R',
+        Piece::Bishop => 'B',
+        Piece::Knight => 'N',
+        Piece::Pawn => 'P',
+    }
+}
+
+// the signature of the pieces, eg. KRKP for a white king and rook against a
+// black king and pawn
+pub(crate) fn signature(pieces: &[(Piece, Color, u8)]) -> String {
+    let side = |color| {
+        ORDER
+            .iter()
+            .flat_map(|&p| {
+                let count = pieces
+                    .iter()
+                    .filter(|&&(q, c, _)| q == p && c == color)
+                    .count();
+                std::iter::repeat_n(letter(p), count)
+            })
+            .collect::<String>()
+    };
+    side(Color::White) + &side(Color::Black)
+}
+
+// the number of pieces of each kind in four bits each, white's in the low
+// 24 bits, a cheaper key for the tables than the signature
+fn material_key(pieces: impl IntoIterator<Item = (Piece, Color)>) -> u64 {
+    pieces
+        .into_iter()
+        .map(|(p, c)| 1 << (4 * (c as usize * 6 + p as usize - 1)))
+        .sum()
+}
+
+// the key with the colors swapped
+fn mirror_key(key: u64) -> u64 {
+    key >> 24 | (key & 0xff_ffff) << 24
+}
+
+// the pieces of a signature in the order of the index
+fn parse_signature(signature: &str) -> Result<Vec<(Piece, Color)>, TablebaseError> {
+    let invalid = || TablebaseError::InvalidSignature(signature.to_owned());
+    let black = signature
+        .get(1..)
+        .and_then(|s| s.find('K'))
+        .ok_or_else(invalid)?
+        + 1;
+    let mut pieces = Vec::new();
+    for (side, color) in [
+        (&signature[..black], Color::White),
+        (&signature[black..], Color::Black),
+    ] {
+        let mut side: Vec<Piece> = side
+            .chars()
+            .map(|c| Piece::try_from(c).map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        side.sort_by_key(|p| ORDER.iter().position(|q| q == p));
+        if side.iter().filter(|&&p| p == Piece::King).count() != 1 {
+            return Err(invalid());
+        }
+        pieces.extend(side.into_iter().map(|p| (p, color)));
+    }
+    // the kings first
+    pieces.sort_by_key(|&(p, c)| (p != Piece::King, c as u8 * (p == Piece::King) as u8));
+    if pieces.len() > MAX_PIECES || signature != self::signature(&with_squares(&pieces)) {
+        return Err(invalid());
+    }
+    Ok(pieces)
+}
+
+fn with_squares(pieces: &[(Piece, Color)]) -> Vec<(Piece, Color, u8)> {
+    pieces.iter().map(|&(p, c)| (p, c, 0)).collect()
+}
+
+// no side can mate
+fn insufficient(pieces: &[(Piece, Color, u8)]) -> bool {
+    pieces
+        .iter()
+        .all(|&(p, _, _)| matches!(p, Piece::King | Piece::Bishop | Piece::Knight))
+        && pieces.len() <= 3
+}
+
+// squares a1-d1-d4 the white king is moved to without pawns
+const TRIANGLE: [u8; 10] = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tablebase {
+    signature: String,
+    pieces: Vec<(Piece, Color)>,
+    values: Vec<u8>,
+}
+
+impl Tablebase {
+    pub fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    fn has_pawns(&self) -> bool {
+        self.pieces.iter().any(|&(p, _)| p == Piece::Pawn)
+    }
+
+    fn king_squares(&self) -> usize {
+        if self.has_pawns() {
+            32
+        } else {
+            TRIANGLE.len()
+        }
+    }
+
+    fn size(&self) -> usize {
+        2 * self.king_squares() * 64usize.pow(self.pieces.len() as u32 - 1)
+    }
+
+    // the index of a position with the pieces of the table
+    fn index(&self, position: &Position) -> usize {
+        // the pieces in table order
+        let mut squares = [0u8; MAX_PIECES];
+        let mut used = [false; MAX_PIECES];
+        for (slot, &(piece, color)) in self.pieces.iter().enumerate() {
+            let i = (0..position.pieces().len())
+                .find(|&i| {
+                    let (p, c, _) = position.pieces()[i];
+                    !used[i] && p == piece && c == color
+                })
+                .expect("position does not match the table");
+            used[i] = true;
+            squares[slot] = position.pieces()[i].2;
+        }
+        let squares = &mut squares[..self.pieces.len()];
+        // mirror the board to bring the white king to the corner
+        let king = squares[0];
+        let flip_file = king % 8 > 3;
+        let flip_rank = !self.has_pawns() && king / 8 > 3;
+        for square in squares.iter_mut() {
+            *square ^= if flip_file { 7 } else { 0 } | if flip_rank { 56 } else { 0 };
+        }
+        let king_index = if self.has_pawns() {
+            (squares[0] / 8 * 4 + squares[0] % 8) as usize
+        } else {
+            // below the diagonal, with the king on it the first piece off it
+            // decides
+            let off = squares.iter().find(|&&s| s / 8 != s % 8);
+            if off.is_some_and(|&s| s / 8 > s % 8) {
+                for square in squares.iter_mut() {
+                    *square = *square % 8 * 8 + *square / 8;
+                }
+            }
+            TRIANGLE.iter().position(|&s| s == squares[0]).unwrap()
+        };
+        let mut index = position.turn as usize * self.king_squares() + king_index;
+        for &square in &squares[1..] {
+            index = index * 64 + square as usize;
+        }
+        index
+    }
+
+    // the position of an index, none if no legal position has that index
+    fn position(&self, index: usize) -> Option<Position> {
+        let mut rest = index;
+        let mut pieces = [(Piece::King, Color::White, 0); MAX_PIECES];
+        for slot in (1..self.pieces.len()).rev() {
+            let (p, c) = self.pieces[slot];
+            pieces[slot] = (p, c, (rest % 64) as u8);
+            rest /= 64;
+        }
+        let king = rest % self.king_squares();
+        let king = if self.has_pawns() {
+            (king / 4 * 8 + king % 4) as u8
+        } else {
+            TRIANGLE[king]
+        };
+        pieces[0] = (Piece::King, Color::White, king);
+        let turn = if rest / self.king_squares() == 0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let position = Position::new(&pieces[..self.pieces.len()], turn);
+        // positions symmetric to one with a smaller index are left out
+        (position.is_legal() && self.index(&position) == index).then_some(position)
+    }
+
+    fn probe_position(&self, position: &Position) -> TablebaseResult {
+        match self.values[self.index(position)] {
+            0 => TablebaseResult::Draw,
+            v if v >= LOSS => TablebaseResult::Loss((v - LOSS) as u32),
+            v => TablebaseResult::Win(v as u32),
+        }
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), TablebaseError> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(self.signature.as_bytes());
+        bytes.push(b'\n');
+        for run in self.values.chunk_by(|a, b| a == b) {
+            bytes.push(run[0]);
+            // the run length in 7 bit groups, low first
+            let mut length = run.len();
+            while length >= 128 {
+                bytes.push(length as u8 | 128);
+                length >>= 7;
+            }
+            bytes.push(length as u8);
+        }
+        fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Tablebase, TablebaseError> {
+        let path = path.as_ref();
+        let invalid = || TablebaseError::InvalidFile(path.display().to_string());
+        let bytes = fs::read(path)?;
+        let rest = bytes.strip_prefix(MAGIC).ok_or_else(invalid)?;
+        let end = rest.iter().position(|&b| b == b'\n').ok_or_else(invalid)?;
+        let signature = std::str::from_utf8(&rest[..end]).map_err(|_| invalid())?;
+        let mut table = Tablebase {
+            signature: signature.to_owned(),
+            pieces: parse_signature(signature)?,
+            values: Vec::new(),
+        };
+        let size = table.size();
+        let mut bytes = rest[end + 1..].iter();
+        while let Some(&value) = bytes.next() {
+            let mut length = 0;
+            for shift in (0..).step_by(7) {
+                let &b = bytes.next().ok_or_else(invalid)?;
+                length |= ((b & 127) as usize)
+                    .checked_shl(shift)
+                    .ok_or_else(invalid)?;
+                if b < 128 {
+                    break;
+                }
+            }
+            // a corrupt length could ask for far more memory than the table
+            if length > size - table.values.len() {
+                return Err(invalid());
+            }
+            table.values.extend(std::iter::repeat_n(value, length));
+        }
+        if table.values.len() != size {
+            return Err(invalid());
+        }
+        Ok(table)
+    }
+}
+
+// the tables probed by the search and Board::tablebase_result, with their
+// material keys
+static TABLES: RwLock<Vec<(u64, Arc<Tablebase>)>> = RwLock::new(Vec::new());
+// pieces of the largest table, positions with more are not probed
+static MOST_PIECES: AtomicUsize = AtomicUsize::new(0);
+
+pub fn add(table: Tablebase) -> Arc<Tablebase> {
+    let table = Arc::new(table);
+    let mut tables = TABLES.write().unwrap();
+    tables.retain(|(_, t)| t.signature != table.signature);
+    tables.push((material_key(table.pieces.iter().copied()), table.clone()));
+    MOST_PIECES.fetch_max(table.pieces.len(), Ordering::Relaxed);
+    table
+}
+
+pub fn get(signature: &str) -> Option<Arc<Tablebase>> {
+    TABLES
+        .read()
+        .unwrap()
+        .iter()
+        .find(|(_, t)| t.signature == signature)
+        .map(|(_, t)| t.clone())
+}
+
+fn find(key: u64) -> Option<Arc<Tablebase>> {
+    TABLES
+        .read()
+        .unwrap()
+        .iter()
+        .find(|&&(k, _)| k == key)
+        .map(|(_, t)| t.clone())
+}
+
+// the signatures of the tables loaded
+pub fn loaded() -> Vec<String> {
+    TABLES
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(_, t)| t.signature.clone())
+        .collect()
+}
+
+// file name of the table of a signature
+pub fn file_name(signature: &str) -> String {
+    format!("{}.ctb", signature)
+}
+
+// load every table in the directory, returning how many there were
+pub fn load_dir(dir: impl AsRef<Path>) -> Result<usize, TablebaseError> {
+    let mut count = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "ctb") {
+            add(Tablebase::read(&path)?);
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+// the result of a position from the loaded tables
+pub(crate) fn probe_position(position: &Position) -> Option<TablebaseResult> {
+    if insufficient(position.pieces()) {
+        return Some(TablebaseResult::Draw);
+    }
+    let key = material_key(position.pieces().iter().map(|&(p, c, _)| (p, c)));
+    if let Some(table) = find(key) {
+        return Some(table.probe_position(position));
+    }
+    find(mirror_key(key)).map(|table| table.probe_position(&position.mirror()))
+}
+
+// called at every node of the search, most positions have too many pieces
+// and are turned away before looking at the board
+pub(crate) fn probe(board: &Board) -> Option<TablebaseResult> {
+    let count = board.piece_count();
+    if count > MOST_PIECES.load(Ordering::Relaxed) || board.castling != 0 {
+        return None;
+    }
+    let mut pieces = [(Piece::King, Color::White, 0); MAX_PIECES];
+    for (slot, (square, p)) in pieces.iter_mut().zip(board.pieces()) {
+        *slot = (p.get_piece(), p.get_color(), u8::from(square));
+    }
+    let pieces = &pieces[..count];
+    // en passant is not part of the tables
+    if board.en_passant.is_some()
+        && pieces
+            .iter()
+            .any(|&(p, c, _)| p == Piece::Pawn && c == board.turn)
+    {
+        return None;
+    }
+    probe_position(&Position::new(pieces, board.turn))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures() {
+        assert_eq!(
+            parse_signature("KRKP").unwrap(),
+            [
+                (Piece::King, Color::White),
+                (Piece::King, Color::Black),
+                (Piece::Rook, Color::White),
+                (Piece::Pawn, Color::Black)
+            ]
+        );
+        assert!(parse_signature("KPRK").is_err());
+        assert!(parse_signature("KQQQK").is_err());
+        assert!(parse_signature("QK").is_err());
+        let board = Board::from_fen("8/8/4k3/8/2p5/8/8/R3K3 w - - 0 1".into()).unwrap();
+        let pieces: Vec<_> = board
+            .pieces()
+            .map(|(s, p)| (p.get_piece(), p.get_color(), u8::from(s)))
+            .collect();
+        assert_eq!(signature(&pieces), "KRKP");
+    }
+
+    #[test]
+    fn indexes() {
+        for signature in ["KQK", "KPK"] {
+            let table = Tablebase {
+                signature: signature.to_owned(),
+                pieces: parse_signature(signature).unwrap(),
+                values: Vec::new(),
+            };
+            for index in (0..table.size()).step_by(7) {
+                if let Some(position) = table.position(index) {
+                    assert_eq!(table.index(&position), index);
+                    // symmetric positions share the index
+                    let flipped = Position::new(
+                        &position
+                            .pieces()
+                            .iter()
+                            .map(|&(p, c, s)| (p, c, s ^ 7))
+                            .collect::<Vec<_>>(),
+                        position.turn,
+                    );
+                    assert_eq!(table.index(&flipped), index);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use crate::{Color, Piece};
+
+// positions of the tablebase generator
+// a few pieces on squares with their own move generator, much cheaper to
+// copy and play through than a board. there is no castling and no en passant
+// in tablebase positions
+
+pub(crate) const MAX_PIECES: usize = 4;
+
+const KING: [(i8, i8); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+const KNIGHT: [(i8, i8); 8] = [
+    (-2, -1),
+    (-2, 1),
+    (-1, -2),
+    (-1, 2),
+    (1, -2),
+    (1, 2),
+    (2, -1),
+    (2, 1),
+];
+const ROOK: [(i8, i8); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const BISHOP: [(i8, i8); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+const PROMOTIONS: [Piece; 4] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight];
+
+fn offset(square: u8, (file, rank): (i8, i8)) -> Option<u8> {
+    let f = (square % 8) as i8 + file;
+    let r = (square / 8) as i8 + rank;
+    ((0..8).contains(&f) && (0..8).contains(&r)).then_some((r * 8 + f) as u8)
+}
+
+// the directions a piece moves in and whether it slides along them
+fn directions(piece: Piece) -> (&'static [(i8, i8)], bool) {
+    match piece {
+        Piece::King => (&KING, false),
+        Piece::Knight => (&KNIGHT, false),
+        Piece::Rook => (&ROOK, true),
+        Piece::Bishop => (&BISHOP, true),
+        Piece::Queen => (&KING, true),
+        Piece::Pawn => (&[], false),
+    }
+}
+
+fn forward(color: Color) -> i8 {
+    match color {
+        Color::White => 1,
+        Color::Black => -1,
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Position {
+    pieces: [(Piece, Color, u8); MAX_PIECES],
+    len: usize,
+    pub turn: Color,
+}
+
+impl Position {
+    pub fn new(pieces: &[(Piece, Color, u8)], turn: Color) -> Position {
+        let mut position = Position {
+            pieces: [(Piece::King, Color::White, 0); MAX_PIECES],
+            len: pieces.len(),
+            turn,
+        };
+        position.pieces[..pieces.len()].copy_from_slice(pieces);
+        position
+    }
+
+    pub fn pieces(&self) -> &[(Piece, Color, u8)] {
+        &self.pieces[..self.len]
+    }
+
+    fn occupant(&self, square: u8) -> Option<usize> {
+        self.pieces().iter().position(|&(_, _, s)| s == square)
+    }
+
+    fn king(&self, color: Color) -> u8 {
+        self.pieces()
+            .iter()
+            .find(|&&(p, c, _)| p == Piece::King && c == color)
+            .map_or(0, |&(_, _, s)| s)
+    }
+
+    fn attacks(&self, (piece, color, from): (Piece, Color, u8), target: u8) -> bool {
+        if piece == Piece::Pawn {
+            return [-1, 1]
+                .into_iter()
+                .any(|file| offset(from, (file, forward(color))) == Some(target));
+        }
+        let (directions, slides) = directions(piece);
+        directions.iter().any(|&direction| {
+            let mut square = from;
+            while let Some(next) = offset(square, direction) {
+                if next == target {
+                    return true;
+                }
+                if !slides || self.occupant(next).is_some() {
+                    break;
+                }
+                square = next;
+            }
+            false
+        })
+    }
+
+    fn attacked(&self, target: u8, by: Color) -> bool {
+        self.pieces()
+            .iter()
+            .any(|&piece| piece.1 == by && self.attacks(piece, target))
+    }
+
+    pub fn in_check(&self) -> bool {
+        self.attacked(self.king(self.turn), self.turn.opposite())
+    }
+
+    // a position that can come up in a game: one piece per square, no pawns
+    // on the first or last rank and the side that just moved not in check
+    pub fn is_legal(&self) -> bool {
+        let pieces = self.pieces();
+        pieces.iter().enumerate().all(|(i, &(p, _, s))| {
+            pieces[i + 1..].iter().all(|&(_, _, t)| t != s)
+                && (p != Piece::Pawn || (8..56).contains(&s))
+        }) && !self.attacked(self.king(self.turn.opposite()), self.turn)
+    }
+
+    // the squares piece i moves to, empty or holding a piece to capture
+    fn targets(&self, i: usize) -> Vec<u8> {
+        let (piece, color, from) = self.pieces[i];
+        let enemy = |square: u8| {
+            self.occupant(square)
+                .is_some_and(|j| self.pieces[j].1 != color && self.pieces[j].0 != Piece::King)
+        };
+        let mut targets = Vec::new();
+        if piece == Piece::Pawn {
+            let ahead = forward(color);
+            if let Some(one) = offset(from, (0, ahead)).filter(|&s| self.occupant(s).is_none()) {
+                targets.push(one);
+                let start = if color == Color::White { 1 } else { 6 };
+                if from / 8 == start {
+                    targets.extend(offset(one, (0, ahead)).filter(|&s| self.occupant(s).is_none()));
+                }
+            }
+            targets.extend(
+                [-1, 1]
+                    .into_iter()
+                    .filter_map(|file| offset(from, (file, ahead)))
+                    .filter(|&s| enemy(s)),
+            );
+            return targets;
+        }
+        let (directions, slides) = directions(piece);
+        for &direction in directions {
+            let mut square = from;
+            while let Some(next) = offset(square, direction) {
+                match self.occupant(next) {
+                    None => targets.push(next),
+                    Some(_) => {
+                        if enemy(next) {
+                            targets.push(next);
+                        }
+                        break;
+                    }
+                }
+                if !slides {
+                    break;
+                }
+                square = next;
+            }
+        }
+        targets
+    }
+
+    fn remove(&mut self, i: usize) {
+        self.pieces.copy_within(i + 1..self.len, i);
+        self.len -= 1;
+    }
+
+    // the positions after every legal move
+    pub fn children(&self) -> Vec<Position> {
+        let mut children = Vec::new();
+        for i in 0..self.len {
+            if self.pieces[i].1 != self.turn {
+                continue;
+            }
+            for target in self.targets(i) {
+                let mut child = *self;
+                child.pieces[i].2 = target;
+                child.turn = self.turn.opposite();
+                if let Some(j) = (0..self.len).find(|&j| j != i && self.pieces[j].2 == target) {
+                    child.remove(j);
+                }
+                if child.attacked(child.king(self.turn), child.turn) {
+                    continue;
+                }
+                let i = child.occupant(target).unwrap();
+                if child.pieces[i].0 == Piece::Pawn && !(8..56).contains(&target) {
+                    for promotion in PROMOTIONS {
+                        child.pieces[i].0 = promotion;
+                        children.push(child);
+                    }
+                } else {
+                    children.push(child);
+                }
+            }
+        }
+        children
+    }
+
+    // the positions this one is reached from by a move that neither
+    // captures nor promotes, so the material stays the same
+    pub fn parents(&self) -> Vec<Position> {
+        let side = self.turn.opposite();
+        let mut parents = Vec::new();
+        let mut add = |i: usize, square: u8| {
+            let mut parent = *self;
+            parent.pieces[i].2 = square;
+            parent.turn = side;
+            if parent.is_legal() {
+                parents.push(parent);
+            }
+        };
+        for i in 0..self.len {
+            let (piece, color, to) = self.pieces[i];
+            if color != side {
+                continue;
+            }
+            if piece == Piece::Pawn {
+                let back = -forward(color);
+                let Some(one) = offset(to, (0, back)).filter(|&s| self.occupant(s).is_none())
+                else {
+                    continue;
+                };
+                if (8..56).contains(&one) {
+                    add(i, one);
+                }
+                let double = if color == Color::White { 3 } else { 4 };
+                if to / 8 == double {
+                    if let Some(two) =
+                        offset(one, (0, back)).filter(|&s| self.occupant(s).is_none())
+                    {
+                        add(i, two);
+                    }
+                }
+                continue;
+            }
+            // pieces other than pawns move back the way they came
+            let (directions, slides) = directions(piece);
+            for &direction in directions {
+                let mut square = to;
+                while let Some(next) = offset(square, direction) {
+                    if self.occupant(next).is_some() {
+                        break;
+                    }
+                    add(i, next);
+                    if !slides {
+                        break;
+                    }
+                    square = next;
+                }
+            }
+        }
+        parents
+    }
+
+    // the same position with the colors swapped, seen from the other side
+    pub fn mirror(&self) -> Position {
+        let mut mirror = *self;
+        for piece in &mut mirror.pieces[..self.len] {
+            piece.1 = piece.1.opposite();
+            piece.2 ^= 56;
+        }
+        mirror.turn = self.turn.opposite();
+        mirror
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(name: &str) -> u8 {
+        let name = name.as_bytes();
+        (name[1] - b'1') * 8 + (name[0] - b'a')
+    }
+
+    #[test]
+    fn moves_and_unmoves() {
+        let pieces = [
+            (Piece::King, Color::White, square("e1")),
+            (Piece::King, Color::Black, square("e8")),
+            (Piece::Rook, Color::White, square("a1")),
+            (Piece::Pawn, Color::Black, square("b2")),
+        ];
+        let position = Position::new(&pieces, Color::White);
+        assert!(position.is_legal());
+        assert!(!position.in_check());
+        // 5 king moves, the rook up to the king and up the file
+        assert_eq!(position.children().len(), 5 + 3 + 7);
+
+        // promotions with capture
+        let black = Position {
+            turn: Color::Black,
+            ..position
+        };
+        let promotions = black
+            .children()
+            .into_iter()
+            .filter(|c| c.len == 3 && c.pieces().iter().any(|p| p.0 == Piece::Queen))
+            .count();
+        assert_eq!(promotions, 1);
+        assert_eq!(black.children().iter().filter(|c| c.len == 3).count(), 4);
+
+        // every parent has this position among its children
+        for parent in black.parents() {
+            assert!(parent.children().contains(&black), "{:?}", parent);
+        }
+        assert!(!black.parents().is_empty());
+        assert_eq!(position.mirror().mirror(), position);
+    }
+}
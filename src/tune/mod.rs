@@ -0,0 +1,296 @@
+use std::{fs, path::Path, sync::LazyLock};
+
+use regex::Regex;
+
+use crate::{
+    eval::{evaluate_params, Params, KNOWN_WIN},
+    search::{is_quiet, MoveOrdering},
+    Board, Color, Move, TuneError,
+};
+
+// texel tuning of the hand-crafted evaluation
+// positions from games are labeled with the result of their game, the
+// evaluation of each is mapped to an expected result by a sigmoid and the
+// weights are changed one step at a time for as long as the mean squared
+// difference to the labels goes down. only quiet positions are used, the
+// end of the quiescence search pv of every position, as the evaluation of
+// one in the middle of an exchange says little about the game
+
+// the quiescence search looks no deeper than this
+const QUIET_PLIES: usize = 8;
+// how much a parameter is changed in one step
+const STEP: i32 = 1;
+
+#[derive(Debug, Clone)]
+pub struct LabeledPosition {
+    pub board: Board,
+    // result of the game for white, 1 for a win, 0.5 for a draw and 0 for a loss
+    pub result: f64,
+}
+
+// a game result, as in pgn or as a number
+static RESULT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"1/2-1/2|1-0|0-1|[01]\.0|0\.5").unwrap());
+
+// a position with its result, as a fen or the first four fields of one
+// followed by the result in any of the usual ways, eg.
+//   <fen> [1-0]
+//   <fen> [0.5]
+//   <epd> c9 "1/2-1/2";
+pub fn parse_labeled(line: &str) -> Option<LabeledPosition> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let counters = fields
+        .get(4..6)
+        .is_some_and(|f| f.iter().all(|f| f.parse::<u32>().is_ok()));
+    let (fen, rest) = if counters {
+        (fields[..6].join(" "), &fields[6..])
+    } else {
+        (format!("{} 0 1", fields.get(..4)?.join(" ")), &fields[4..])
+    };
+    let result = match RESULT.find(&rest.join(" "))?.as_str() {
+        "1-0" | "1.0" => 1.0,
+        "0-1" | "0.0" => 0.0,
+        _ => 0.5,
+    };
+    let board = Board::from_fen(fen).ok()?;
+    Some(LabeledPosition { board, result })
+}
+
+// the quiet positions of a file, lines without a position and a result
+// are skipped as well as games that are already over
+pub fn read_positions(
+    path: impl AsRef<Path>,
+    params: &Params,
+) -> Result<Vec<LabeledPosition>, TuneError> {
+    let path = path.as_ref();
+    let positions: Vec<LabeledPosition> = fs::read_to_string(path)?
+        .lines()
+        .filter_map(parse_labeled)
+        .filter(|p| !p.board.legal_moves().is_empty())
+        .map(|p| LabeledPosition {
+            board: quiet(&p.board, params),
+            result: p.result,
+        })
+        // known endgames do not depend on the weights
+        .filter(|p| evaluate_params(&p.board, params).abs() < KNOWN_WIN)
+        .collect();
+    if positions.is_empty() {
+        return Err(TuneError::NoPositions(path.display().to_string()));
+    }
+    Ok(positions)
+}
+
+// the position at the end of the quiescence search pv
+pub fn quiet(board: &Board, params: &Params) -> Board {
+    let mut pv = Vec::new();
+    quiescence(board, params, -KNOWN_WIN, KNOWN_WIN, 0, &mut pv);
+    let mut board = board.clone_position();
+    for mv in pv {
+        board.do_move(mv);
+    }
+    board
+}
+
+fn quiescence(
+    board: &Board,
+    params: &Params,
+    mut alpha: i32,
+    beta: i32,
+    ply: usize,
+    pv: &mut Vec<Move>,
+) -> i32 {
+    let stand_pat = evaluate_params(board, params);
+    if stand_pat >= beta || ply >= QUIET_PLIES {
+        return stand_pat;
+    }
+    alpha = alpha.max(stand_pat);
+    let mut moves: Vec<Move> = board
+        .legal_moves()
+        .into_iter()
+        .filter(|&mv| !is_quiet(board, mv))
+        .collect();
+    MoveOrdering::default().sort(board, &mut moves, None, ply);
+    let mut best = stand_pat;
+    for mv in moves {
+        let mut child = board.clone_position();
+        child.do_move(mv);
+        let mut child_pv = Vec::new();
+        let score = -quiescence(&child, params, -beta, -alpha, ply + 1, &mut child_pv);
+        if score > best {
+            best = score;
+            if score > alpha {
+                alpha = score;
+                pv.clear();
+                pv.push(mv);
+                pv.append(&mut child_pv);
+                if score >= beta {
+                    break;
+                }
+            }
+        }
+    }
+    best
+}
+
+// expected result for white of a position evaluated at score centipawns
+pub fn sigmoid(score: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score / 400.0))
+}
+
+pub struct Tuner {
+    positions: Vec<LabeledPosition>,
+    params: Params,
+    // scale of the sigmoid, fitted to the weights the tuning starts from
+    k: f64,
+    error: f64,
+    threads: usize,
+}
+
+impl Tuner {
+    pub fn new(positions: Vec<LabeledPosition>, params: Params) -> Tuner {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let mut tuner = Tuner {
+            positions,
+            params,
+            k: 1.0,
+            error: 0.0,
+            threads,
+        };
+        tuner.fit_k();
+        tuner.error = tuner.error(&tuner.params);
+        tuner
+    }
+
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    pub fn k(&self) -> f64 {
+        self.k
+    }
+
+    // mean squared error of the current weights
+    pub fn current_error(&self) -> f64 {
+        self.error
+    }
+
+    // mean squared difference between the results and the expected results
+    // of the weights
+    pub fn error(&self, params: &Params) -> f64 {
+        let chunk = self.positions.len().div_ceil(self.threads).max(1);
+        let sum: f64 = std::thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .positions
+                .chunks(chunk)
+                .map(|positions| scope.spawn(move || self.squared_errors(positions, params)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("tuning thread panicked"))
+                .sum()
+        });
+        sum / self.positions.len() as f64
+    }
+
+    fn squared_errors(&self, positions: &[LabeledPosition], params: &Params) -> f64 {
+        positions
+            .iter()
+            .map(|p| {
+                let score = evaluate_params(&p.board, params);
+                let white = match p.board.turn {
+                    Color::White => score,
+                    Color::Black => -score,
+                };
+                (p.result - sigmoid(white as f64, self.k)).powi(2)
+            })
+            .sum()
+    }
+
+    // the scale giving the lowest error, error is about convex in it so a
+    // ternary search finds it
+    fn fit_k(&mut self) {
+        let (mut low, mut high) = (0.0, 3.0);
+        for _ in 0..30 {
+            let (a, b) = (low + (high - low) / 3.0, high - (high - low) / 3.0);
+            self.k = a;
+            let error_a = self.error(&self.params);
+            self.k = b;
+            let error_b = self.error(&self.params);
+            if error_a < error_b {
+                high = b;
+            } else {
+                low = a;
+            }
+        }
+        self.k = (low + high) / 2.0;
+    }
+
+    // try moving every parameter a step up, then down, keeping the changes
+    // that lower the error, returns whether any did
+    pub fn pass(&mut self) -> bool {
+        let mut values = self.params.values();
+        let mut improved = false;
+        for i in 0..values.len() {
+            let original = values[i];
+            for step in [STEP, -STEP] {
+                values[i] = original + step;
+                let mut params = self.params.clone();
+                params.set_values(&values);
+                let error = self.error(&params);
+                if error < self.error {
+                    self.error = error;
+                    self.params = params;
+                    improved = true;
+                    break;
+                }
+                values[i] = original;
+            }
+        }
+        improved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labeled_positions() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -";
+        let parsed = |line: &str| parse_labeled(line).map(|p| p.result);
+        assert_eq!(parsed(&format!("{} 0 1 [1-0]", start)), Some(1.0));
+        assert_eq!(parsed(&format!("{} 0 1 [0.5]", start)), Some(0.5));
+        assert_eq!(parsed(&format!("{} c9 \"0-1\";", start)), Some(0.0));
+        assert_eq!(parsed(&format!("{} 1-0", start)), Some(1.0));
+        assert_eq!(parsed("8/8/8 w - 1-0"), None);
+        assert_eq!(parsed(&format!("{} 0 1 1/2-1/2", start)), Some(0.5));
+        assert_eq!(parsed(&format!("{} 0 1", start)), None);
+        assert_eq!(parsed("[Event \"?\"]"), None);
+    }
+
+    #[test]
+    fn quiet_positions() {
+        // white wins a rook with the pawn, then the exchanges are over
+        let board = Board::from_fen("4k3/8/8/3r4/4P3/8/8/4K3 w - - 0 1".to_owned()).unwrap();
+        let quiet = quiet(&board, &Params::DEFAULT);
+        assert_eq!(quiet.fen_notation(), "4k3/8/8/3P4/8/8/8/4K3 b - - 0 1");
+    }
+
+    #[test]
+    fn tuning_lowers_the_error() {
+        // white wins every game, so its tempo bonus should grow
+        let lines = [
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3 [1-0]",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4 [1-0]",
+        ];
+        let positions = lines.iter().filter_map(|l| parse_labeled(l)).collect();
+        let mut tuner = Tuner::new(positions, Params::DEFAULT);
+        let before = tuner.current_error();
+        let mut params = tuner.params().clone();
+        params.tempo.mg += 1;
+        assert!(tuner.error(&params) < before);
+        assert!(tuner.pass());
+        assert!(tuner.current_error() < before);
+        assert_eq!(tuner.error(tuner.params()), tuner.current_error());
+    }
+}
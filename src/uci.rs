@@ -0,0 +1,743 @@
+use std::{
+    fmt::Display,
+    io::{BufRead, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    book::{Book, BookSelection},
+    eval::{Network, Params},
+    mcts::{Playout, Selection},
+    search::{
+        Algorithm, Rng, Search, SearchEvent, SearchLimits, SearchOptions, SearchThread, Skill,
+        SystemClock, TimeControl, TimeManager, MAX_ELO, MAX_SKILL_LEVEL, MIN_ELO,
+    },
+    tablebase, Board, Game, Move,
+};
+
+// Universal Chess Interface
+// commands are read line by line, searches run on a separate thread and
+// report info lines and the best move through the shared output
+
+pub const ENGINE_NAME: &str = concat!("chess ", env!("CARGO_PKG_VERSION"));
+
+const DEFAULT_MOVE_OVERHEAD: u64 = 10;
+const MAX_HASH: usize = 4096;
+const MAX_MULTI_PV: usize = 256;
+const MAX_THREADS: usize = 256;
+const CURRMOVE_AFTER: Duration = Duration::from_secs(3);
+
+// where responses go, shared with the search thread
+pub type Output = Arc<Mutex<dyn Write + Send>>;
+
+pub(crate) fn send(out: &Output, line: impl Display) {
+    let mut out = out.lock().unwrap();
+    // nothing sensible to do when the GUI went away
+    let _ = writeln!(out, "{}", line);
+    let _ = out.flush();
+}
+
+// arguments of the go command
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Go {
+    limits: SearchLimits,
+    time: TimeControl,
+    infinite: bool,
+    ponder: bool,
+}
+
+impl Go {
+    fn parse(args: &[&str]) -> Go {
+        let mut go = Go::default();
+        let mut args = args.iter().peekable();
+        while let Some(&arg) = args.next() {
+            if arg == "searchmoves" {
+                while let Some(mv) = args.peek().and_then(|mv| Move::try_from(**mv).ok()) {
+                    go.limits.search_moves.push(mv);
+                    args.next();
+                }
+                continue;
+            }
+            let mut number = || args.next().and_then(|v| v.parse::<i64>().ok());
+            let millis = |v: Option<i64>| v.map(|v| Duration::from_millis(v.max(0) as u64));
+            match arg {
+                "wtime" => go.time.wtime = millis(number()),
+                "btime" => go.time.btime = millis(number()),
+                "winc" => go.time.winc = millis(number()),
+                "binc" => go.time.binc = millis(number()),
+                "movetime" => go.time.movetime = millis(number()),
+                "movestogo" => go.time.movestogo = number().map(|v| v.max(1) as u32),
+                "depth" => go.limits.depth = number().map(|v| v.max(1) as i32),
+                "nodes" => go.limits.nodes = number().map(|v| v.max(1) as u64),
+                "mate" => go.limits.mate = number().map(|v| v.max(1) as i32),
+                "infinite" => go.infinite = true,
+                "ponder" => go.ponder = true,
+                _ => {}
+            }
+        }
+        go
+    }
+}
+
+pub struct Uci {
+    game: Game,
+    thread: SearchThread,
+    out: Output,
+    move_overhead: Duration,
+    // set while the GUI does not want the best move yet,
+    // during go infinite and go ponder
+    waiting: Arc<AtomicBool>,
+    // the ponder flag of the search, cleared on ponderhit
+    pondering: Arc<AtomicBool>,
+    // Skill Level, or UCI_Elo when UCI_LimitStrength is set
+    skill_level: u8,
+    limit_strength: bool,
+    elo: u32,
+    // the book is only used with OwnBook set
+    book: Option<Book>,
+    own_book: bool,
+    book_selection: BookSelection,
+    rng: Rng,
+}
+
+impl Uci {
+    pub fn new(out: Output) -> Uci {
+        let search = Search::new(SearchOptions::default());
+        Uci {
+            game: Game::default(),
+            pondering: search.ponder_flag(),
+            thread: SearchThread::new(search),
+            out,
+            move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD),
+            waiting: Arc::new(AtomicBool::new(false)),
+            skill_level: MAX_SKILL_LEVEL,
+            limit_strength: false,
+            elo: MAX_ELO,
+            book: None,
+            own_book: false,
+            book_selection: BookSelection::Weighted,
+            rng: Rng::from_time(),
+        }
+    }
+
+    // handle commands until quit or the end of the input
+    pub fn run(&mut self, input: impl BufRead) {
+        for line in input.lines() {
+            let Ok(line) = line else { break };
+            if !self.handle(&line) {
+                break;
+            }
+        }
+        self.stop();
+    }
+
+    // handle a single command, false if the engine should quit
+    pub fn handle(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else {
+            return true;
+        };
+        match command {
+            "uci" => self.uci(),
+            "isready" => self.send("readyok"),
+            "ucinewgame" => {
+                self.stop().clear();
+                self.game = Game::default();
+            }
+            "position" => self.position(args),
+            "go" => self.go(args),
+            "stop" => {
+                self.stop();
+            }
+            // the expected move was played, the search goes on as a normal one
+            "ponderhit" => {
+                self.pondering.store(false, Ordering::Relaxed);
+                self.waiting.store(false, Ordering::Relaxed);
+            }
+            "setoption" => self.set_option(args),
+            "quit" => {
+                self.stop();
+                return false;
+            }
+            _ => self.send(format!("info string unknown command {}", command)),
+        }
+        true
+    }
+
+    fn send(&self, line: impl Display) {
+        send(&self.out, line)
+    }
+
+    fn stop(&mut self) -> &mut Search {
+        self.waiting.store(false, Ordering::Relaxed);
+        self.thread.stop()
+    }
+
+    fn uci(&mut self) {
+        self.send(format!("id name {}", ENGINE_NAME));
+        self.send("id author the chess developers");
+        let options = self.stop().options;
+        self.send(format!(
+            "option name Hash type spin default {} min 1 max {}",
+            options.hash_size, MAX_HASH
+        ));
+        self.send(format!(
+            "option name Threads type spin default {} min 1 max {}",
+            options.threads, MAX_THREADS
+        ));
+        self.send("option name Clear Hash type button");
+        self.send("option name Ponder type check default false");
+        self.send(format!(
+            "option name MultiPV type spin default {} min 1 max {}",
+            options.multi_pv, MAX_MULTI_PV
+        ));
+        self.send(format!(
+            "option name Move Overhead type spin default {} min 0 max 5000",
+            DEFAULT_MOVE_OVERHEAD
+        ));
+        self.send(format!(
+            "option name Skill Level type spin default {} min 0 max {}",
+            MAX_SKILL_LEVEL, MAX_SKILL_LEVEL
+        ));
+        self.send("option name UCI_LimitStrength type check default false");
+        self.send(format!(
+            "option name UCI_Elo type spin default {} min {} max {}",
+            MAX_ELO, MIN_ELO, MAX_ELO
+        ));
+        self.send("option name OwnBook type check default false");
+        self.send("option name BookFile type string default <empty>");
+        self.send("option name Best Book Move type check default false");
+        self.send("option name TablebasePath type string default <empty>");
+        self.send("option name EvalFile type string default <empty>");
+        self.send("option name EvalParams type string default <empty>");
+        for (name, value) in [
+            ("NullMovePruning", options.null_move_pruning),
+            ("LateMoveReductions", options.late_move_reductions),
+            ("ReverseFutilityPruning", options.reverse_futility_pruning),
+            ("FutilityPruning", options.futility_pruning),
+            ("CheckExtensions", options.check_extensions),
+            ("MateSearch", options.mate_search),
+        ] {
+            self.send(format!("option name {} type check default {}", name, value));
+        }
+        self.send("option name Algorithm type combo default AlphaBeta var AlphaBeta var MCTS");
+        self.send(
+            "option name MCTS Playout type combo default Evaluation var Evaluation var Random",
+        );
+        self.send("uciok");
+    }
+
+    // position startpos|fen <fen> [moves <move>...]
+    fn position(&mut self, args: &[&str]) {
+        let moves_at = args
+            .iter()
+            .position(|&a| a == "moves")
+            .unwrap_or(args.len());
+        let board = match args.first() {
+            Some(&"startpos") => Ok(Board::default()),
+            Some(&"fen") => Board::from_fen(args[1..moves_at].join(" ")),
+            _ => {
+                self.send("info string expected startpos or fen");
+                return;
+            }
+        };
+        let mut game = match board {
+            Ok(board) => Game::new(board),
+            Err(e) => {
+                self.send(format!("info string {}", e));
+                return;
+            }
+        };
+        for mv in args.iter().skip(moves_at + 1) {
+            if let Err(e) = game.play_str(mv) {
+                self.send(format!("info string {}", e));
+                return;
+            }
+        }
+        self.game = game;
+    }
+
+    fn go(&mut self, args: &[&str]) {
+        let go = Go::parse(args);
+        // a search still running after go infinite or go ponder ends here,
+        // waiting for it would never return
+        self.stop();
+        // a book move needs no search, unless the GUI wants to wait for it
+        if !go.infinite && !go.ponder {
+            if let Some(mv) = self.book_move(&go.limits) {
+                self.send(format!("bestmove {}", mv));
+                return;
+            }
+        }
+        // the clock starts now, not when the thread gets going
+        let clock = Box::new(SystemClock::default());
+        let board = self.game.board().clone();
+        // pondering keeps the time control for when the move is played
+        let time = if go.infinite {
+            None
+        } else {
+            TimeManager::new(&go.time, board.turn, self.move_overhead, clock)
+        };
+        let history = self.game.hashes();
+        self.waiting
+            .store(go.infinite || go.ponder, Ordering::Relaxed);
+        self.pondering.store(go.ponder, Ordering::Relaxed);
+
+        let out = self.out.clone();
+        let waiting = self.waiting.clone();
+        let stop = self.thread.cancellation_token();
+        self.thread.start(move |search| {
+            let search = search.searcher();
+            search.set_history(history);
+            let info = out.clone();
+            search.set_reporter(Box::new(move |event| match event {
+                SearchEvent::Info(i) => send(&info, i),
+                // only worth the noise in long searches
+                SearchEvent::CurrentMove {
+                    depth,
+                    mv,
+                    number,
+                    time,
+                } if *time >= CURRMOVE_AFTER => send(
+                    &info,
+                    format!(
+                        "info depth {} currmove {} currmovenumber {}",
+                        depth, mv, number
+                    ),
+                ),
+                _ => {}
+            }));
+            let result = match time {
+                Some(time) => search.search_with_time(&board, &go.limits, time),
+                None => search.search_with_limits(&board, &go.limits),
+            };
+            // the best move must not be sent before the GUI asks for it
+            while waiting.load(Ordering::Relaxed) && !stop.is_cancelled() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            // the reply the engine expects is what it ponders on next
+            match (result.best_move, result.pv.get(1)) {
+                (Some(mv), Some(reply)) => send(&out, format!("bestmove {} ponder {}", mv, reply)),
+                (Some(mv), None) => send(&out, format!("bestmove {}", mv)),
+                (None, _) => send(&out, "bestmove 0000"),
+            }
+        });
+    }
+
+    fn book_move(&mut self, limits: &SearchLimits) -> Option<Move> {
+        let book = self.book.as_ref().filter(|_| self.own_book)?;
+        let mv = book.pick(self.game.board(), self.book_selection, &mut self.rng)?;
+        (limits.search_moves.is_empty() || limits.search_moves.contains(&mv)).then_some(mv)
+    }
+
+    fn load_book(&mut self, path: &str) {
+        self.book = None;
+        if path.is_empty() || path == "<empty>" {
+            return;
+        }
+        match Book::open(path) {
+            Ok(book) => self.book = Some(book),
+            Err(e) => self.send(format!("info string {}", e)),
+        }
+    }
+
+    fn load_tablebases(&mut self, dir: &str) {
+        if dir.is_empty() || dir == "<empty>" {
+            return;
+        }
+        match tablebase::load_dir(dir) {
+            Ok(count) => self.send(format!("info string {} tablebases loaded", count)),
+            Err(e) => self.send(format!("info string {}", e)),
+        }
+    }
+
+    // without a network the hand-crafted evaluation is used
+    fn load_network(&mut self, path: &str) {
+        let network = if path.is_empty() || path == "<empty>" {
+            None
+        } else {
+            match Network::load(path) {
+                Ok(network) => {
+                    self.send(format!(
+                        "info string network with {} hidden neurons loaded",
+                        network.hidden()
+                    ));
+                    Some(network)
+                }
+                Err(e) => {
+                    self.send(format!("info string {}", e));
+                    None
+                }
+            }
+        };
+        self.stop().set_network(network);
+    }
+
+    // tuned weights for the hand-crafted evaluation, the defaults without
+    fn load_params(&mut self, path: &str) {
+        let params = if path.is_empty() || path == "<empty>" {
+            Params::default()
+        } else {
+            match Params::read(path) {
+                Ok(params) => params,
+                Err(e) => {
+                    self.send(format!("info string {}", e));
+                    Params::default()
+                }
+            }
+        };
+        self.stop().set_params(Arc::new(params));
+    }
+
+    // setoption name <name> [value <value>]
+    fn set_option(&mut self, args: &[&str]) {
+        let value_at = args
+            .iter()
+            .position(|&a| a == "value")
+            .unwrap_or(args.len());
+        let name = args
+            .get(1..value_at)
+            .unwrap_or_default()
+            .join(" ")
+            .to_lowercase();
+        let value = args.get(value_at + 1..).unwrap_or_default().join(" ");
+        let number = value.parse::<u64>();
+        let check = value.parse::<bool>();
+        let search = self.stop();
+        match (name.as_str(), number, check) {
+            ("hash", Ok(mb), _) => search.set_hash_size((mb as usize).clamp(1, MAX_HASH)),
+            ("clear hash", _, _) => search.clear(),
+            // the GUI decides when to ponder
+            ("ponder", _, Ok(_)) => {}
+            ("threads", Ok(n), _) => search.options.threads = (n as usize).clamp(1, MAX_THREADS),
+            ("multipv", Ok(n), _) => search.options.multi_pv = (n as usize).clamp(1, MAX_MULTI_PV),
+            ("move overhead", Ok(ms), _) => self.move_overhead = Duration::from_millis(ms),
+            ("skill level", Ok(level), _) => self.skill_level = level.min(u8::MAX as u64) as u8,
+            ("uci_limitstrength", _, Ok(on)) => self.limit_strength = on,
+            ("uci_elo", Ok(elo), _) => self.elo = elo.min(u32::MAX as u64) as u32,
+            ("ownbook", _, Ok(on)) => self.own_book = on,
+            ("bookfile", _, _) => self.load_book(&value),
+            ("tablebasepath", _, _) => self.load_tablebases(&value),
+            ("evalfile", _, _) => self.load_network(&value),
+            ("evalparams", _, _) => self.load_params(&value),
+            ("best book move", _, Ok(best)) => {
+                self.book_selection = if best {
+                    BookSelection::Best
+                } else {
+                    BookSelection::Weighted
+                }
+            }
+            ("nullmovepruning", _, Ok(on)) => search.options.null_move_pruning = on,
+            ("latemovereductions", _, Ok(on)) => search.options.late_move_reductions = on,
+            ("reversefutilitypruning", _, Ok(on)) => search.options.reverse_futility_pruning = on,
+            ("futilitypruning", _, Ok(on)) => search.options.futility_pruning = on,
+            ("checkextensions", _, Ok(on)) => search.options.check_extensions = on,
+            ("matesearch", _, Ok(on)) => search.options.mate_search = on,
+            ("algorithm", _, _) if value == "AlphaBeta" => {
+                search.options.algorithm = Algorithm::AlphaBeta
+            }
+            ("algorithm", _, _) if value == "MCTS" => search.options.algorithm = Algorithm::Mcts,
+            // random playouts are scored by chance, uct tries every move once
+            ("mcts playout", _, _) if value == "Evaluation" => {
+                search.options.mcts.selection = Selection::Puct;
+                search.options.mcts.playout = Playout::Evaluation;
+            }
+            ("mcts playout", _, _) if value == "Random" => {
+                search.options.mcts.selection = Selection::Uct;
+                search.options.mcts.playout = Playout::Random;
+            }
+            _ => self.send(format!("info string invalid option {} {}", name, value)),
+        }
+        let skill = if self.limit_strength {
+            Skill::from_elo(self.elo)
+        } else {
+            Skill::new(self.skill_level)
+        };
+        self.thread.wait().options.skill = skill;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::INPUTS;
+
+    fn engine() -> (Uci, Arc<Mutex<Vec<u8>>>) {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        (Uci::new(buffer.clone()), buffer)
+    }
+
+    fn output(buffer: &Arc<Mutex<Vec<u8>>>) -> String {
+        String::from_utf8(buffer.lock().unwrap().clone()).unwrap()
+    }
+
+    #[test]
+    fn parse_go() {
+        let go = Go::parse(&[
+            "wtime",
+            "1000",
+            "btime",
+            "2000",
+            "winc",
+            "10",
+            "movestogo",
+            "5",
+        ]);
+        assert_eq!(go.time.wtime, Some(Duration::from_millis(1000)));
+        assert_eq!(go.time.btime, Some(Duration::from_millis(2000)));
+        assert_eq!(go.time.winc, Some(Duration::from_millis(10)));
+        assert_eq!(go.time.movestogo, Some(5));
+        assert_eq!(go.limits, SearchLimits::default());
+
+        let go = Go::parse(&["mate", "2", "nodes", "500", "depth", "7"]);
+        assert_eq!(go.limits.mate, Some(2));
+        assert_eq!(go.limits.nodes, Some(500));
+        assert_eq!(go.limits.depth, Some(7));
+        assert!(Go::parse(&["infinite"]).infinite);
+
+        let go = Go::parse(&["searchmoves", "e2e4", "d2d4", "infinite"]);
+        assert_eq!(go.limits.search_moves.len(), 2);
+        assert_eq!(go.limits.search_moves[1], Move::try_from("d2d4").unwrap());
+        assert!(go.infinite);
+    }
+
+    #[test]
+    fn position() {
+        let (mut uci, buffer) = engine();
+        uci.handle("position startpos moves e2e4 c7c5 g1f3");
+        assert_eq!(
+            uci.game.board().fen_notation(),
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+        uci.handle("position fen 4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 moves e2e4");
+        assert_eq!(
+            uci.game.board().fen_notation(),
+            "4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1"
+        );
+        // a bad move keeps the previous position
+        uci.handle("position startpos moves e2e5");
+        assert_eq!(
+            uci.game.board().fen_notation(),
+            "4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1"
+        );
+        assert!(output(&buffer).contains("info string Illegal move: e2e5"));
+    }
+
+    #[test]
+    fn bad_fen() {
+        let (mut uci, buffer) = engine();
+        for fen in [
+            "pppppppppp/8/8/8/8/8/8/8 w - - 0 1",
+            "4k3/8/8/8/8/8/8 w - - 0 1",
+            "4k3/8/8/8/8/8/8/8 w - - 0 1",
+            "4k3/8/8/8/8/8/8/3KK3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K39 w - - 0 1",
+            "4k3/8/8/8/8/8/4P3/4K3 w - a1 0 1",
+            "4k3/8/8/8/4P3/8/8/4K3 w - e3 0 1",
+        ] {
+            uci.handle(&format!("position fen {}", fen));
+            assert_eq!(
+                uci.game.board().fen_notation(),
+                Board::default().fen_notation()
+            );
+        }
+        let output = output(&buffer);
+        assert_eq!(
+            output.matches("info string Invalid FEN Notation").count(),
+            7,
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn search() {
+        let (mut uci, buffer) = engine();
+        for command in [
+            "uci",
+            "isready",
+            "setoption name Hash value 1",
+            "position startpos",
+        ] {
+            assert!(uci.handle(command));
+        }
+        uci.handle("go depth 3");
+        uci.thread.wait();
+        let output = output(&buffer);
+        assert!(output.contains("option name Hash type spin"));
+        assert!(output.contains("uciok\nreadyok\n"));
+        assert!(output.contains("info depth 3 "));
+        assert!(output.lines().last().unwrap().starts_with("bestmove "));
+        assert!(!uci.handle("quit"));
+    }
+
+    #[test]
+    fn multi_pv() {
+        let (mut uci, buffer) = engine();
+        uci.handle("setoption name MultiPV value 3");
+        uci.handle("position startpos");
+        uci.handle("go depth 3");
+        uci.thread.wait();
+        let output = output(&buffer);
+        let mut moves: Vec<&str> = (1..=3)
+            .map(|n| {
+                let line = output
+                    .lines()
+                    .find(|l| {
+                        l.starts_with("info depth 3 ") && l.contains(&format!(" multipv {} ", n))
+                    })
+                    .unwrap_or_else(|| panic!("no multipv {} in {}", n, output));
+                line.split(" pv ")
+                    .nth(1)
+                    .unwrap()
+                    .split(' ')
+                    .next()
+                    .unwrap()
+            })
+            .collect();
+        // three different moves
+        moves.sort();
+        moves.dedup();
+        assert_eq!(moves.len(), 3, "{}", output);
+        assert!(output.lines().last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn mcts_option() {
+        let (mut uci, buffer) = engine();
+        uci.handle("uci");
+        assert!(output(&buffer).contains("option name Algorithm type combo default AlphaBeta"));
+        uci.handle("setoption name Algorithm value MCTS");
+        assert_eq!(uci.thread.wait().options.algorithm, Algorithm::Mcts);
+        uci.handle("position fen 6k1/5ppp/8/8/8/8/8/3R2K1 w - - 0 1");
+        uci.handle("go nodes 200");
+        uci.thread.wait();
+        let output = output(&buffer);
+        assert!(output.contains("info depth "));
+        assert!(output.ends_with("bestmove d1d8\n"), "{}", output);
+    }
+
+    #[test]
+    fn eval_file() {
+        let (mut uci, buffer) = engine();
+        uci.handle("uci");
+        assert!(output(&buffer).contains("option name EvalFile type string default <empty>"));
+        let hidden = 16;
+        let network = Network::new(
+            hidden,
+            400,
+            vec![1; INPUTS * hidden],
+            vec![0; hidden],
+            vec![1; 2 * hidden],
+            0,
+        )
+        .unwrap();
+        let path = std::env::temp_dir().join(format!("uci-{}.nnue", std::process::id()));
+        network.write(&path).unwrap();
+        uci.handle(&format!("setoption name EvalFile value {}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        assert!(output(&buffer).contains("info string network with 16 hidden neurons loaded"));
+        uci.handle("position fen 6k1/5ppp/8/8/8/8/8/3R2K1 w - - 0 1");
+        uci.handle("go depth 3");
+        uci.thread.wait();
+        assert!(output(&buffer).ends_with("bestmove d1d8\n"));
+
+        uci.handle("setoption name EvalFile value /nonexistent/net.nnue");
+        assert!(output(&buffer).contains("info string Cannot read network"));
+        uci.handle("setoption name EvalParams value /nonexistent/params.txt");
+        assert!(output(&buffer).contains("info string Cannot read parameters"));
+    }
+
+    #[test]
+    fn skill_options() {
+        let (mut uci, buffer) = engine();
+        uci.handle("uci");
+        assert!(output(&buffer).contains("option name Skill Level type spin default 20"));
+        uci.handle("setoption name Skill Level value 3");
+        assert_eq!(uci.thread.wait().options.skill, Skill::new(3));
+        // the elo only counts with limited strength
+        uci.handle("setoption name UCI_Elo value 800");
+        assert_eq!(uci.thread.wait().options.skill, Skill::new(3));
+        uci.handle("setoption name UCI_LimitStrength value true");
+        assert_eq!(uci.thread.wait().options.skill, Skill::new(0));
+        uci.handle("setoption name UCI_LimitStrength value false");
+        uci.handle("setoption name Skill Level value 20");
+        assert!(!uci.thread.wait().options.skill.is_limited());
+    }
+
+    #[test]
+    fn own_book() {
+        let (mut uci, buffer) = engine();
+        // one entry: e2e4 from the starting position
+        let mut bytes = 0x463b_9618_1691_fc9c_u64.to_be_bytes().to_vec();
+        bytes.extend([0x03, 0x1c, 0, 1, 0, 0, 0, 0]);
+        let path = std::env::temp_dir().join(format!("chess-uci-{}.bin", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        uci.handle(&format!("setoption name BookFile value {}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+
+        // the book is not used until asked for
+        uci.handle("position startpos");
+        uci.handle("go depth 1");
+        uci.thread.wait();
+        assert!(output(&buffer).contains("info depth 1"));
+        uci.handle("setoption name OwnBook value true");
+        buffer.lock().unwrap().clear();
+        uci.handle("go depth 5");
+        assert_eq!(output(&buffer), "bestmove e2e4\n");
+        // out of book the engine searches
+        uci.handle("position startpos moves e2e4");
+        uci.handle("go depth 1");
+        uci.thread.wait();
+        assert!(output(&buffer).contains("info depth 1"));
+
+        uci.handle("setoption name BookFile value /nonexistent/book.bin");
+        assert!(output(&buffer).contains("info string Cannot read book"));
+    }
+
+    #[test]
+    fn infinite_waits_for_stop() {
+        let (mut uci, buffer) = engine();
+        uci.handle("position fen 6k1/5ppp/8/8/8/8/8/3R2K1 w - - 0 1");
+        uci.handle("go infinite");
+        thread::sleep(Duration::from_millis(50));
+        assert!(!output(&buffer).contains("bestmove"));
+        uci.handle("stop");
+        assert!(output(&buffer).ends_with("bestmove d1d8\n"));
+    }
+
+    #[test]
+    fn go_replaces_infinite_search() {
+        let (mut uci, buffer) = engine();
+        uci.handle("position fen 6k1/5ppp/8/8/8/8/8/3R2K1 w - - 0 1");
+        uci.handle("go infinite");
+        thread::sleep(Duration::from_millis(50));
+        uci.handle("go depth 1");
+        uci.thread.wait();
+        let output = output(&buffer);
+        assert_eq!(output.matches("bestmove d1d8").count(), 2, "{}", output);
+        assert!(output.ends_with("bestmove d1d8\n"));
+    }
+
+    #[test]
+    fn ponder() {
+        let (mut uci, buffer) = engine();
+        uci.handle("position startpos moves e2e4 e7e5");
+        uci.handle("go ponder movetime 50");
+        thread::sleep(Duration::from_millis(100));
+        // pondering ignores the clock
+        assert!(uci.thread.is_searching());
+        assert!(!output(&buffer).contains("bestmove"));
+        uci.handle("ponderhit");
+        uci.thread.wait();
+        let output = output(&buffer);
+        let last = output.lines().last().unwrap();
+        assert!(last.starts_with("bestmove "));
+        assert!(last.contains(" ponder "));
+    }
+}
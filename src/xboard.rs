@@ -0,0 +1,585 @@
+use std::{
+    fmt::Display,
+    io::BufRead,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    search::{
+        Bound, Search, SearchEvent, SearchLimits, SearchOptions, SearchThread, SystemClock,
+        TimeControl, TimeManager, MATE, MATE_BOUND, MAX_PLY,
+    },
+    uci::{send, Output, ENGINE_NAME},
+    Board, BoardStatus, Color, Game, Move,
+};
+
+// Chess Engine Communication Protocol, as spoken by XBoard and WinBoard
+// the engine plays one side of the game and answers the moves of the other
+// side by itself; unlike UCI the engine keeps the game and the clocks
+
+const DEFAULT_MOVE_OVERHEAD: Duration = Duration::from_millis(10);
+// time per move when no clock has been set
+const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(5);
+
+// clock set with the level command
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+struct Level {
+    // moves per time control, 0 for the whole game
+    moves: u32,
+    base: Duration,
+    increment: Duration,
+}
+
+impl Level {
+    // level <moves> <minutes[:seconds]> <increment seconds>
+    fn parse(args: &[&str]) -> Option<Level> {
+        let [moves, base, increment] = args else {
+            return None;
+        };
+        let (minutes, seconds) = base.split_once(':').unwrap_or((base, "0"));
+        let base = minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?;
+        Some(Level {
+            moves: moves.parse().ok()?,
+            base: Duration::from_secs(base),
+            increment: Duration::from_secs_f64(increment.parse::<f64>().ok()?.max(0.0)),
+        })
+    }
+}
+
+// the clocks as set by the interface, shared with the search thread which
+// keeps the engine's own clock running between time commands
+#[derive(Debug, Copy, Clone, Default)]
+struct Clocks {
+    level: Level,
+    movetime: Option<Duration>,
+    time: Option<Duration>,
+    opponent_time: Option<Duration>,
+    // when the engine's clock started, None while it is not running
+    started: Option<Instant>,
+}
+
+impl Clocks {
+    // time control for the engine playing color in the given move of the game,
+    // a fixed time per move if there is no clock and no depth limit
+    fn time_control(&self, color: Color, fullmove_number: u32, depth: Option<i32>) -> TimeControl {
+        let movestogo = if self.level.moves > 0 {
+            let played = fullmove_number - 1;
+            Some(self.level.moves - played % self.level.moves)
+        } else {
+            None
+        };
+        let (mut wtime, mut btime) = (self.time, self.opponent_time);
+        if color == Color::Black {
+            std::mem::swap(&mut wtime, &mut btime);
+        }
+        let movetime = match self.movetime {
+            None if self.time.is_none() && depth.is_none() => Some(DEFAULT_MOVE_TIME),
+            movetime => movetime,
+        };
+        TimeControl {
+            wtime,
+            btime,
+            winc: Some(self.level.increment),
+            binc: Some(self.level.increment),
+            movestogo,
+            movetime,
+        }
+    }
+
+    // stop the engine's clock after it moved
+    fn stop(&mut self) {
+        let elapsed = self.started.take().map(|started| started.elapsed());
+        if let (Some(time), Some(elapsed)) = (&mut self.time, elapsed) {
+            *time = time.saturating_sub(elapsed) + self.level.increment;
+        }
+    }
+}
+
+// score in centipawns, mates as 100000 plus the number of moves
+fn format_score(score: i32) -> i32 {
+    if score >= MATE_BOUND {
+        100000 + (MATE - score + 1) / 2
+    } else if score <= -MATE_BOUND {
+        -100000 - (MATE + score) / 2
+    } else {
+        score
+    }
+}
+
+// result command for a finished game
+fn game_result(game: &Game) -> Option<&'static str> {
+    let board = game.board();
+    let hash = board.hash();
+    match board.status {
+        BoardStatus::Checkmate(Color::White) => Some("0-1 {Black mates}"),
+        BoardStatus::Checkmate(Color::Black) => Some("1-0 {White mates}"),
+        BoardStatus::Stalemate => Some("1/2-1/2 {Stalemate}"),
+        _ if board.halfmove_clock >= 100 => Some("1/2-1/2 {Fifty move rule}"),
+        _ if game.hashes().iter().filter(|&&h| h == hash).count() >= 2 => {
+            Some("1/2-1/2 {Draw by repetition}")
+        }
+        _ => None,
+    }
+}
+
+pub struct XBoard {
+    // shared with the search thread, which plays the engine's moves
+    game: Arc<Mutex<Game>>,
+    thread: SearchThread,
+    out: Output,
+    // side the engine plays, None in force mode
+    engine: Option<Color>,
+    // print thinking output
+    post: bool,
+    depth: Option<i32>,
+    clocks: Arc<Mutex<Clocks>>,
+    // set when the move of the running search should not be played,
+    // eg. because the user took a move back
+    discard: Arc<AtomicBool>,
+    // think on the opponent's time
+    ponder: Arc<AtomicBool>,
+    // the ponder flag of the search and the move it ponders on
+    pondering: Arc<AtomicBool>,
+    expected: Arc<Mutex<Option<Move>>>,
+}
+
+impl XBoard {
+    pub fn new(out: Output) -> XBoard {
+        let search = Search::new(SearchOptions::default());
+        XBoard {
+            game: Arc::default(),
+            pondering: search.ponder_flag(),
+            thread: SearchThread::new(search),
+            out,
+            engine: Some(Color::Black),
+            post: false,
+            depth: None,
+            clocks: Arc::default(),
+            discard: Arc::new(AtomicBool::new(false)),
+            ponder: Arc::default(),
+            expected: Arc::default(),
+        }
+    }
+
+    // handle commands until quit or the end of the input
+    pub fn run(&mut self, input: impl BufRead) {
+        for line in input.lines() {
+            let Ok(line) = line else { break };
+            if !self.handle(&line) {
+                break;
+            }
+        }
+        self.stop();
+    }
+
+    // handle a single command, false if the engine should quit
+    pub fn handle(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else {
+            return true;
+        };
+        match command {
+            "xboard" | "accepted" | "rejected" | "random" | "computer" | "white" | "black" => {}
+            "hard" => self.ponder.store(true, Ordering::Relaxed),
+            "easy" => {
+                self.ponder.store(false, Ordering::Relaxed);
+                if self.pondering.load(Ordering::Relaxed) {
+                    self.stop();
+                }
+            }
+            "protover" => self.features(),
+            "new" => {
+                self.stop().clear();
+                *self.game() = Game::default();
+                self.engine = Some(Color::Black);
+                self.depth = None;
+                self.clocks().movetime = None;
+            }
+            "setboard" => self.set_board(args),
+            "usermove" => match args.first() {
+                Some(mv) => self.user_move(mv),
+                None => self.error("usermove", "missing move"),
+            },
+            "go" => {
+                self.stop();
+                let turn = self.game().board().turn;
+                self.engine = Some(turn);
+                self.think();
+            }
+            "force" => {
+                self.stop();
+                self.engine = None;
+            }
+            // move now
+            "?" => self.thread.cancellation_token().cancel(),
+            "level" => match Level::parse(args) {
+                Some(level) => {
+                    let mut clocks = self.clocks();
+                    clocks.level = level;
+                    clocks.movetime = None;
+                }
+                None => self.error(command, "expected level <moves> <base> <increment>"),
+            },
+            "st" => match args.first().and_then(|s| s.parse::<f64>().ok()) {
+                Some(seconds) => {
+                    self.clocks().movetime = Some(Duration::from_secs_f64(seconds.max(0.0)))
+                }
+                None => self.error(command, "expected seconds"),
+            },
+            "sd" => match args.first().and_then(|s| s.parse::<i32>().ok()) {
+                Some(depth) => self.depth = Some(depth.clamp(1, MAX_PLY as i32)),
+                None => self.error(command, "expected depth"),
+            },
+            "time" | "otim" => match args.first().and_then(|s| s.parse::<u64>().ok()) {
+                // centiseconds
+                Some(cs) => {
+                    let time = Some(Duration::from_millis(cs * 10));
+                    let mut clocks = self.clocks();
+                    if command == "time" {
+                        clocks.time = time;
+                    } else {
+                        clocks.opponent_time = time;
+                    }
+                }
+                None => self.error(command, "expected centiseconds"),
+            },
+            "undo" | "remove" => {
+                self.stop();
+                let moves = if command == "undo" { 1 } else { 2 };
+                let mut game = self.game();
+                for _ in 0..moves {
+                    game.undo();
+                }
+            }
+            "result" => {
+                self.stop();
+                self.engine = None;
+            }
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "ping" => self.send(format!("pong {}", args.first().unwrap_or(&""))),
+            "quit" => {
+                self.stop();
+                return false;
+            }
+            // old versions send moves without usermove
+            _ if Move::try_from(command).is_ok() => self.user_move(command),
+            _ => self.error(command, "unknown command"),
+        }
+        true
+    }
+
+    fn send(&self, line: impl Display) {
+        send(&self.out, line)
+    }
+
+    fn error(&self, command: &str, reason: &str) {
+        self.send(format!("Error ({}): {}", reason, command))
+    }
+
+    fn game(&self) -> std::sync::MutexGuard<'_, Game> {
+        self.game.lock().unwrap()
+    }
+
+    fn clocks(&self) -> std::sync::MutexGuard<'_, Clocks> {
+        self.clocks.lock().unwrap()
+    }
+
+    // abort the running search without playing its move
+    fn stop(&mut self) -> &mut Search {
+        self.discard.store(true, Ordering::Relaxed);
+        *self.expected.lock().unwrap() = None;
+        self.thread.stop()
+    }
+
+    fn features(&self) {
+        self.send(format!(
+            "feature myname=\"{}\" setboard=1 usermove=1 ping=1 playother=0 san=0 \
+             colors=0 sigint=0 sigterm=0 analyze=0 reuse=1 done=1",
+            ENGINE_NAME
+        ));
+    }
+
+    fn set_board(&mut self, args: &[&str]) {
+        self.stop();
+        match Board::from_fen(args.join(" ")) {
+            Ok(board) => *self.game() = Game::new(board),
+            Err(e) => self.send(format!("tellusererror {}", e)),
+        }
+    }
+
+    fn user_move(&mut self, mv: &str) {
+        let expected = self.expected.lock().unwrap().take();
+        let ponder_hit = expected.is_some() && expected == Move::try_from(mv).ok();
+        if !ponder_hit {
+            self.stop();
+        }
+        let played = self.game().play_str(mv);
+        if played.is_err() {
+            return self.send(format!("Illegal move: {}", mv));
+        }
+        // the search already has this position, now it plays for real
+        if ponder_hit {
+            self.clocks().started = Some(Instant::now());
+            self.pondering.store(false, Ordering::Relaxed);
+            return;
+        }
+        let (result, turn) = {
+            let game = self.game();
+            (game_result(&game), game.board().turn)
+        };
+        match result {
+            Some(result) => self.send(result),
+            None if self.engine == Some(turn) => self.think(),
+            None => {}
+        }
+    }
+
+    // search for a move for the side to move and play it
+    fn think(&mut self) {
+        let clock = Box::new(SystemClock::default());
+        let (board, history) = {
+            let game = self.game();
+            (game.board().clone(), game.hashes())
+        };
+        if game_result(&self.game()).is_some() {
+            return;
+        }
+        let depth = self.depth;
+        let tc = {
+            let mut clocks = self.clocks();
+            clocks.started = Some(Instant::now());
+            clocks.time_control(board.turn, board.fullmove_number as u32, depth)
+        };
+        let time = TimeManager::new(&tc, board.turn, DEFAULT_MOVE_OVERHEAD, clock);
+        let limits = SearchLimits {
+            depth,
+            ..Default::default()
+        };
+        let post = self.post;
+        let ponder = self.ponder.clone();
+        self.discard.store(false, Ordering::Relaxed);
+        self.pondering.store(false, Ordering::Relaxed);
+
+        let out = self.out.clone();
+        let game = self.game.clone();
+        let discard = self.discard.clone();
+        let pondering = self.pondering.clone();
+        let expected = self.expected.clone();
+        let clocks = self.clocks.clone();
+        let stop = self.thread.cancellation_token();
+        self.thread.start(move |search| {
+            let info = out.clone();
+            search.set_reporter(move |event| match event {
+                SearchEvent::Info(i) if post && i.bound == Bound::Exact => {
+                    let pv: Vec<String> = i.pv.iter().map(Move::to_string).collect();
+                    send(
+                        &info,
+                        format!(
+                            "{} {} {} {} {}",
+                            i.depth,
+                            format_score(i.score),
+                            i.time.as_millis() / 10,
+                            i.nodes,
+                            pv.join(" ")
+                        ),
+                    );
+                }
+                _ => {}
+            });
+            let (mut board, mut history, mut time) = (board, history, time);
+            loop {
+                search.set_history(history);
+                let result = match time {
+                    Some(time) => search.search_with_time(&board, &limits, time),
+                    None => search.search_with_limits(&board, &limits),
+                };
+                // a ponder search must not play before the expected move is
+                while pondering.load(Ordering::Relaxed) && !stop.is_cancelled() {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                let Some(mv) = result.best_move else { return };
+                let mut game = game.lock().unwrap();
+                if discard.load(Ordering::Relaxed)
+                    || game.board().hash() != board.hash()
+                    || game.play(mv).is_err()
+                {
+                    return;
+                }
+                send(&out, format!("move {}", mv));
+                clocks.lock().unwrap().stop();
+                if let Some(result) = game_result(&game) {
+                    return send(&out, result);
+                }
+
+                // think about the position after the reply the engine
+                // expects until the opponent moves
+                let Some(&reply) = result.pv.get(1).filter(|_| ponder.load(Ordering::Relaxed))
+                else {
+                    return;
+                };
+                let mut next = game.clone();
+                if next.play(reply).is_err() || game_result(&next).is_some() {
+                    return;
+                }
+                board = next.board().clone();
+                history = next.hashes();
+                let tc = clocks.lock().unwrap().time_control(
+                    board.turn,
+                    board.fullmove_number as u32,
+                    depth,
+                );
+                let clock = Box::new(SystemClock::default());
+                time = TimeManager::new(&tc, board.turn, DEFAULT_MOVE_OVERHEAD, clock);
+                pondering.store(true, Ordering::Relaxed);
+                *expected.lock().unwrap() = Some(reply);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine() -> (XBoard, Arc<Mutex<Vec<u8>>>) {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        (XBoard::new(buffer.clone()), buffer)
+    }
+
+    fn output(buffer: &Arc<Mutex<Vec<u8>>>) -> String {
+        String::from_utf8(buffer.lock().unwrap().clone()).unwrap()
+    }
+
+    #[test]
+    fn parse_level() {
+        let level = Level::parse(&["40", "5", "0"]).unwrap();
+        assert_eq!(level.moves, 40);
+        assert_eq!(level.base, Duration::from_secs(300));
+        let level = Level::parse(&["0", "2:30", "1.5"]).unwrap();
+        assert_eq!(level.base, Duration::from_secs(150));
+        assert_eq!(level.increment, Duration::from_millis(1500));
+        assert_eq!(Level::parse(&["0", "5"]), None);
+    }
+
+    #[test]
+    fn time_controls() {
+        // without any clock the engine gets a fixed time per move
+        let mut clocks = Clocks::default();
+        let tc = clocks.time_control(Color::White, 1, None);
+        assert_eq!(tc.movetime, Some(DEFAULT_MOVE_TIME));
+        assert_eq!(clocks.time_control(Color::White, 1, Some(4)).movetime, None);
+
+        clocks.level = Level::parse(&["40", "5", "2"]).unwrap();
+        clocks.time = Some(Duration::from_secs(60));
+        clocks.opponent_time = Some(Duration::from_secs(30));
+        let tc = clocks.time_control(Color::Black, 41, None);
+        assert_eq!(tc.movetime, None);
+        assert_eq!(tc.btime, Some(Duration::from_secs(60)));
+        assert_eq!(tc.wtime, Some(Duration::from_secs(30)));
+        assert_eq!(tc.movestogo, Some(40));
+
+        // the engine's clock runs while it thinks and gets the increment
+        clocks.started = Some(Instant::now() - Duration::from_secs(10));
+        clocks.stop();
+        let time = clocks.time.unwrap();
+        assert!(time <= Duration::from_secs(52) && time > Duration::from_secs(51));
+        clocks.stop();
+        assert_eq!(clocks.time, Some(time));
+    }
+
+    #[test]
+    fn plays_moves() {
+        let (mut xboard, buffer) = engine();
+        for command in [
+            "xboard",
+            "protover 2",
+            "new",
+            "sd 2",
+            "post",
+            "usermove e2e4",
+        ] {
+            assert!(xboard.handle(command));
+        }
+        xboard.thread.wait();
+        let out = output(&buffer);
+        assert!(out.starts_with("feature myname="));
+        assert!(out.lines().any(|l| l.starts_with("2 ")));
+        let reply = out.lines().last().unwrap().strip_prefix("move ").unwrap();
+        assert_eq!(xboard.game().moves().count(), 2);
+        assert_eq!(xboard.game().moves().last().unwrap().to_string(), reply);
+
+        xboard.handle("remove");
+        assert_eq!(xboard.game().moves().count(), 0);
+        xboard.handle("ping 7");
+        assert!(output(&buffer).ends_with("pong 7\n"));
+    }
+
+    #[test]
+    fn force_mode() {
+        let (mut xboard, buffer) = engine();
+        xboard.handle("new");
+        xboard.handle("force");
+        xboard.handle("usermove e2e4");
+        xboard.handle("e7e5");
+        xboard.handle("usermove e1e3");
+        xboard.thread.wait();
+        assert_eq!(output(&buffer), "Illegal move: e1e3\n");
+        assert_eq!(xboard.game().moves().count(), 2);
+        xboard.handle("undo");
+        assert_eq!(xboard.game().board().turn, Color::Black);
+    }
+
+    #[test]
+    fn ponder() {
+        let (mut xboard, buffer) = engine();
+        for command in ["new", "hard", "sd 2", "usermove e2e4"] {
+            xboard.handle(command);
+        }
+        // wait until the engine moved and ponders on the reply
+        while xboard.expected.lock().unwrap().is_none() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(xboard.thread.is_searching());
+        let expected = xboard.expected.lock().unwrap().unwrap();
+        xboard.handle(&format!("usermove {}", expected));
+        while output(&buffer).lines().count() < 2 {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(xboard.game().moves().count(), 4);
+
+        // a different move stops pondering and starts a new search
+        while xboard.expected.lock().unwrap().is_none() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let expected = xboard.expected.lock().unwrap().unwrap();
+        let other = xboard
+            .game()
+            .board()
+            .legal_moves()
+            .into_iter()
+            .find(|&mv| mv != expected)
+            .unwrap();
+        xboard.handle(&format!("usermove {}", other));
+        while output(&buffer).lines().count() < 3 {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        xboard.handle("force");
+        assert_eq!(xboard.game().moves().count(), 6);
+        assert!(!xboard.thread.is_searching());
+    }
+
+    #[test]
+    fn announces_results() {
+        let (mut xboard, buffer) = engine();
+        xboard.handle("setboard 6k1/5ppp/8/8/8/8/8/3R2K1 w - - 0 1");
+        xboard.handle("sd 2");
+        xboard.handle("go");
+        xboard.thread.wait();
+        assert_eq!(output(&buffer), "move d1d8\n1-0 {White mates}\n");
+    }
+}
@@ -0,0 +1,101 @@
+// zobrist hashing
+// every (piece, color, square) combination, castling state, en passant file
+// and side to move gets a random 64 bit key, the hash of a position is the
+// xor of the keys of everything present on the board
+// keys are generated at compile time so hashes are stable across runs
+
+pub(crate) struct Zobrist {
+    // indexed by color, piece - 1 and square
+    pub pieces: [[[u64; 64]; 6]; 2],
+    pub castling: [u64; 16],
+    pub en_passant: [u64; 8],
+    pub black_to_move: u64,
+}
+
+pub(crate) static ZOBRIST: Zobrist = Zobrist::new();
+
+// xorshift64* generator
+const fn next(state: u64) -> (u64, u64) {
+    let mut x = state;
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    (x, x.wrapping_mul(0x2545_F491_4F6C_DD1D))
+}
+
+impl Zobrist {
+    const fn new() -> Zobrist {
+        let mut state = 0x9E37_79B9_7F4A_7C15;
+        let mut pieces = [[[0; 64]; 6]; 2];
+        let mut castling = [0; 16];
+        let mut en_passant = [0; 8];
+
+        let mut c = 0;
+        while c < 2 {
+            let mut p = 0;
+            while p < 6 {
+                let mut sq = 0;
+                while sq < 64 {
+                    let (s, key) = next(state);
+                    state = s;
+                    pieces[c][p][sq] = key;
+                    sq += 1;
+                }
+                p += 1;
+            }
+            c += 1;
+        }
+        let mut i = 0;
+        while i < 16 {
+            let (s, key) = next(state);
+            state = s;
+            castling[i] = key;
+            i += 1;
+        }
+        let mut i = 0;
+        while i < 8 {
+            let (s, key) = next(state);
+            state = s;
+            en_passant[i] = key;
+            i += 1;
+        }
+        let (_, black_to_move) = next(state);
+
+        Zobrist {
+            pieces,
+            castling,
+            en_passant,
+            black_to_move,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BitPiece, Board, Move, Square};
+
+    #[test]
+    fn hashes_follow_moves() {
+        // castling, en passant and a capturing promotion
+        let mut board =
+            Board::from_fen("r3k2r/1P3ppp/8/3pP3/8/8/5PPP/R3K2R w KQkq d6 0 1".into()).unwrap();
+        for mv in ["e5d6", "e8g8", "b7a8q", "f8a8", "e1c1", "a8a2"] {
+            board.play(Move::try_from(mv).unwrap()).unwrap();
+            let fresh = Board::from_fen(board.fen_notation()).unwrap();
+            assert_eq!(board.hash(), fresh.hash(), "{}", mv);
+            assert_eq!(board.pawn_hash(), fresh.pawn_hash(), "{}", mv);
+        }
+        assert_ne!(board.hash(), board.mirror().hash());
+        assert_eq!(board.hash(), board.mirror().mirror().hash());
+
+        // a pawn taken off through IndexMut
+        let mut board = Board::default();
+        board[Square::try_from(12).unwrap()] = BitPiece::new_blank();
+        let fresh =
+            Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1".into())
+                .unwrap();
+        assert_eq!(board.hash(), fresh.hash());
+        assert_eq!(board.pawn_hash(), fresh.pawn_hash());
+        assert_eq!(board.piece_count(), 31);
+    }
+}